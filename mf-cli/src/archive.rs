@@ -0,0 +1,211 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use zip::ZipArchive;
+
+use mf_core::convert::{convert_in_memory_with_limit, write_output};
+use mf_core::error::{ConversionError, FormatError, InputError, IoError, MeltforgeError};
+use mf_core::format::FormatType;
+use mf_core::job::{ConvertJob, ConvertOutcome, Timings};
+use mf_core::output::{is_directory_target, ConflictPolicy};
+
+// An entry this large is not an image worth unpacking into memory, and may
+// well be a zip bomb.
+const MAX_ENTRY_BYTES: u64 = 512 * 1024 * 1024;
+
+/// One converted (or failed) entry: its path shown as `bundle.zip/dir/a.jpg`,
+/// its uncompressed size and the result.
+pub type EntryResult = (PathBuf, u64, Result<ConvertOutcome, MeltforgeError>);
+
+/// Whether `path` is read as a zip of images instead of as an image.
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// Converts every image entry of the zip `job.input` to `job.format_type`,
+/// keeping the entry names under the output directory: `--output` when it is
+/// one, otherwise a directory named after the archive next to it. Entries
+/// that are not images of an enabled format are skipped. A missing
+/// `--output` directory is only created with `--create-dirs`, the ones
+/// below it mirror the archive and always are.
+///
+/// Fails as a whole only if the archive cannot be read; each entry reports
+/// its own result.
+pub fn convert_archive(
+    job: &ConvertJob,
+    cancel: &AtomicBool,
+) -> Result<Vec<EntryResult>, MeltforgeError> {
+    let out_dir = match &job.output {
+        Some(dir) if is_directory_target(dir) => dir.clone(),
+        Some(_) => {
+            return Err(InputError::InvalidArgument(format!(
+                "--output must be a directory to convert {}",
+                job.input.display()
+            ))
+            .into())
+        }
+        None => job.input.with_extension(""),
+    };
+    if job.output.is_some() && !job.create_dirs && !out_dir.exists() {
+        return Err(IoError::MissingParent(out_dir).into());
+    }
+    let file = File::open(&job.input).map_err(|_| IoError::ReadError(job.input.clone()))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| {
+        ConversionError::ExecutionFailed(format!("open {}: {e}", job.input.display()))
+    })?;
+
+    let mut results = Vec::new();
+    for index in 0..archive.len() {
+        if cancel.load(Ordering::Relaxed) {
+            results.push((job.input.clone(), 0, Err(ConversionError::Cancelled.into())));
+            break;
+        }
+        let started = Instant::now();
+        let mut entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                let err = ConversionError::ExecutionFailed(format!(
+                    "{}: entry {index}: {e}",
+                    job.input.display()
+                ));
+                results.push((job.input.clone(), 0, Err(err.into())));
+                continue;
+            }
+        };
+        // names reaching outside the output directory (`../x.png`) are skipped
+        let Some(name) = entry.enclosed_name().filter(|_| !entry.is_dir()) else {
+            continue;
+        };
+        let Some(from) = name
+            .extension()
+            .and_then(|ext| FormatType::from_extension(&ext.to_string_lossy()).ok())
+            .filter(|f| f.is_enabled())
+        else {
+            continue;
+        };
+        let shown = job.input.join(&name);
+        let size = entry.size();
+
+        let result = if size > MAX_ENTRY_BYTES {
+            Err(ConversionError::ExecutionFailed(format!(
+                "{} is {size} bytes unpacked, more than the limit of {MAX_ENTRY_BYTES}",
+                shown.display()
+            ))
+            .into())
+        } else {
+            let mut data = Vec::with_capacity(size as usize);
+            match entry.read_to_end(&mut data) {
+                Ok(_) => convert_entry(job, &data, from, &out_dir.join(&name), started),
+                Err(_) => Err(IoError::ReadError(shown.clone()).into()),
+            }
+        };
+        results.push((shown, size, result));
+    }
+    Ok(results)
+}
+
+fn convert_entry(
+    job: &ConvertJob,
+    data: &[u8],
+    from: FormatType,
+    target: &Path,
+    started: Instant,
+) -> Result<ConvertOutcome, MeltforgeError> {
+    let to = job.format_type;
+    if from == to && !job.allow_same_format {
+        return Err(FormatError::SameFormat(from).into());
+    }
+    let output = target.with_extension(to.extension());
+    let outcome = |output, skipped| ConvertOutcome {
+        outputs: vec![output],
+        warnings: Vec::new(),
+        timings: Timings {
+            total: started.elapsed(),
+            ..Timings::default()
+        },
+        pixels: 0,
+        decoded_bytes: 0,
+        skipped,
+        retries: Vec::new(),
+    };
+    // checked before converting as well, to not convert for nothing
+    if job.on_conflict == ConflictPolicy::Skip && output.exists() {
+        return Ok(outcome(output, true));
+    }
+
+    let bytes = convert_in_memory_with_limit(data, from, to, &job.options, job.max_pixels)?;
+    // written like files, so --output-suffix-on-conflict and --skip-existing
+    // apply to entries too
+    match write_output(&bytes, &output, job.on_conflict, true)? {
+        Some(written) => Ok(outcome(written, false)),
+        None => Ok(outcome(output, true)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    #[test]
+    fn converts_image_entries_keeping_their_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut png = Vec::new();
+        image::RgbImage::new(4, 3)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let bundle = dir.path().join("bundle.zip");
+        let mut zip = ZipWriter::new(File::create(&bundle).unwrap());
+        let opts = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, data) in [
+            ("a.png", &png[..]),
+            ("sub/b.png", &png[..]),
+            ("notes.txt", b"not an image"),
+            ("broken.png", b"not a png"),
+        ] {
+            zip.start_file(name, opts).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let job = ConvertJob::new(&bundle, FormatType::JPEG);
+        let results = convert_archive(&job, &AtomicBool::new(false)).unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0, bundle.join("a.png"));
+        let out = &results[1].2.as_ref().unwrap().outputs[0];
+        assert_eq!(out, &dir.path().join("bundle").join("sub").join("b.jpg"));
+        assert_eq!(image::open(out).unwrap().width(), 4);
+        assert!(results[2].2.is_err());
+
+        // entries are held to the job's pixel limit
+        let job = ConvertJob {
+            max_pixels: 4,
+            on_conflict: ConflictPolicy::Overwrite,
+            ..ConvertJob::new(&bundle, FormatType::JPEG)
+        };
+        let results = convert_archive(&job, &AtomicBool::new(false)).unwrap();
+        let err = results[0].2.as_ref().unwrap_err().to_string();
+        assert!(err.contains("limit of 4 pixels"), "{err}");
+
+        // a missing --output directory needs --create-dirs
+        let job = ConvertJob {
+            output: Some(dir.path().join("out/")),
+            ..ConvertJob::new(&bundle, FormatType::JPEG)
+        };
+        assert!(convert_archive(&job, &AtomicBool::new(false)).is_err());
+        let job = ConvertJob {
+            create_dirs: true,
+            ..job
+        };
+        let results = convert_archive(&job, &AtomicBool::new(false)).unwrap();
+        assert!(dir.path().join("out/sub/b.jpg").exists());
+        assert!(results[0].2.is_ok());
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+
+use mf_core::error::{FormatError, InputError, IoError, MeltforgeError};
+use mf_core::format::FormatType;
+use mf_core::output::is_directory_target;
+use mf_core::transform::Dimensions;
+
+pub const CONFIG_FILE: &str = "meltforge.toml";
+pub const DEFAULT_TO_ENV: &str = "MELTFORGE_DEFAULT_TO";
+
+/// Settings read from `meltforge.toml` in the working directory.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Target format used when `--to` is omitted.
+    pub default_to: Option<String>,
+    /// Encoder quality per lossy format, e.g. `[quality] jpeg = 90`, used
+    /// when `--quality` is omitted.
+    #[serde(default)]
+    pub quality: HashMap<FormatType, u8>,
+    /// Named option sets for `--preset`, e.g. `[presets.blog] quality = 70`.
+    /// A preset named like a built-in one replaces it.
+    #[serde(default)]
+    pub presets: HashMap<String, Preset>,
+}
+
+/// Options bundled under one name. Explicit flags override them.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    pub to: Option<FormatType>,
+    pub quality: Option<u8>,
+    /// Shrink to fit within `WxH`, as with `--max-size`.
+    #[serde(default, deserialize_with = "parsed")]
+    pub max_size: Option<Dimensions>,
+    /// Edge length of a center-cropped square thumbnail.
+    pub thumbnail: Option<u32>,
+}
+
+pub const BUILTIN_PRESETS: [&str; 3] = ["web", "thumbnail", "archive"];
+
+impl Preset {
+    /// `web`: JPEG at quality 82 within 1920x1920. `thumbnail`: 256px square
+    /// JPEG. `archive`: lossless PNG. Metadata is never written, so every
+    /// preset strips it.
+    pub fn builtin(name: &str) -> Option<Preset> {
+        match name {
+            "web" => Some(Preset {
+                to: Some(FormatType::JPEG),
+                quality: Some(82),
+                max_size: Some(Dimensions {
+                    width: 1920,
+                    height: 1920,
+                }),
+                thumbnail: None,
+            }),
+            "thumbnail" => Some(Preset {
+                to: Some(FormatType::JPEG),
+                quality: Some(80),
+                max_size: None,
+                thumbnail: Some(256),
+            }),
+            "archive" => Some(Preset {
+                to: Some(FormatType::PNG),
+                ..Preset::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// The preset's quality for `format`. It is meant for the preset's lossy
+    /// target, so a lossless one, e.g. png through `--to`, takes none.
+    pub fn quality_for(&self, format: FormatType) -> Option<u8> {
+        self.quality.filter(|_| format.is_lossy())
+    }
+}
+
+// Reads values like `1920x1080` through their `FromStr`.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+impl Config {
+    /// Reads `meltforge.toml` from `dir`; a missing file is an empty config.
+    pub fn load(dir: &Path) -> Result<Self, MeltforgeError> {
+        let path = dir.join(CONFIG_FILE);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(_) => return Err(IoError::ReadError(path).into()),
+        };
+        let config: Config = toml::from_str(&text)
+            .map_err(|e| InputError::InvalidArgument(format!("invalid {}: {e}", path.display())))?;
+        for (format, quality) in &config.quality {
+            if !format.is_lossy() || !(1..=100).contains(quality) {
+                return Err(InputError::InvalidArgument(format!(
+                    "invalid {}: quality for {format} must be 1-100 on a lossy format",
+                    path.display()
+                ))
+                .into());
+            }
+        }
+        Ok(config)
+    }
+
+    /// The preset called `name`, from the config file or built in.
+    pub fn preset(&self, name: &str) -> Result<Preset, InputError> {
+        self.presets
+            .get(name)
+            .cloned()
+            .or_else(|| Preset::builtin(name))
+            .ok_or_else(|| {
+                let mut known: Vec<&str> = BUILTIN_PRESETS.to_vec();
+                known.extend(self.presets.keys().map(String::as_str));
+                known.sort_unstable();
+                known.dedup();
+                InputError::InvalidArgument(format!(
+                    "unknown preset '{name}' (known: {})",
+                    known.join(", ")
+                ))
+            })
+    }
+
+    /// Quality for `format` by precedence: `--quality`, then the config
+    /// file, then the format's built-in default. Lossless JPEG XL
+    /// (`--jxl-lossless`) takes no default, so only a quality the user set
+    /// is reported as ignored.
+    pub fn quality_for(
+        &self,
+        format: FormatType,
+        cli: Option<u8>,
+        jxl_lossless: bool,
+    ) -> Option<u8> {
+        let lossless = jxl_lossless && format == FormatType::JXL;
+        cli.or_else(|| self.quality.get(&format).copied())
+            .or_else(|| format.default_quality().filter(|_| !lossless))
+    }
+}
+
+/// What `--to` names: a format, or `auto` to take it from the extension of
+/// the output. It is one format for the whole batch either way, only a
+/// `batch` manifest gives each input its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Format(FormatType),
+    Auto,
+}
+
+impl FromStr for Target {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("auto") {
+            return Ok(Target::Auto);
+        }
+        s.parse().map(Target::Format)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Format(format) => write!(f, "{}", format.name()),
+            Target::Auto => f.write_str("auto"),
+        }
+    }
+}
+
+/// Target format by precedence: `--to`, then `MELTFORGE_DEFAULT_TO`, then
+/// `default_to` from `meltforge.toml`.
+pub fn resolve_target(
+    cli: Option<String>,
+    env: Option<String>,
+    config: impl FnOnce() -> Result<Config, MeltforgeError>,
+) -> Result<Option<String>, MeltforgeError> {
+    if let Some(to) = cli.or(env.filter(|v| !v.trim().is_empty())) {
+        return Ok(Some(to));
+    }
+    Ok(config()?.default_to)
+}
+
+/// Target format named by the extension of an `--output` file, e.g.
+/// `out.jpg`. Directories and unknown extensions name none.
+pub fn target_from_output(output: &Path) -> Option<String> {
+    if is_directory_target(output) {
+        return None;
+    }
+    let ext = output.extension()?.to_string_lossy();
+    FormatType::from_extension(&ext)
+        .ok()
+        .map(|_| ext.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mf_core::{job::ConvertJob, validate::option_warnings};
+
+    fn config(to: &str) -> Result<Config, MeltforgeError> {
+        Ok(Config {
+            default_to: Some(to.into()),
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn output_extension_names_the_target() {
+        assert_eq!(
+            target_from_output(Path::new("x.png")).as_deref(),
+            Some("png")
+        );
+        assert_eq!(
+            target_from_output(Path::new("out/x.JPG")).as_deref(),
+            Some("JPG")
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let photos = dir.path().join("photos.png");
+        fs::create_dir(&photos).unwrap();
+        assert_eq!(target_from_output(&photos), None);
+        assert_eq!(target_from_output(Path::new("out.webp/")), None);
+
+        assert_eq!(target_from_output(Path::new("x.xyz")), None);
+        assert_eq!(target_from_output(Path::new("result")), None);
+    }
+
+    #[test]
+    fn explicit_flag_beats_env_beats_file() {
+        let resolve = |cli: Option<&str>, env: Option<&str>| {
+            resolve_target(cli.map(Into::into), env.map(Into::into), || config("tiff")).unwrap()
+        };
+        assert_eq!(resolve(Some("png"), Some("webp")).as_deref(), Some("png"));
+        assert_eq!(resolve(None, Some("webp")).as_deref(), Some("webp"));
+        assert_eq!(resolve(None, Some("")).as_deref(), Some("tiff"));
+        assert_eq!(resolve(None, None).as_deref(), Some("tiff"));
+    }
+
+    #[test]
+    fn target_is_a_format_or_auto() {
+        assert_eq!("AUTO".parse::<Target>().unwrap(), Target::Auto);
+        assert_eq!(
+            "webp".parse::<Target>().unwrap(),
+            Target::Format(FormatType::WEBP)
+        );
+        assert!("bogus".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn loads_default_from_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Config::load(dir.path()).unwrap().default_to.is_none());
+
+        fs::write(dir.path().join(CONFIG_FILE), "default_to = \"webp\"\n").unwrap();
+        let loaded = Config::load(dir.path()).unwrap();
+        assert_eq!(loaded.default_to.as_deref(), Some("webp"));
+
+        fs::write(dir.path().join(CONFIG_FILE), "default_too = 1\n").unwrap();
+        assert!(Config::load(dir.path()).is_err());
+    }
+
+    #[test]
+    fn quality_comes_from_flag_then_file_then_format() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(CONFIG_FILE), "[quality]\njpg = 92\n").unwrap();
+        let loaded = Config::load(dir.path()).unwrap();
+        assert_eq!(
+            loaded.quality_for(FormatType::JPEG, Some(50), false),
+            Some(50)
+        );
+        assert_eq!(loaded.quality_for(FormatType::JPEG, None, false), Some(92));
+        assert_eq!(loaded.quality_for(FormatType::AVIF, None, false), Some(80));
+        assert_eq!(loaded.quality_for(FormatType::PNG, None, false), None);
+
+        fs::write(dir.path().join(CONFIG_FILE), "[quality]\npng = 90\n").unwrap();
+        assert!(Config::load(dir.path()).is_err());
+        fs::write(dir.path().join(CONFIG_FILE), "[quality]\njpeg = 0\n").unwrap();
+        assert!(Config::load(dir.path()).is_err());
+    }
+
+    #[test]
+    fn lossless_jpeg_xl_only_warns_about_a_quality_the_user_set() {
+        let config = Config::default();
+        let job = |quality| {
+            let mut job = ConvertJob::new("in.png", FormatType::JXL);
+            job.options.jxl_lossless = true;
+            job.options.quality = quality;
+            job
+        };
+        assert_eq!(config.quality_for(FormatType::JXL, None, false), Some(90));
+
+        let quality = config.quality_for(FormatType::JXL, None, true);
+        assert_eq!(quality, None);
+        assert!(option_warnings(&job(quality)).is_empty());
+
+        let quality = config.quality_for(FormatType::JXL, Some(70), true);
+        assert_eq!(
+            option_warnings(&job(quality)),
+            ["--quality is ignored with --jxl-lossless"]
+        );
+    }
+
+    #[test]
+    fn config_presets_extend_and_replace_the_built_in_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(CONFIG_FILE),
+            "[presets.blog]\nto = \"jpg\"\nmax_size = \"800x600\"\n\n[presets.web]\nquality = 60\n",
+        )
+        .unwrap();
+        let loaded = Config::load(dir.path()).unwrap();
+
+        let blog = loaded.preset("blog").unwrap();
+        assert_eq!(blog.to, Some(FormatType::JPEG));
+        assert_eq!(blog.max_size.unwrap().to_string(), "800x600");
+        assert_eq!(loaded.preset("web").unwrap().quality, Some(60));
+        let web = loaded.preset("web").unwrap();
+        assert_eq!(web.quality_for(FormatType::JPEG), Some(60));
+        assert_eq!(web.quality_for(FormatType::PNG), None);
+        assert_eq!(loaded.preset("archive").unwrap().to, Some(FormatType::PNG));
+
+        let err = loaded.preset("print").unwrap_err().to_string();
+        assert!(err.contains("archive, blog, thumbnail, web"), "{err}");
+    }
+}
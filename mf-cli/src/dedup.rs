@@ -0,0 +1,137 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use mf_core::convert::write_output;
+use mf_core::error::{IoError, MeltforgeError};
+use mf_core::job::{ConvertJob, ConvertOutcome, Timings};
+use mf_core::output::resolve_output_path;
+use mf_core::validate::validate_job;
+
+// Inputs of a batch by content, with the output each one was converted to.
+// The hash only narrows the candidates, a match is confirmed byte by byte.
+#[derive(Default)]
+pub struct Dedup {
+    seen: HashMap<u64, Vec<(PathBuf, PathBuf)>>,
+    pub copied: usize,
+}
+
+impl Dedup {
+    // Output of an earlier input with the same bytes as `input`. Unreadable
+    // inputs have none and are left to the conversion to report.
+    pub fn original_output(&self, input: &Path) -> Option<PathBuf> {
+        let bytes = fs::read(input).ok()?;
+        let candidates = self.seen.get(&content_hash(&bytes))?;
+        candidates
+            .iter()
+            .find(|(earlier, _)| fs::read(earlier).is_ok_and(|b| b == bytes))
+            .map(|(_, output)| output.clone())
+    }
+
+    // Remembers a finished conversion. Skipped inputs, whose existing output
+    // may be stale, and animations written as several frames are not reused.
+    pub fn record(&mut self, input: &Path, outcome: &ConvertOutcome) {
+        let [output] = outcome.outputs.as_slice() else {
+            return;
+        };
+        if outcome.skipped {
+            return;
+        }
+        if let Ok(bytes) = fs::read(input) {
+            self.seen
+                .entry(content_hash(&bytes))
+                .or_default()
+                .push((input.to_path_buf(), output.clone()));
+        }
+    }
+
+    // Copies the output of the identical earlier input instead of converting
+    // `job` again. The job is validated and the copy written like any output,
+    // so conflicts and --create-dirs still apply.
+    pub fn copy(
+        &mut self,
+        job: &ConvertJob,
+        original: &Path,
+    ) -> Result<ConvertOutcome, MeltforgeError> {
+        let started = Instant::now();
+        validate_job(job)?;
+        let bytes = fs::read(original).map_err(|_| IoError::ReadError(original.to_path_buf()))?;
+        let target = resolve_output_path(&job.input, job.output.as_deref(), job.format_type);
+        let written = write_output(&bytes, &target, job.on_conflict, job.create_dirs)?;
+        if written.is_some() {
+            self.copied += 1;
+        }
+        Ok(ConvertOutcome {
+            skipped: written.is_none(),
+            outputs: vec![written.unwrap_or(target)],
+            warnings: Vec::new(),
+            timings: Timings {
+                total: started.elapsed(),
+                ..Timings::default()
+            },
+            pixels: 0,
+            decoded_bytes: 0,
+            retries: Vec::new(),
+        })
+    }
+}
+
+fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mf_core::convert::convert;
+    use mf_core::format::FormatType;
+    use mf_core::output::ConflictPolicy;
+
+    #[test]
+    fn identical_inputs_reuse_the_first_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("a.png");
+        let copy = dir.path().join("b.png");
+        let other = dir.path().join("c.png");
+        image::RgbImage::new(8, 8).save(&first).unwrap();
+        fs::copy(&first, &copy).unwrap();
+        image::RgbImage::new(9, 9).save(&other).unwrap();
+
+        let mut dedup = Dedup::default();
+        assert!(dedup.original_output(&first).is_none());
+        let outcome = convert(ConvertJob::new(&first, FormatType::JPEG)).unwrap();
+        dedup.record(&first, &outcome);
+
+        assert!(dedup.original_output(&other).is_none());
+        let original = dedup.original_output(&copy).unwrap();
+        assert_eq!(original, dir.path().join("a.jpg"));
+
+        let copied = dedup
+            .copy(&ConvertJob::new(&copy, FormatType::JPEG), &original)
+            .unwrap();
+        assert_eq!(copied.outputs, [dir.path().join("b.jpg")]);
+        assert_eq!(
+            fs::read(&copied.outputs[0]).unwrap(),
+            fs::read(&original).unwrap()
+        );
+        assert_eq!(dedup.copied, 1);
+
+        // an existing output is kept under --skip-existing and numbered under --output-suffix-on-conflict
+        fs::write(&copied.outputs[0], b"keep me").unwrap();
+        let job = |on_conflict| ConvertJob {
+            on_conflict,
+            ..ConvertJob::new(&copy, FormatType::JPEG)
+        };
+        let skipped = dedup.copy(&job(ConflictPolicy::Skip), &original).unwrap();
+        assert!(skipped.skipped);
+        assert_eq!(fs::read(&copied.outputs[0]).unwrap(), b"keep me");
+        let renamed = dedup.copy(&job(ConflictPolicy::Rename), &original).unwrap();
+        assert_eq!(renamed.outputs, [dir.path().join("b-1.jpg")]);
+        assert_eq!(fs::read(&copied.outputs[0]).unwrap(), b"keep me");
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use clap::ValueEnum;
+
+use crate::style;
+
+/// Where error diagnostics go, `--error-output`. Warnings and progress stay
+/// on stderr, results on stdout.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ErrorOutput {
+    #[default]
+    Stderr,
+    /// For environments that only capture stdout
+    Stdout,
+    /// Leave errors to the exit code
+    None,
+}
+
+// Set once at startup, like the colors in `style`.
+static OUTPUT: AtomicU8 = AtomicU8::new(ErrorOutput::Stderr as u8);
+
+pub fn init(output: ErrorOutput) {
+    OUTPUT.store(output as u8, Ordering::Relaxed);
+}
+
+fn output() -> ErrorOutput {
+    match OUTPUT.load(Ordering::Relaxed) {
+        1 => ErrorOutput::Stdout,
+        2 => ErrorOutput::None,
+        _ => ErrorOutput::Stderr,
+    }
+}
+
+/// The `Error:` label, colored for the stream it is printed on.
+pub fn label() -> String {
+    match output() {
+        ErrorOutput::Stdout => style::error_stdout("Error:"),
+        _ => style::error("Error:"),
+    }
+}
+
+/// Prints the lines of one error diagnostic.
+pub fn print(lines: &[String]) {
+    match output() {
+        ErrorOutput::Stderr => lines.iter().for_each(|line| eprintln!("{line}")),
+        ErrorOutput::Stdout => lines.iter().for_each(|line| println!("{line}")),
+        ErrorOutput::None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destination_survives_the_round_trip() {
+        for choice in [ErrorOutput::Stdout, ErrorOutput::None, ErrorOutput::Stderr] {
+            init(choice);
+            assert_eq!(output(), choice);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::path::Path;
+#[cfg(feature = "net")]
+use std::time::Duration;
+
+#[cfg(feature = "net")]
+use mf_core::error::IoError;
+
+// Like a zip entry, a download this large is not an image worth holding in
+// memory.
+#[cfg(feature = "net")]
+const MAX_DOWNLOAD_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Whether the input is an `http://` or `https://` URL to download instead of
+/// a path.
+pub fn is_url(input: &Path) -> bool {
+    let input = input.to_string_lossy();
+    ["http://", "https://"].iter().any(|scheme| {
+        input
+            .get(..scheme.len())
+            .is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+    })
+}
+
+/// Last segment of the URL's path, without query or fragment: `pic.png` for
+/// `https://example.com/img/pic.png?v=2`. `None` if the path is empty.
+#[cfg(feature = "net")]
+pub fn file_name(url: &str) -> Option<&str> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let path = rest.split(['?', '#']).next().unwrap_or_default();
+    let (_, path) = path.split_once('/')?;
+    path.rsplit('/').next().filter(|name| !name.is_empty())
+}
+
+/// A downloaded input.
+#[cfg(feature = "net")]
+#[derive(Debug)]
+pub struct Download {
+    pub data: Vec<u8>,
+    /// The `Content-Type` the server sent, e.g. `image/png`.
+    pub content_type: Option<String>,
+}
+
+/// Downloads `url` into memory, giving up after `timeout` if set. A response
+/// other than 2xx is an error.
+#[cfg(feature = "net")]
+pub fn download(url: &str, timeout: Option<Duration>) -> Result<Download, IoError> {
+    let failed = |reason: String| IoError::Network {
+        url: url.to_string(),
+        reason,
+    };
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(timeout)
+        .build()
+        .into();
+    let reason = |e: ureq::Error| match e {
+        ureq::Error::StatusCode(status) => format!("the server answered with status {status}"),
+        ureq::Error::Timeout(_) => match timeout {
+            Some(t) => format!("no complete response within {}s", t.as_secs()),
+            None => "timed out".into(),
+        },
+        ureq::Error::BodyExceedsLimit(_) => format!("larger than {MAX_DOWNLOAD_BYTES} bytes"),
+        ureq::Error::Io(e) => e.to_string(),
+        e => e.to_string(),
+    };
+
+    let mut response = agent.get(url).call().map_err(|e| failed(reason(e)))?;
+    let content_type = response.body().mime_type().map(str::to_string);
+    let data = response
+        .body_mut()
+        .with_config()
+        .limit(MAX_DOWNLOAD_BYTES)
+        .read_to_vec()
+        .map_err(|e| failed(reason(e)))?;
+    Ok(Download { data, content_type })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_http_urls_are_downloaded() {
+        assert!(is_url(Path::new("https://example.com/pic.png")));
+        assert!(is_url(Path::new("HTTP://example.com")));
+        assert!(!is_url(Path::new("http.png")));
+        assert!(!is_url(Path::new("ftp://example.com/pic.png")));
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn url_file_name_leaves_out_query_and_fragment() {
+        assert_eq!(
+            file_name("https://example.com/img/pic.png?v=2#top"),
+            Some("pic.png")
+        );
+        assert_eq!(file_name("https://example.com/"), None);
+        assert_eq!(file_name("https://example.com?img=pic.png"), None);
+    }
+
+    #[cfg(feature = "net")]
+    #[test]
+    fn downloads_and_reports_failures() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            for answer in [
+                "200 OK\r\nConnection: close\r\nContent-Type: image/png\r\nContent-Length: 4\r\n\r\nPNG!",
+                "404 Not Found\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(stream, "HTTP/1.1 {answer}").unwrap();
+            }
+        });
+
+        let fetched = download(&format!("{base}/pic.png"), None).unwrap();
+        assert_eq!(fetched.data, b"PNG!");
+        assert_eq!(fetched.content_type.as_deref(), Some("image/png"));
+
+        let err = download(&format!("{base}/gone.png"), Some(Duration::from_secs(5))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("network error fetching {base}/gone.png: the server answered with status 404")
+        );
+        server.join().unwrap();
+    }
+}
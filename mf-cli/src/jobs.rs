@@ -0,0 +1,132 @@
+use std::io::Read;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use mf_core::convert::convert;
+use mf_core::error::{InputError, MeltforgeError};
+use mf_core::job::ConvertJob;
+
+// stdin may hold a single job or an array of jobs
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JobsInput {
+    One(Box<ConvertJob>),
+    Many(Vec<ConvertJob>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum JobResult {
+    Ok {
+        input: PathBuf,
+        outputs: Vec<PathBuf>,
+        warnings: Vec<String>,
+        skipped: bool,
+    },
+    Error {
+        input: PathBuf,
+        error: String,
+        code: &'static str,
+        exit_code: u8,
+    },
+}
+
+impl JobResult {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            JobResult::Ok { .. } => 0,
+            JobResult::Error { exit_code, .. } => *exit_code,
+        }
+    }
+}
+
+/// Reads serialized `ConvertJob`s and runs each one, failures do not stop the rest.
+/// With `strict`, every job is run as strict whatever its own setting.
+pub fn run_jobs(reader: impl Read, strict: bool) -> Result<Vec<JobResult>, MeltforgeError> {
+    let jobs = match serde_json::from_reader(reader) {
+        Ok(JobsInput::One(job)) => vec![*job],
+        Ok(JobsInput::Many(jobs)) => jobs,
+        Err(e) => {
+            return Err(InputError::InvalidArgument(format!("invalid job JSON: {e}")).into());
+        }
+    };
+
+    Ok(jobs
+        .into_iter()
+        .map(|job| {
+            run_job(ConvertJob {
+                strict: job.strict || strict,
+                ..job
+            })
+        })
+        .collect())
+}
+
+fn run_job(job: ConvertJob) -> JobResult {
+    let input = job.input.clone();
+    match convert(job) {
+        Ok(outcome) => JobResult::Ok {
+            input,
+            outputs: outcome.outputs,
+            warnings: outcome.warnings,
+            skipped: outcome.skipped,
+        },
+        Err(e) => JobResult::Error {
+            input,
+            error: e.to_string(),
+            code: e.code(),
+            exit_code: e.exit_code(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_two_job_array() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+        let missing = dir.path().join("missing.png");
+
+        let json = serde_json::json!([
+            { "input": input, "format_type": "jpeg" },
+            { "input": missing, "to": "jpg" },
+        ]);
+        let results = run_jobs(json.to_string().as_bytes(), false).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert!(matches!(&results[0], JobResult::Ok { outputs, .. } if outputs[0].exists()));
+        assert_eq!(results[1].exit_code(), 2);
+        assert!(matches!(
+            &results[1],
+            JobResult::Error {
+                code: "E_MISSING_INPUT",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn options_sit_next_to_the_job_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+
+        let json = serde_json::json!({ "input": input, "to": "jpeg", "scale": 0.5, "quality": 90 });
+        let results = run_jobs(json.to_string().as_bytes(), false).unwrap();
+
+        let JobResult::Ok { outputs, .. } = &results[0] else {
+            panic!("{results:?}");
+        };
+        assert_eq!(image::open(&outputs[0]).unwrap().width(), 4);
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        assert!(run_jobs("{ not json".as_bytes(), false).is_err());
+    }
+}
@@ -1,84 +1,1521 @@
-use clap::{Parser, Subcommand, ValueHint};
-use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use mf_core::convert::convert;
-use mf_core::error::{IoError, MeltforgeError};
+use mf_core::batch::{aggregate_exit_code, convert_batch, BatchResult};
+use mf_core::color::{BitDepth, Channels, Color};
+use mf_core::convert::{
+    convert, convert_in_memory_with_limit, convert_with_events, convert_with_timeout_and_events,
+    write_output,
+};
+use mf_core::density::PrintSize;
+use mf_core::error::{ConversionError, FormatError, InputError, IoError, MeltforgeError};
 use mf_core::format::FormatType;
-use mf_core::job::ConvertJob;
+use mf_core::ico::IcoSizes;
+use mf_core::info::inspect;
+use mf_core::job::{ConvertJob, ConvertOptions, ConvertOutcome, ConvertStage, Timings};
+use mf_core::jpeg::ChromaSubsampling;
+use mf_core::output::{
+    is_directory_target, job_output_path, number_colliding_outputs, with_target_extension,
+    ConflictPolicy, OutputPattern,
+};
+use mf_core::transform::{
+    Crop, Dimensions, Flip, Rotation, Scale, Thumbnail, ThumbnailFit,
+    DEFAULT_MAX_OUTPUT_DIMENSIONS, DEFAULT_MAX_PIXELS,
+};
+use mf_core::validate::{
+    option_warnings, supported_conversions, validate_image, validate_unique_outputs,
+};
+
+mod archive;
+mod config;
+mod dedup;
+mod diag;
+mod fetch;
+mod jobs;
+mod manifest;
+mod progress;
+mod report;
+mod sidecar;
+mod style;
+mod walk;
+
+use config::{Config, Preset, Target};
+use dedup::Dedup;
+use diag::ErrorOutput;
+use jobs::JobResult;
+use progress::Progress;
+use report::Report;
+use sidecar::{sidecar_path, write_sidecar};
+use style::ColorChoice;
+use walk::{walk_inputs, Skipped};
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success
+  2  usage or input error (bad flags, missing file, invalid argument)
+  3  format error (unknown, unsupported or disabled format)
+  4  conversion error (decode/encode failed, or a warning under --strict)
+  5  I/O error (output exists, missing directory, permissions)
+  6  partial failure: some inputs of a batch converted, others failed
+  7  --to matches the input format and --allow-same-format was not given
+  130  interrupted with Ctrl-C; outputs finished before are kept
+When every input of a batch fails, the code of the first failure is used.";
+
+const INTERRUPTED_EXIT_CODE: u8 = 130;
 
 #[derive(Parser, Debug)]
-#[command(name = "meltforge", version, about = "Universal converter")]
+#[command(name = "meltforge", version, about = "Universal converter", after_help = EXIT_CODES)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Suppress progress output
+    #[arg(long = "quiet", short = 'q', global = true)]
+    quiet: bool,
+
+    /// Print a timing breakdown (decode, transforms, encode) per file
+    #[arg(long = "verbose", short = 'v', global = true)]
+    verbose: bool,
+
+    /// Treat every warning as an error, e.g. for CI (exit code 4)
+    #[arg(long = "strict", global = true)]
+    strict: bool,
+
+    /// Color error, warning and success labels; JSON output is never colored
+    #[arg(
+        long = "color",
+        value_enum,
+        value_name = "WHEN",
+        default_value_t,
+        global = true
+    )]
+    color: ColorChoice,
+
+    /// Same as --color never
+    #[arg(long = "no-color", global = true)]
+    no_color: bool,
+
+    /// Where error messages go, whatever the --format of the results
+    #[arg(
+        long = "error-output",
+        value_enum,
+        value_name = "WHERE",
+        default_value_t,
+        global = true
+    )]
+    error_output: ErrorOutput,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Convert {
+    Convert(Box<ConvertArgs>),
+
+    /// Run JSON-serialized convert jobs (one object or an array) read from stdin
+    RunJobs,
+
+    /// Convert the files listed in a manifest, one `input -> format` or `input -> output` per line
+    Batch {
+        /// Manifest file; `#` starts a comment, paths are relative to its directory
+        #[arg(long = "manifest", value_hint = ValueHint::FilePath)]
+        manifest: PathBuf,
+    },
+
+    /// Make a square thumbnail: center-crop (or letterbox with --fit), then resize
+    Thumbnail(ThumbnailArgs),
+
+    /// Show the format, dimensions and color type of an image without converting it
+    Info {
         #[arg(value_hint = ValueHint::FilePath)]
-        input: PathBuf,
+        path: PathBuf,
 
-        #[arg(long = "to", value_name = "FORMAT", required = true)]
-        to: String,
+        /// Input format, for files without a (recognized) extension
+        #[arg(long = "from", value_name = "FORMAT")]
+        from: Option<FormatType>,
 
-        #[arg(long = "output", short = 'o', value_hint = ValueHint::FilePath)]
-        output: Option<PathBuf>,
+        #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
     },
+
+    /// Check that images decode in full without converting them, e.g. uploads before storing them
+    Validate {
+        #[arg(value_hint = ValueHint::FilePath, required = true, num_args = 1..)]
+        paths: Vec<PathBuf>,
+
+        /// Input format, for files without a (recognized) extension
+        #[arg(long = "from", value_name = "FORMAT")]
+        from: Option<FormatType>,
+    },
+
+    /// List the formats and conversions supported by this build
+    ListFormats {
+        #[arg(long = "format", value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+}
+
+#[derive(Args, Debug)]
+struct ThumbnailArgs {
+    #[arg(value_hint = ValueHint::FilePath)]
+    input: PathBuf,
+
+    /// Edge length of the square thumbnail in pixels
+    #[arg(long = "size", value_name = "PX", default_value_t = 256, value_parser = clap::value_parser!(u32).range(1..))]
+    size: u32,
+
+    /// Target format [default: the --output extension, else the input format]
+    #[arg(long = "to", value_name = "FORMAT")]
+    to: Option<FormatType>,
+
+    /// Output file [default: <name>-thumb.<ext> next to the input]
+    #[arg(long = "output", short = 'o', value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+
+    /// Keep the whole image and pad it to a square instead of cropping
+    #[arg(long = "fit")]
+    fit: bool,
+
+    /// Padding color for --fit on inputs without alpha, and background for JPEG
+    #[arg(long = "background", value_name = "RRGGBB", default_value_t = Color::WHITE)]
+    background: Color,
+
+    /// Encoder quality for lossy formats (1-100)
+    #[arg(long = "quality", value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Args, Debug)]
+struct ConvertArgs {
+    /// Input files, or `-` to read one image from stdin and write it to stdout, or an
+    /// http(s) URL to download (with the `net` feature)
+    #[arg(value_hint = ValueHint::FilePath, required = true, num_args = 1..)]
+    inputs: Vec<PathBuf>,
+
+    /// Convert the image files in directory inputs and all their subdirectories
+    #[arg(long = "recursive", short = 'r')]
+    recursive: bool,
+
+    /// Follow symlinks while walking directories (each real path is still visited once)
+    #[arg(long = "follow-symlinks", requires = "recursive")]
+    follow_symlinks: bool,
+
+    /// Target format, or `auto` to take it from the extension of --output or --output-pattern.
+    /// If omitted, it is taken from the --output extension,
+    /// then the preset, then $MELTFORGE_DEFAULT_TO, then `default_to` from ./meltforge.toml
+    #[arg(long = "to", value_name = "FORMAT")]
+    to: Option<String>,
+
+    /// Named option set: web, thumbnail, archive or one from ./meltforge.toml; flags override it
+    #[arg(long = "preset", value_name = "NAME")]
+    preset: Option<String>,
+
+    /// Input format, for files without a (recognized) extension
+    #[arg(long = "from", value_name = "FORMAT")]
+    from: Option<String>,
+
+    /// Input format as a MIME type, e.g. image/png (alternative to --from).
+    /// For `-` (stdin) it is checked against the data, which wins if they disagree
+    #[arg(
+        long = "input-mime",
+        alias = "content-type",
+        value_name = "MIME",
+        conflicts_with = "from"
+    )]
+    input_mime: Option<String>,
+
+    /// Allow --to to match the input format, to recompress or strip metadata
+    #[arg(long = "allow-same-format")]
+    allow_same_format: bool,
+
+    /// Replace each input with its re-encoded result (--to must match the input format)
+    #[arg(long = "in-place", conflicts_with = "output")]
+    in_place: bool,
+
+    /// Output file, or a directory (existing or ending in '/') to place converted files in.
+    /// A file name without an extension gets the target's, an error with --strict
+    #[arg(long = "output", short = 'o', value_hint = ValueHint::AnyPath)]
+    output: Option<PathBuf>,
+
+    /// Name outputs from a template: {stem}, {ext}, {parent}, {index} (placed inside --output if given)
+    #[arg(long = "output-pattern", value_name = "PATTERN")]
+    output_pattern: Option<OutputPattern>,
+
+    /// Number outputs that several inputs would write (name-1.png, ...) instead of failing
+    #[arg(long = "unique")]
+    unique: bool,
+
+    /// Embed the DPI needed to print at this size, e.g. 4x6in, 10x15cm
+    #[arg(long = "print-size", value_name = "WxH[in|cm|mm]")]
+    print_size: Option<PrintSize>,
+
+    /// Resolution to declare in PNG and JPEG outputs, in dots per inch
+    #[arg(
+        long = "dpi",
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        conflicts_with = "print_size"
+    )]
+    dpi: Option<u16>,
+
+    /// Create missing parent directories of the output path
+    #[arg(long = "create-dirs")]
+    create_dirs: bool,
+
+    /// Check each output directory for writability once instead of once per file
+    #[arg(long = "no-clobber-check")]
+    no_clobber_check: bool,
+
+    /// Resize by a percentage, e.g. 50%
+    #[arg(long = "scale", value_name = "PERCENT")]
+    scale: Option<Scale>,
+
+    /// Shrink to fit within WxH, keeping the aspect ratio; smaller images are kept as is
+    #[arg(long = "max-size", value_name = "WxH")]
+    max_size: Option<Dimensions>,
+
+    /// Apply the EXIF orientation of JPEG inputs (the default)
+    #[arg(long = "auto-orient", overrides_with = "no_auto_orient")]
+    auto_orient: bool,
+
+    /// Keep JPEG pixels as stored, ignoring the EXIF orientation
+    #[arg(long = "no-auto-orient", overrides_with = "auto_orient")]
+    no_auto_orient: bool,
+
+    /// Keep only the W by H region at X,Y of the upright input, before any resizing
+    #[arg(long = "crop", value_name = "X,Y,W,H")]
+    crop: Option<Crop>,
+
+    /// Rotate clockwise by 90, 180 or 270 degrees (applied before --flip)
+    #[arg(long = "rotate", value_name = "DEGREES")]
+    rotate: Option<Rotation>,
+
+    /// Mirror horizontally or vertically
+    #[arg(long = "flip", value_name = "horizontal|vertical")]
+    flip: Option<Flip>,
+
+    /// Crop to the non-transparent (or non-border-color) content before scaling
+    #[arg(long = "autocrop-to-content")]
+    autocrop_to_content: bool,
+
+    /// Always write 8-bit RGBA (RGB for JPEG) instead of keeping e.g. grayscale
+    #[arg(long = "force-rgba")]
+    force_rgba: bool,
+
+    /// Channel layout of the output; dropped alpha is composited over --background
+    #[arg(
+        long = "channels",
+        value_name = "rgb|rgba|gray|graya",
+        conflicts_with = "force_rgba"
+    )]
+    channels: Option<Channels>,
+
+    /// Background for transparent areas when the target has no alpha (JPEG)
+    #[arg(long = "background", value_name = "RRGGBB", default_value_t = Color::WHITE)]
+    background: Color,
+
+    /// Reject transforms producing an output larger than this
+    #[arg(long = "max-output-dimensions", value_name = "WxH", default_value_t = DEFAULT_MAX_OUTPUT_DIMENSIONS)]
+    max_output_dimensions: Dimensions,
+
+    /// Refuse inputs with more pixels than this instead of decoding them
+    #[arg(long = "max-pixels", value_name = "N", default_value_t = DEFAULT_MAX_PIXELS)]
+    max_pixels: u64,
+
+    /// Decode inputs over --max-pixels at 1/2, 1/4 or 1/8 size instead of failing (JPEG only)
+    #[arg(long = "retry-on-oom")]
+    retry_on_oom: bool,
+
+    /// Threads used to decode each JPEG input (other formats decode single-threaded)
+    #[arg(long = "decode-threads", value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    decode_threads: Option<u16>,
+
+    /// Re-decode the written output and check its dimensions (doubles decode cost)
+    #[arg(long = "verify")]
+    verify: bool,
+
+    /// Write the same bytes for the same input on every run, e.g. for builds cached on content
+    /// hashes (pins encoder threads, leaves times out of --sidecar files)
+    #[arg(long = "reproducible")]
+    reproducible: bool,
+
+    /// Give up on an input after SECONDS; its decoding may still finish in the background
+    #[arg(long = "timeout", value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    timeout: Option<u64>,
+
+    /// Retry failed output writes up to N times with backoff, e.g. on flaky network mounts
+    #[arg(long = "retries", value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Encoder quality for lossy formats (1-100)
+    #[arg(long = "quality", value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+
+    /// Bits per channel of PNG and TIFF outputs, 1 for black-and-white PNG [default: keep the input's]
+    #[arg(long = "bit-depth", alias = "output-bit-depth", value_name = "1|8|16")]
+    bit_depth: Option<BitDepth>,
+
+    /// Gray level (0-255) from which a pixel is white with --bit-depth 1 [default: 128]
+    #[arg(long = "threshold", value_name = "N")]
+    threshold: Option<u8>,
+
+    /// Encode WebP losslessly for archiving, pixel-identical to the source (ignores --quality)
+    #[arg(long = "webp-lossless")]
+    webp_lossless: bool,
+
+    /// Encode JPEG XL losslessly, pixel-identical to the source (ignores --quality)
+    #[arg(long = "jxl-lossless")]
+    jxl_lossless: bool,
+
+    /// JPEG chroma subsampling: 444 keeps full color detail, 420 gives the smallest files [default: 444]
+    #[arg(long = "subsampling", value_name = "444|422|420")]
+    subsampling: Option<ChromaSubsampling>,
+
+    /// Write progressive JPEGs, which browsers show coarse first and refine as they load
+    #[arg(long = "progressive")]
+    progressive: bool,
+
+    /// Text written into JPEG outputs as a comment, e.g. a pipeline identifier
+    #[arg(long = "jpeg-comment", value_name = "TEXT")]
+    jpeg_comment: Option<String>,
+
+    /// Icon sizes packed into ICO outputs
+    #[arg(long = "ico-sizes", value_name = "N,N,...", default_value_t = IcoSizes::default())]
+    ico_sizes: IcoSizes,
+
+    /// Write to name-1.ext, name-2.ext, ... instead of failing when the output exists
+    #[arg(long = "output-suffix-on-conflict")]
+    output_suffix_on_conflict: bool,
+
+    /// Leave inputs whose output already exists alone instead of failing
+    #[arg(long = "skip-existing", conflicts_with_all = ["output_suffix_on_conflict", "in_place"])]
+    skip_existing: bool,
+
+    /// Ask whether to overwrite, skip or rename when an output exists (needs a terminal)
+    #[arg(
+        long = "interactive",
+        short = 'i',
+        conflicts_with_all = ["output_suffix_on_conflict", "skip_existing", "in_place"]
+    )]
+    interactive: bool,
+
+    /// Convert byte-identical inputs once and copy that output for the others
+    #[arg(long = "dedup")]
+    dedup: bool,
+
+    /// Stop a batch at the first failed input and exit with its error code
+    #[arg(long = "fail-fast", overrides_with = "keep_going")]
+    fail_fast: bool,
+
+    /// Convert the remaining inputs after a failure, reported at the end (default)
+    #[arg(long = "keep-going", overrides_with = "fail_fast")]
+    keep_going: bool,
+
+    /// All or nothing: stop at the first failure (or Ctrl-C) and delete every
+    /// output this run created, successful ones included
+    #[arg(long = "transactional", conflicts_with_all = ["keep_going", "in_place"])]
+    transactional: bool,
+
+    /// Write every frame of an animated input as a numbered file
+    #[arg(long = "all-frames")]
+    all_frames: bool,
+
+    /// Print wall time, average time per file and megapixels/s when done
+    #[arg(long = "stats")]
+    stats: bool,
+
+    /// Print how long decoding, each transform, encoding and writing took for
+    /// every file, and the share of each in the whole batch when done
+    #[arg(long = "profile")]
+    profile: bool,
+
+    /// Write a JSON record of every input, output, status, size and timing to this file
+    #[arg(long = "report", value_name = "FILE", value_hint = ValueHint::FilePath)]
+    report: Option<PathBuf>,
+
+    /// Write <output>.json next to each output with its dimensions, source and options
+    #[arg(long = "sidecar")]
+    sidecar: bool,
+
+    /// Warn when the input's alpha channel looks premultiplied
+    #[arg(long = "pre-multiply-check")]
+    pre_multiply_check: bool,
+
+    /// Give outputs the modification and access times of their input
+    #[arg(long = "preserve-timestamps")]
+    preserve_timestamps: bool,
+
+    /// Give outputs the file mode of their input (Unix only)
+    #[arg(long = "preserve-permissions")]
+    preserve_permissions: bool,
 }
 
 fn main() {
     let cli = Cli::parse();
 
+    diag::init(cli.error_output);
+    style::init(if cli.no_color {
+        ColorChoice::Never
+    } else {
+        cli.color
+    });
+    #[cfg(feature = "plugins")]
+    load_plugins(cli.verbose);
     let exit_code = match cli.command {
-        Commands::Convert { input, to, output } => {
-            println!("input : {}", input.display());
-            println!("to    : {}", to);
-            if let Some(p) = &output {
-                println!("output: {}", p.display());
-            }
-
-            let format_type = match to.to_lowercase().as_str() {
-                "jpg" | "jpeg" => FormatType::JPEG,
-                "png" => FormatType::PNG,
-                _ => {
-                    eprintln!("Unsupported format: {}", to);
-                    return;
-                }
+        Commands::Convert(args) => run_convert(*args, cli.quiet, cli.verbose, cli.strict),
+        Commands::RunJobs => run_stdin_jobs(cli.strict),
+        Commands::Thumbnail(args) => run_thumbnail(args, cli.quiet, cli.strict),
+        Commands::Batch { manifest } => run_manifest(&manifest, cli.quiet, cli.strict),
+        Commands::Info { path, from, format } => show_info(&path, from, format),
+        Commands::Validate { paths, from } => run_validate(&paths, from, cli.quiet),
+        Commands::ListFormats { format } => list_formats(format),
+    };
+
+    std::process::exit(exit_code.into());
+}
+
+// A plugin that fails to load only costs its own formats, the run goes on
+// without them.
+#[cfg(feature = "plugins")]
+fn load_plugins(verbose: bool) {
+    for (path, result) in mf_core::plugin::load_from_env() {
+        match result {
+            Ok(formats) if verbose => {
+                let names: Vec<&str> = formats.iter().map(|f| f.name()).collect();
+                eprintln!("Plugin {}: {}", path.display(), names.join(", "));
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("{} {e}", style::warning("Warning:")),
+        }
+    }
+}
+
+fn run_convert(args: ConvertArgs, quiet: bool, verbose: bool, strict: bool) -> u8 {
+    let config = match Config::load(Path::new(".")) {
+        Ok(config) => config,
+        Err(e) => exit_with(e),
+    };
+    let preset = match &args.preset {
+        Some(name) => match config.preset(name) {
+            Ok(preset) => preset,
+            Err(e) => exit_with(e),
+        },
+        None => Preset::default(),
+    };
+    let to = config::resolve_target(
+        args.to
+            .clone()
+            .or_else(|| args.output.as_deref().and_then(config::target_from_output))
+            .or_else(|| preset.to.map(|f| f.name().to_string())),
+        std::env::var(config::DEFAULT_TO_ENV).ok(),
+        || Ok(config.clone()),
+    );
+    let to = match to {
+        Ok(Some(to)) => to,
+        Ok(None) => exit_with(InputError::MissingTargetFormat),
+        Err(e) => exit_with(e),
+    };
+    let target = match to.parse::<Target>() {
+        Ok(target) => target,
+        Err(e) => exit_with(e),
+    };
+    let quality_for = |format| {
+        let chosen = args.quality.or(preset.quality_for(format));
+        config.quality_for(format, chosen, args.jxl_lossless)
+    };
+    // with `auto` the format is only known once the output is resolved, the
+    // jobs then get its quality
+    let quality = match target {
+        Target::Format(format) => quality_for(format),
+        Target::Auto => args.quality,
+    };
+    if verbose {
+        if let Some(q) = quality {
+            eprintln!("Quality: {q} for {target}");
+        }
+    }
+    let input_format = match (&args.from, &args.input_mime) {
+        (Some(from), _) => from.parse().map(Some),
+        (None, Some(mime)) => FormatType::from_mime(mime).map(Some),
+        (None, None) => Ok(None),
+    };
+    let input_format = match input_format {
+        Ok(f) => f,
+        Err(e) => exit_with(e),
+    };
+
+    let options = ConvertOptions {
+        quality,
+        ico_sizes: args.ico_sizes.clone(),
+        print_size: args.print_size,
+        dpi: args.dpi,
+        subsampling: args.subsampling,
+        progressive: args.progressive,
+        jpeg_comment: args.jpeg_comment.clone(),
+        scale: args.scale,
+        max_size: args.max_size.or(preset.max_size),
+        auto_orient: !args.no_auto_orient,
+        crop: args.crop,
+        rotate: args.rotate,
+        flip: args.flip,
+        autocrop: args.autocrop_to_content,
+        background: args.background,
+        max_output_dimensions: args.max_output_dimensions,
+        force_rgba: args.force_rgba,
+        channels: args.channels,
+        webp_lossless: args.webp_lossless,
+        jxl_lossless: args.jxl_lossless,
+        thumbnail: preset.thumbnail.map(|size| Thumbnail {
+            size,
+            fit: ThumbnailFit::Crop,
+        }),
+        bit_depth: args.bit_depth,
+        threshold: args.threshold,
+        reproducible: args.reproducible,
+    };
+    let on_conflict = if args.output_suffix_on_conflict {
+        ConflictPolicy::Rename
+    } else if args.skip_existing {
+        ConflictPolicy::Skip
+    } else {
+        ConflictPolicy::Fail
+    };
+    if args.inputs == [Path::new("-")] {
+        let output = args.output.as_deref().filter(|o| *o != Path::new("-"));
+        let format_type = match resolve_format(target, output, None) {
+            Ok(format) => format,
+            Err(e) => exit_with(e),
+        };
+        return convert_stdin(ConvertJob {
+            output: output_file(output, format_type, strict),
+            options: ConvertOptions {
+                quality: quality_for(format_type),
+                ..options
+            },
+            create_dirs: args.create_dirs,
+            on_conflict,
+            input_format,
+            allow_same_format: args.allow_same_format,
+            strict,
+            max_pixels: args.max_pixels,
+            ..ConvertJob::new("-", format_type)
+        });
+    }
+
+    if let Some(url) = args.inputs.iter().find(|input| fetch::is_url(input)) {
+        let url = url.to_string_lossy();
+        if args.inputs.len() > 1 || args.recursive {
+            exit_with(InputError::InvalidArgument(format!(
+                "{url} must be the only input, without --recursive"
+            )));
+        }
+        #[cfg(not(feature = "net"))]
+        exit_with(InputError::InvalidArgument(format!(
+            "cannot fetch {url}, this build has no `net` feature"
+        )));
+        #[cfg(feature = "net")]
+        {
+            let output = args.output.as_deref().filter(|o| *o != Path::new("-"));
+            let format_type = match resolve_format(target, output, None) {
+                Ok(format) => format,
+                Err(e) => exit_with(e),
             };
+            let output = match output {
+                Some(output) => output_file(Some(output), format_type, strict),
+                None => match fetch::file_name(&url) {
+                    Some(name) => Some(mf_core::output::derive_output_path(
+                        Path::new(name),
+                        format_type,
+                    )),
+                    None => exit_with(InputError::InvalidArgument(format!(
+                        "{url} does not name a file, pass --output"
+                    ))),
+                },
+            };
+            return convert_url(
+                &url,
+                ConvertJob {
+                    output,
+                    options: ConvertOptions {
+                        quality: quality_for(format_type),
+                        ..options
+                    },
+                    create_dirs: args.create_dirs,
+                    on_conflict,
+                    input_format,
+                    allow_same_format: args.allow_same_format,
+                    strict,
+                    max_pixels: args.max_pixels,
+                    ..ConvertJob::new(url.as_ref(), format_type)
+                },
+                args.timeout.map(Duration::from_secs),
+            );
+        }
+    }
+
+    let inputs = if args.recursive {
+        match walk_inputs(args.inputs.clone(), args.follow_symlinks) {
+            Ok(walk) => {
+                if verbose {
+                    for skipped in &walk.skipped {
+                        match skipped {
+                            Skipped::Symlink(p) => eprintln!("Skipped symlink: {}", p.display()),
+                            Skipped::AlreadyVisited(p) => {
+                                eprintln!("Skipped, already visited: {}", p.display())
+                            }
+                        }
+                    }
+                }
+                walk.files
+            }
+            Err(e) => exit_with(e),
+        }
+    } else {
+        args.inputs.clone()
+    };
 
-            let job = ConvertJob {
-                input,
+    if (inputs.len() > 1 || args.recursive || args.output_pattern.is_some())
+        && args
+            .output
+            .as_deref()
+            .is_some_and(|o| !is_directory_target(o))
+    {
+        exit_with(InputError::InvalidArgument(
+            "--output must be a directory when converting multiple inputs, with --recursive or --output-pattern"
+                .into(),
+        ));
+    }
+
+    let format_type =
+        match resolve_format(target, args.output.as_deref(), args.output_pattern.as_ref()) {
+            Ok(format) => format,
+            Err(e) => exit_with(e),
+        };
+    let mut jobs: Vec<ConvertJob> = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            let output = match &args.output_pattern {
+                Some(pattern) => {
+                    let path = pattern.expand(input, format_type, index + 1);
+                    Some(match &args.output {
+                        Some(dir) => dir.join(path),
+                        None => path,
+                    })
+                }
+                None => args.output.clone(),
+            };
+            ConvertJob {
                 output,
-                format_type,
+                options: ConvertOptions {
+                    quality: quality_for(format_type),
+                    ..options.clone()
+                },
+                create_dirs: args.create_dirs,
+                reuse_dir_probe: args.no_clobber_check,
+                max_pixels: args.max_pixels,
+                retry_on_oom: args.retry_on_oom,
+                decode_threads: args.decode_threads.map(usize::from),
+                verify: args.verify,
+                write_retries: args.retries,
+                on_conflict,
+                input_format,
+                allow_same_format: args.allow_same_format,
+                in_place: args.in_place,
+                all_frames: args.all_frames,
+                premultiply_check: args.pre_multiply_check,
+                preserve_timestamps: args.preserve_timestamps,
+                preserve_permissions: args.preserve_permissions,
+                strict,
+                ..ConvertJob::new(input, format_type)
+            }
+        })
+        .collect();
+    // two inputs landing on one output would lose one of them, so that is
+    // settled before anything is written
+    if args.unique {
+        number_colliding_outputs(&mut jobs);
+    } else if let Err(e) = validate_unique_outputs(&jobs) {
+        exit_with(e);
+    }
+
+    let sizes: Vec<u64> = inputs
+        .iter()
+        .map(|p| fs::metadata(p).map(|m| m.len()).unwrap_or(0))
+        .collect();
+    let total = inputs.len();
+    let mut progress = Progress::new(sizes.iter().sum(), total, quiet);
+    let mut batch = BatchResult::default();
+    // Ctrl-C lets the current file finish (or stop before encoding) instead of
+    // killing the process mid-write. Without a handler it still exits as usual.
+    let cancel = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&cancel);
+    let _ = ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed));
+    // a warning repeated for every input of a batch is only printed once
+    let mut warned = HashSet::new();
+    let started = Instant::now();
+    // the later of --fail-fast and --keep-going wins
+    let fail_fast = (args.fail_fast || args.transactional) && !args.keep_going;
+    // outputs written by this run, removed again if --transactional fails
+    let mut created = Vec::new();
+    let mut stopped_by = None;
+    let mut dedup = args.dedup.then(Dedup::default);
+    let mut report = args.report.is_some().then(|| Report::new(format_type));
+
+    // each file is converted in sequence, failures are logged and skipped
+    // unless --fail-fast stops at the first one
+    'inputs: for (job, size) in jobs.into_iter().zip(sizes) {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        let input = job.input.clone();
+        let sidecar_job = args.sidecar.then(|| job.clone());
+        if !quiet {
+            progress.println(format!("input : {}", input.display()));
+            progress.println(format!("to    : {}", job.format_type));
+            if let Some(p) = &job.output {
+                progress.println(format!("output: {}", p.display()));
+            }
+        }
+
+        // a file replaced in place of an existing one cannot be rolled back,
+        // so only new files are recorded
+        let mut replaces = job.on_conflict == ConflictPolicy::Overwrite;
+        let preexisting: HashSet<PathBuf> = if args.transactional {
+            let output = job_output_path(&job);
+            [sidecar_path(&output), output]
+                .into_iter()
+                .filter(|path| path.exists())
+                .collect()
+        } else {
+            HashSet::new()
+        };
+
+        // a zip stands for all the images in it, each with its own result
+        let from_archive = archive::is_archive(&input);
+        let results = if from_archive {
+            match archive::convert_archive(&job, &cancel) {
+                Ok(results) => results,
+                Err(e) => vec![(input.clone(), size, Err(e))],
+            }
+        } else {
+            // without a terminal to ask on, an existing output fails as usual
+            let existing = (args.interactive && io::stdin().is_terminal())
+                .then(|| job_output_path(&job))
+                .filter(|path| path.exists());
+            let job = match existing {
+                Some(path) => ConvertJob {
+                    on_conflict: progress.suspend(|| ask_on_conflict(&path)),
+                    ..job
+                },
+                None => job,
+            };
+            replaces |= job.on_conflict == ConflictPolicy::Overwrite;
+            let on_event = |stage: ConvertStage| progress.stage(&stage);
+            let duplicate_of = dedup.as_ref().and_then(|d| d.original_output(&input));
+            let result = match (&mut dedup, duplicate_of) {
+                (Some(dedup), Some(original)) => dedup.copy(&job, &original),
+                _ => match args.timeout {
+                    Some(secs) => convert_with_timeout_and_events(
+                        job,
+                        Duration::from_secs(secs),
+                        &cancel,
+                        &on_event,
+                    ),
+                    None => convert_with_events(job, &cancel, &on_event),
+                },
             };
-            match convert(job) {
-                Ok(out_path) => {
-                    println!("Conversion was successful");
-                    println!("{}", out_path.display());
-                    0
+            if let (Some(dedup), Ok(outcome)) = (&mut dedup, &result) {
+                dedup.record(&input, outcome);
+            }
+            vec![(input, size, result)]
+        };
+        for (input, entry_size, result) in results {
+            if from_archive && !quiet {
+                progress.println(format!("entry : {}", input.display()));
+            }
+            match &result {
+                Err(MeltforgeError::Conversion(ConversionError::Cancelled)) => break 'inputs,
+                Ok(outcome) if outcome.skipped => {
+                    if !quiet {
+                        progress.println(format!(
+                            "Skipped, output exists: {}",
+                            outcome.outputs[0].display()
+                        ));
+                    }
                 }
-                Err(e) => {
-                    eprintln!("Error: {e}");
-                    if let MeltforgeError::Io(ioe) = &e {
-                        match ioe {
-                            IoError::AlreadyExists(p) => {
-                                eprintln!("File already exists: {}", p.display())
-                            }
-                            IoError::MissingParent(p) => {
-                                eprintln!("Target directory not found: {}", p.display())
-                            }
-                            IoError::PermissionDenied(p) => {
-                                eprintln!("No permission for: {}", p.display())
-                            }
-                            _ => {}
+                Ok(outcome) => {
+                    for warning in &outcome.warnings {
+                        if warned.insert(warning.clone()) {
+                            progress.eprintln(format!("{} {warning}", style::warning("Warning:")));
                         }
                     }
-                    e.exit_code()
+                    if !quiet {
+                        progress.println(style::success("Conversion was successful"));
+                        if outcome.outputs.len() > 1 {
+                            progress.println(format!("Wrote {} frames", outcome.outputs.len()));
+                        }
+                        for out_path in &outcome.outputs {
+                            progress.println(format!("{}", out_path.display()));
+                        }
+                    }
+                    if verbose {
+                        for retry in &outcome.retries {
+                            progress.eprintln(format!("Retrying write: {retry}"));
+                        }
+                    }
+                    if verbose || args.profile {
+                        let line = format_timings(&outcome.timings);
+                        // without the "input :" lines, say which file it was
+                        progress.eprintln(if quiet {
+                            format!("{}: {line}", input.display())
+                        } else {
+                            line
+                        });
+                    }
+                }
+                Err(e) => report_error(&progress, e),
+            }
+            if let Some(report) = &mut report {
+                report.push(&input, entry_size, &result);
+            }
+            let written = match &result {
+                Ok(outcome) if !outcome.skipped => &outcome.outputs[..],
+                _ => &[],
+            };
+            let mut sidecars = Vec::new();
+            if let Some(job) = &sidecar_job {
+                // a sidecar is replaced along with its output
+                let job = ConvertJob {
+                    on_conflict: if replaces {
+                        ConflictPolicy::Overwrite
+                    } else {
+                        job.on_conflict
+                    },
+                    ..job.clone()
+                };
+                for output in written {
+                    match write_sidecar(output, &input, entry_size, &job) {
+                        Ok(path) => sidecars.extend(path),
+                        Err(e) => progress.eprintln(format!("{} {e}", style::warning("Warning:"))),
+                    }
                 }
             }
+            if args.transactional && !replaces {
+                created.extend(
+                    written
+                        .iter()
+                        .chain(&sidecars)
+                        .filter(|path| !preexisting.contains(*path))
+                        .cloned(),
+                );
+            }
+            let failure = result.as_ref().err().map(MeltforgeError::exit_code);
+            batch.push(input, result);
+            if fail_fast && failure.is_some() {
+                stopped_by = failure;
+                break;
+            }
+        }
+        progress.file_done(size);
+        if stopped_by.is_some() {
+            break;
+        }
+    }
+    progress.finish();
+    if args.transactional && (stopped_by.is_some() || cancel.load(Ordering::Relaxed)) {
+        roll_back(&created);
+    }
+
+    if !quiet && batch.entries.len() > 1 {
+        println!(
+            "Done: {} converted, {} skipped, {} failed",
+            batch.succeeded() - batch.skipped(),
+            batch.skipped(),
+            batch.failed()
+        );
+    }
+    if let Some(dedup) = dedup.filter(|d| !quiet && d.copied > 0) {
+        println!(
+            "Duplicates: {} copied from an identical earlier input",
+            dedup.copied
+        );
+    }
+    if args.stats {
+        print_stats(&batch, started.elapsed(), quality);
+    }
+    if args.profile {
+        print_profile(&batch);
+    }
+    let code = if cancel.load(Ordering::Relaxed) {
+        eprintln!(
+            "Interrupted after {} of {total} inputs",
+            batch.entries.len()
+        );
+        INTERRUPTED_EXIT_CODE
+    } else if let Some(code) = stopped_by {
+        if batch.entries.len() < total {
+            eprintln!(
+                "Stopped at the first failure after {} of {total} inputs",
+                batch.entries.len()
+            );
         }
+        code
+    } else {
+        batch.exit_code()
     };
+    if let (Some(report), Some(path)) = (report, &args.report) {
+        if let Err(e) = report.write(path, code) {
+            diag::print(&[format!("{} {e}", diag::label())]);
+            // a run that converted everything still failed at its record
+            if code == 0 {
+                return e.exit_code();
+            }
+        }
+    }
+    code
+}
 
-    std::process::exit(exit_code.into());
+// Converts image data piped to stdin, e.g. behind a web proxy, without
+// touching disk. The output goes to stdout unless the job has an output file.
+fn convert_stdin(job: ConvertJob) -> u8 {
+    let mut data = Vec::new();
+    if io::stdin().lock().read_to_end(&mut data).is_err() {
+        exit_with(IoError::ReadError("-".into()));
+    }
+    convert_data(&data, "stdin", &job)
+}
+
+// Downloads a URL input and converts it in memory like stdin. Without
+// --output it is written to the working directory, named after the URL.
+#[cfg(feature = "net")]
+fn convert_url(url: &str, job: ConvertJob, timeout: Option<Duration>) -> u8 {
+    let download = match fetch::download(url, timeout) {
+        Ok(download) => download,
+        Err(e) => exit_with(e),
+    };
+    // what the server says the data is, else the URL's extension
+    let input_format = job
+        .input_format
+        .or_else(|| FormatType::from_mime(download.content_type.as_deref()?).ok())
+        .or_else(|| {
+            let ext = Path::new(fetch::file_name(url)?).extension()?;
+            FormatType::from_extension(&ext.to_string_lossy()).ok()
+        });
+    convert_data(
+        &download.data,
+        url,
+        &ConvertJob {
+            input_format,
+            ..job
+        },
+    )
+}
+
+// The file an in-memory conversion writes, `None` for stdout.
+fn output_file(output: Option<&Path>, to: FormatType, strict: bool) -> Option<PathBuf> {
+    output.filter(|o| *o != Path::new("-")).map(|given| {
+        let path = with_target_extension(given, to);
+        if path != given {
+            let warning = format!(
+                "--output {} has no extension, writing {}",
+                given.display(),
+                path.display()
+            );
+            if strict {
+                exit_with(ConversionError::StrictWarning(warning));
+            }
+            eprintln!("{} {warning}", style::warning("Warning:"));
+        }
+        path
+    })
+}
+
+// Converts the image data read from `source` as `job` describes and writes
+// it to the job's output file, or to stdout. The file is written like any
+// conversion output, so --on-conflict and --create-dirs apply. A declared
+// format that does not match the data's magic bytes is only trusted as a
+// fallback, a lying header gets a warning.
+fn convert_data(data: &[u8], source: &str, job: &ConvertJob) -> u8 {
+    let warn = |warning: String| {
+        if job.strict {
+            exit_with(ConversionError::StrictWarning(warning));
+        }
+        eprintln!("{} {warning}", style::warning("Warning:"));
+    };
+    let from = match (job.input_format, FormatType::sniff(data)) {
+        (Some(declared), Some(sniffed)) if declared != sniffed => {
+            warn(format!(
+                "{source} is declared as {declared} but holds {sniffed}, reading it as {sniffed}"
+            ));
+            sniffed
+        }
+        (Some(format), _) | (None, Some(format)) => format,
+        (None, None) => exit_with(InputError::InvalidArgument(format!(
+            "cannot detect the format of {source}, pass --from or --content-type"
+        ))),
+    };
+    if from == job.format_type && !job.allow_same_format {
+        exit_with(FormatError::SameFormat(from));
+    }
+    option_warnings(job).into_iter().for_each(warn);
+    let converted =
+        convert_in_memory_with_limit(data, from, job.format_type, &job.options, job.max_pixels);
+    let bytes = match converted {
+        Ok(bytes) => bytes,
+        Err(e) => exit_with(e),
+    };
+    let Some(path) = &job.output else {
+        return match io::stdout().lock().write_all(&bytes) {
+            Ok(()) => 0,
+            Err(_) => exit_with(IoError::WriteError("-".into())),
+        };
+    };
+    match write_output(&bytes, path, job.on_conflict, job.create_dirs) {
+        Ok(Some(_)) => 0,
+        Ok(None) => {
+            eprintln!("Skipped, output exists: {}", path.display());
+            0
+        }
+        Err(e) => exit_with(e),
+    }
+}
+
+// The format a --to target stands for. `auto` takes it from the extension of
+// the --output file or the one the --output-pattern ends in.
+fn resolve_format(
+    target: Target,
+    output: Option<&Path>,
+    pattern: Option<&OutputPattern>,
+) -> Result<FormatType, MeltforgeError> {
+    if let Target::Format(format) = target {
+        return Ok(format);
+    }
+    let ext = match pattern {
+        Some(pattern) => pattern.extension(),
+        None => output
+            .filter(|o| !is_directory_target(o))
+            .and_then(|o| o.extension()?.to_str()),
+    };
+    match ext {
+        Some(ext) => Ok(FormatType::from_extension(ext)?),
+        None => Err(InputError::InvalidArgument(
+            "--to auto needs an --output file or an --output-pattern ending in an extension".into(),
+        )
+        .into()),
+    }
+}
+
+// Reads the answer from stdin. An empty or closed input keeps the default
+// of failing on the existing file.
+fn ask_on_conflict(path: &Path) -> ConflictPolicy {
+    loop {
+        eprint!("{} exists. [o]verwrite, [s]kip, [r]ename? ", path.display());
+        let _ = io::stderr().flush();
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+            return ConflictPolicy::Fail;
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "" => return ConflictPolicy::Fail,
+            "o" | "overwrite" => return ConflictPolicy::Overwrite,
+            "s" | "skip" => return ConflictPolicy::Skip,
+            "r" | "rename" => return ConflictPolicy::Rename,
+            _ => eprintln!("Please answer o, s or r"),
+        }
+    }
+}
+
+fn run_stdin_jobs(strict: bool) -> u8 {
+    let results = match jobs::run_jobs(std::io::stdin().lock(), strict) {
+        Ok(results) => results,
+        Err(e) => exit_with(e),
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&results).expect("job results serialize")
+    );
+
+    aggregate_exit_code(results.iter().map(JobResult::exit_code))
+}
+
+fn run_thumbnail(args: ThumbnailArgs, quiet: bool, strict: bool) -> u8 {
+    let config = match Config::load(Path::new(".")) {
+        Ok(config) => config,
+        Err(e) => exit_with(e),
+    };
+    let format_type = args
+        .to
+        .or_else(|| {
+            config::target_from_output(args.output.as_deref()?)?
+                .parse()
+                .ok()
+        })
+        .or_else(|| mf_core::validate::detect_input_format(&args.input).ok());
+    let Some(format_type) = format_type else {
+        exit_with(InputError::MissingTargetFormat);
+    };
+    let output = args.output.clone().unwrap_or_else(|| {
+        let stem = args.input.file_stem().unwrap_or_default().to_string_lossy();
+        args.input
+            .with_file_name(format!("{stem}-thumb.{}", format_type.extension()))
+    });
+
+    let job = ConvertJob {
+        output: Some(output),
+        options: ConvertOptions {
+            quality: config.quality_for(format_type, args.quality, false),
+            background: args.background,
+            thumbnail: Some(Thumbnail {
+                size: args.size,
+                fit: if args.fit {
+                    ThumbnailFit::Letterbox
+                } else {
+                    ThumbnailFit::Crop
+                },
+            }),
+            ..ConvertOptions::default()
+        },
+        // a thumbnail is a new image even in the input's format
+        allow_same_format: true,
+        strict,
+        ..ConvertJob::new(&args.input, format_type)
+    };
+    match convert(job) {
+        Ok(outcome) => {
+            for warning in &outcome.warnings {
+                eprintln!("{} {warning}", style::warning("Warning:"));
+            }
+            if !quiet {
+                println!("{}", outcome.outputs[0].display());
+            }
+            0
+        }
+        Err(e) => exit_with(e),
+    }
+}
+
+fn run_manifest(path: &Path, quiet: bool, strict: bool) -> u8 {
+    let jobs = match manifest::load_manifest(path) {
+        Ok(jobs) => jobs,
+        Err(e) => exit_with(e),
+    };
+    let jobs: Vec<ConvertJob> = jobs
+        .into_iter()
+        .map(|job| ConvertJob { strict, ..job })
+        .collect();
+    if let Err(e) = validate_unique_outputs(&jobs) {
+        exit_with(e);
+    }
+
+    let mut batch = BatchResult::default();
+    for (job, result) in convert_batch(jobs) {
+        match &result {
+            Ok(outcome) if !quiet => {
+                for output in &outcome.outputs {
+                    println!("{} -> {}", job.input.display(), output.display());
+                }
+            }
+            Ok(_) => {}
+            Err(e) => diag::print(&[format!("{} {}: {e}", diag::label(), job.input.display())]),
+        }
+        batch.push(job.input, result);
+    }
+    if !quiet {
+        println!(
+            "Done: {} converted, {} failed",
+            batch.succeeded(),
+            batch.failed()
+        );
+    }
+    batch.exit_code()
+}
+
+fn print_stats(batch: &BatchResult, wall: Duration, quality: Option<u8>) {
+    let converted: Vec<&ConvertOutcome> = batch
+        .entries
+        .iter()
+        .filter_map(|(_, r)| r.as_ref().ok())
+        .filter(|o| !o.skipped)
+        .collect();
+    let busy: Duration = converted.iter().map(|o| o.timings.total).sum();
+    let megapixels = converted.iter().map(|o| o.pixels).sum::<u64>() as f64 / 1_000_000.0;
+
+    println!(
+        "Files:       {} ({} converted)",
+        batch.entries.len(),
+        converted.len()
+    );
+    if let Some(q) = quality {
+        println!("Quality:     {q}");
+    }
+    println!("Wall time:   {wall:.2?}");
+    if !converted.is_empty() {
+        println!("Per file:    {:.2?} average", busy / converted.len() as u32);
+    }
+    let secs = wall.as_secs_f64();
+    if secs > 0.0 {
+        println!(
+            "Throughput:  {:.2} MP/s ({megapixels:.2} MP)",
+            megapixels / secs
+        );
+    }
+    if let Some(largest) = converted.iter().map(|o| o.decoded_bytes).max() {
+        println!("Decoded:     {:.1} MB largest input", megabytes(largest));
+    }
+    if let Some(peak) = peak_rss() {
+        println!("Peak memory: {:.1} MB resident", megabytes(peak));
+    }
+}
+
+fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / 1_000_000.0
+}
+
+// Highest resident set size of the process so far, covering every file of
+// the batch. Only Linux exposes it without extra dependencies.
+#[cfg(target_os = "linux")]
+fn peak_rss() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peak_rss() -> Option<u64> {
+    None
+}
+
+// Deletes the outputs of a failed --transactional run, newest first. One
+// that cannot be removed is reported and the rest are still tried.
+fn roll_back(created: &[PathBuf]) {
+    let mut removed = 0;
+    for path in created.iter().rev() {
+        match fs::remove_file(path) {
+            Ok(()) => removed += 1,
+            Err(e) => eprintln!(
+                "{} cannot roll back {}: {e}",
+                style::warning("Warning:"),
+                path.display()
+            ),
+        }
+    }
+    eprintln!("Rolled back: removed {removed} outputs written by this run");
+}
+
+// Where the converted files of a batch spent their time, stage by stage,
+// so a slow resize stands out from a slow encoder.
+fn print_profile(batch: &BatchResult) {
+    let mut sum = Timings::default();
+    let mut files = 0;
+    for outcome in batch.entries.iter().filter_map(|(_, r)| r.as_ref().ok()) {
+        if !outcome.skipped {
+            sum += &outcome.timings;
+            files += 1;
+        }
+    }
+    if files == 0 {
+        return;
+    }
+    let mut stages = vec![("decode", sum.decode)];
+    stages.extend(sum.transforms.iter().copied());
+    stages.extend([
+        ("encode", sum.encode),
+        ("write", sum.write),
+        ("other", sum.total.saturating_sub(sum.stages())),
+    ]);
+    let total = sum.total.as_secs_f64();
+    eprintln!("Profile of {files} converted files:");
+    for (name, elapsed) in stages {
+        let share = if total > 0.0 {
+            elapsed.as_secs_f64() / total * 100.0
+        } else {
+            0.0
+        };
+        eprintln!(
+            "  {name:<12} {:>10} {share:>5.1}%",
+            format!("{elapsed:.1?}")
+        );
+    }
+    eprintln!("  {:<12} {:>10}", "total", format!("{:.1?}", sum.total));
+}
+
+fn format_timings(t: &Timings) -> String {
+    let mut line = format!("timings: decode {:.1?}", t.decode);
+    for (name, elapsed) in &t.transforms {
+        line.push_str(&format!(", {name} {elapsed:.1?}"));
+    }
+    line.push_str(&format!(
+        ", encode {:.1?}, write {:.1?}, total {:.1?}",
+        t.encode, t.write, t.total
+    ));
+    line
+}
+
+fn show_info(path: &Path, from: Option<FormatType>, output: OutputFormat) -> u8 {
+    let info = match inspect(path, from) {
+        Ok(info) => info,
+        Err(e) => exit_with(e),
+    };
+    match output {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&info).expect("image info serializes")
+        ),
+        OutputFormat::Text => {
+            println!("{}", info.path.display());
+            println!("  format:    {}", info.format);
+            println!("  size:      {}x{}", info.width, info.height);
+            println!("  color:     {}, {}-bit", info.color, info.bit_depth);
+            println!("  file size: {} bytes", info.file_size);
+        }
+    }
+    0
+}
+
+// Reports each image as OK or with its error. The exit code is that of the
+// error, or the partial-failure code when only some images failed.
+fn run_validate(paths: &[PathBuf], from: Option<FormatType>, quiet: bool) -> u8 {
+    let codes = paths.iter().map(|path| match validate_image(path, from) {
+        Ok(info) => {
+            if !quiet {
+                println!(
+                    "{} {} ({}, {}x{})",
+                    style::success("OK:"),
+                    path.display(),
+                    info.format,
+                    info.width,
+                    info.height
+                );
+            }
+            0
+        }
+        Err(e) => {
+            diag::print(&error_lines(&e));
+            e.exit_code()
+        }
+    });
+    aggregate_exit_code(codes)
+}
+
+#[derive(Serialize)]
+struct Capabilities {
+    inputs: Vec<FormatType>,
+    outputs: Vec<FormatType>,
+    conversions: Vec<Conversion>,
+    disabled: Vec<FormatType>,
+}
+
+#[derive(Serialize)]
+struct Conversion {
+    from: FormatType,
+    to: FormatType,
+}
+
+// Everything is derived from the compatibility rules, so the listing cannot drift from them.
+fn list_formats(output: OutputFormat) -> u8 {
+    let conversions = supported_conversions();
+    let used_as = |pick: fn(&(FormatType, FormatType)) -> FormatType| {
+        FormatType::all()
+            .into_iter()
+            .filter(|f| conversions.iter().any(|c| pick(c) == *f))
+            .collect::<Vec<_>>()
+    };
+    let caps = Capabilities {
+        inputs: used_as(|c| c.0),
+        outputs: used_as(|c| c.1),
+        conversions: conversions
+            .iter()
+            .map(|&(from, to)| Conversion { from, to })
+            .collect(),
+        disabled: FormatType::all()
+            .into_iter()
+            .filter(|f| !f.is_enabled())
+            .collect(),
+    };
+
+    match output {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&caps).expect("capabilities serialize")
+        ),
+        OutputFormat::Text => {
+            let names = |formats: &[FormatType]| {
+                formats
+                    .iter()
+                    .map(|f| f.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            println!("Input formats:  {}", names(&caps.inputs));
+            println!("Output formats: {}", names(&caps.outputs));
+            println!("Conversions:");
+            for c in &caps.conversions {
+                println!("  {:<5} -> {}", c.from.name(), c.to.name());
+            }
+            if !caps.disabled.is_empty() {
+                println!(
+                    "Not enabled in this build: {} (see cargo features)",
+                    names(&caps.disabled)
+                );
+            }
+        }
+    }
+    0
+}
+
+fn exit_with(e: impl Into<MeltforgeError>) -> ! {
+    let e = e.into();
+    diag::print(&error_lines(&e));
+    std::process::exit(e.exit_code().into());
+}
+
+// The error and its hint, if there is one.
+fn error_lines(e: &MeltforgeError) -> Vec<String> {
+    let mut lines = vec![format!("{} {e}", diag::label())];
+    lines.extend(hint(e).map(|hint| format!("Hint: {hint}")));
+    lines
+}
+
+fn hint(e: &MeltforgeError) -> Option<String> {
+    match e {
+        MeltforgeError::Format(FormatError::MissingExtension(_)) => {
+            Some("specify the input format with --from, e.g. --from png".into())
+        }
+        MeltforgeError::Format(FormatError::UnknownFormat(name)) => {
+            FormatType::suggest(name).map(|f| format!("did you mean '{f}'?"))
+        }
+        _ => None,
+    }
+}
+
+fn report_error(progress: &Progress, e: &MeltforgeError) {
+    let mut lines = error_lines(e);
+    if let MeltforgeError::Io(ioe) = e {
+        match ioe {
+            IoError::AlreadyExists(p) => {
+                lines.push(format!("File already exists: {}", p.display()))
+            }
+            IoError::MissingParent(p) => {
+                lines.push(format!("Target directory not found: {}", p.display()))
+            }
+            IoError::PermissionDenied(p) => {
+                lines.push(format!("No permission for: {}", p.display()))
+            }
+            _ => {}
+        }
+    }
+    progress.suspend(|| diag::print(&lines));
 }
@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::Path;
+
+use mf_core::error::{InputError, IoError, MeltforgeError};
+use mf_core::format::FormatType;
+use mf_core::job::ConvertJob;
+
+/// Reads a manifest with one conversion per line, `input -> format` or
+/// `input -> output`. Blank lines and `#` comments are ignored, whole-line
+/// or after an entry (`a.png -> jpg  # hero`), and relative paths are
+/// resolved against the manifest's directory.
+pub fn load_manifest(path: &Path) -> Result<Vec<ConvertJob>, MeltforgeError> {
+    let text = fs::read_to_string(path).map_err(|_| IoError::ReadError(path.to_path_buf()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    parse_manifest(&text, base).map_err(|(line, msg)| {
+        InputError::InvalidArgument(format!("{}:{line}: {msg}", path.display())).into()
+    })
+}
+
+// Errors carry the 1-based line number, the caller adds the file name.
+fn parse_manifest(text: &str, base: &Path) -> Result<Vec<ConvertJob>, (usize, String)> {
+    let mut jobs = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = strip_comment(line.trim()).trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let fail = |msg: String| (index + 1, msg);
+        let entry = line
+            .split_once("->")
+            .map(|(input, target)| (input.trim(), target.trim()))
+            .filter(|(input, target)| !input.is_empty() && !target.is_empty());
+        let Some((input, target)) = entry else {
+            return Err(fail(
+                "expected `input -> format` or `input -> output`".into(),
+            ));
+        };
+        let input = base.join(input);
+
+        // a bare name like `webp` is a format, anything else an output path
+        let job = match target.parse::<FormatType>() {
+            Ok(format) => ConvertJob::new(&input, format),
+            Err(_) => {
+                let output = base.join(target);
+                let format = output
+                    .extension()
+                    .and_then(|ext| FormatType::from_extension(ext.to_str()?).ok())
+                    .ok_or_else(|| {
+                        fail(format!(
+                            "'{target}' is neither a format nor an output with a known extension"
+                        ))
+                    })?;
+                ConvertJob {
+                    output: Some(output),
+                    ..ConvertJob::new(&input, format)
+                }
+            }
+        };
+        jobs.push(job);
+    }
+    Ok(jobs)
+}
+
+// A comment starts at a `#` that begins the line or follows whitespace, so
+// names like `shot#2.png` are kept whole.
+fn strip_comment(line: &str) -> &str {
+    let start = line
+        .char_indices()
+        .find(|&(i, c)| c == '#' && (i == 0 || line[..i].ends_with(char::is_whitespace)));
+    start.map_or(line, |(i, _)| &line[..i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_formats_and_outputs_skipping_comments() {
+        let text = "# thumbnails\na.png -> jpg\n\n  sub/b.png->out/b.tiff\n";
+        let jobs = parse_manifest(text, Path::new("site")).unwrap();
+
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].input, Path::new("site/a.png"));
+        assert_eq!(jobs[0].format_type, FormatType::JPEG);
+        assert!(jobs[0].output.is_none());
+        assert_eq!(jobs[1].input, Path::new("site/sub/b.png"));
+        assert_eq!(jobs[1].format_type, FormatType::TIFF);
+        assert_eq!(
+            jobs[1].output.as_deref(),
+            Some(Path::new("site/out/b.tiff"))
+        );
+    }
+
+    #[test]
+    fn trailing_comments_are_ignored() {
+        let text = "a.png -> jpg  # hero\nshot#2.png -> out/shot#2.tiff\t# kept\n";
+        let jobs = parse_manifest(text, Path::new("")).unwrap();
+
+        assert_eq!(jobs[0].input, Path::new("a.png"));
+        assert_eq!(jobs[0].format_type, FormatType::JPEG);
+        assert_eq!(jobs[1].input, Path::new("shot#2.png"));
+        assert_eq!(
+            jobs[1].output.as_deref(),
+            Some(Path::new("out/shot#2.tiff"))
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_a_malformed_entry() {
+        let err = parse_manifest("a.png -> jpg\nb.png jpg\n", Path::new("")).unwrap_err();
+        assert_eq!(err.0, 2);
+        let err = parse_manifest("# a\n\nb.png -> out/b.xyz\n", Path::new("")).unwrap_err();
+        assert_eq!(err.0, 3);
+    }
+}
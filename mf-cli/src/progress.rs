@@ -0,0 +1,95 @@
+use std::io::IsTerminal;
+use std::time::Instant;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use mf_core::job::ConvertStage;
+
+// the prefix holds the last stage of the current file, see `Progress::stage`
+const TEMPLATE: &str = "{spinner} [{elapsed_precise}] [{bar:30}] {msg} ETA {eta}  {prefix}";
+
+// Progress over a batch of files. The bar length is the total input size in
+// bytes, so the ETA reflects file sizes instead of just the file count.
+pub struct Progress {
+    bar: ProgressBar,
+    started: Instant,
+    files_total: usize,
+    files_done: usize,
+    bytes_done: u64,
+}
+
+impl Progress {
+    pub fn new(total_bytes: u64, files_total: usize, quiet: bool) -> Self {
+        // hidden under --quiet and when stderr is piped/redirected
+        let bar = if quiet || !std::io::stderr().is_terminal() {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(total_bytes)
+        };
+        bar.set_style(
+            ProgressStyle::with_template(TEMPLATE)
+                .expect("valid progress template")
+                .progress_chars("=> "),
+        );
+
+        let progress = Self {
+            bar,
+            started: Instant::now(),
+            files_total,
+            files_done: 0,
+            bytes_done: 0,
+        };
+        progress.update_message();
+        progress
+    }
+
+    // Shows how far the current file got, e.g. "encoded cat.jpg".
+    pub fn stage(&self, stage: &ConvertStage) {
+        let name = stage.path().file_name().unwrap_or_default();
+        self.bar
+            .set_prefix(format!("{} {}", stage.name(), name.to_string_lossy()));
+    }
+
+    // Called once per finished file (successful or not) with its input size.
+    pub fn file_done(&mut self, bytes: u64) {
+        self.bar.set_prefix("");
+        self.files_done += 1;
+        self.bytes_done += bytes;
+        self.bar.inc(bytes);
+        self.update_message();
+    }
+
+    // Prints a line without tearing the bar; still prints when the bar is hidden.
+    pub fn println(&self, line: impl AsRef<str>) {
+        self.bar.suspend(|| println!("{}", line.as_ref()));
+    }
+
+    pub fn eprintln(&self, line: impl AsRef<str>) {
+        self.bar.suspend(|| eprintln!("{}", line.as_ref()));
+    }
+
+    // Hides the bar while `f` runs, e.g. to prompt on the terminal.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.bar.suspend(f)
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+
+    fn update_message(&self) {
+        self.bar.set_message(format!(
+            "{}/{} files  {}",
+            self.files_done,
+            self.files_total,
+            format_throughput(self.bytes_done, self.started.elapsed().as_secs_f64())
+        ));
+    }
+}
+
+// Cumulative throughput: all input bytes processed so far over total elapsed time.
+fn format_throughput(bytes: u64, secs: f64) -> String {
+    if secs <= 0.0 {
+        return "-- MB/s".to_string();
+    }
+    format!("{:.2} MB/s", bytes as f64 / 1_000_000.0 / secs)
+}
@@ -0,0 +1,176 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use mf_core::error::{IoError, MeltforgeError};
+use mf_core::format::FormatType;
+use mf_core::job::ConvertOutcome;
+
+/// Everything a convert run did, for `--report`. Entries are added as the
+/// batch goes and the file is written once at the end, whatever the outcome.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// Seconds since the Unix epoch.
+    started: u64,
+    to: String,
+    entries: Vec<Entry>,
+    converted: usize,
+    skipped: usize,
+    failed: usize,
+    exit_code: u8,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum Entry {
+    Converted {
+        input: PathBuf,
+        input_bytes: u64,
+        outputs: Vec<PathBuf>,
+        output_bytes: u64,
+        warnings: Vec<String>,
+        timings: Timings,
+    },
+    Skipped {
+        input: PathBuf,
+        input_bytes: u64,
+        outputs: Vec<PathBuf>,
+    },
+    Failed {
+        input: PathBuf,
+        input_bytes: u64,
+        error: String,
+        code: &'static str,
+    },
+}
+
+// milliseconds, so the numbers stay readable next to each other
+#[derive(Debug, Serialize)]
+struct Timings {
+    decode: f64,
+    encode: f64,
+    write: f64,
+    total: f64,
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl Report {
+    pub fn new(to: FormatType) -> Self {
+        Report {
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            to: to.name().to_string(),
+            entries: Vec::new(),
+            converted: 0,
+            skipped: 0,
+            failed: 0,
+            exit_code: 0,
+        }
+    }
+
+    pub fn push(
+        &mut self,
+        input: &Path,
+        input_bytes: u64,
+        result: &Result<ConvertOutcome, MeltforgeError>,
+    ) {
+        let input = input.to_path_buf();
+        let entry = match result {
+            Ok(outcome) if outcome.skipped => {
+                self.skipped += 1;
+                Entry::Skipped {
+                    input,
+                    input_bytes,
+                    outputs: outcome.outputs.clone(),
+                }
+            }
+            Ok(outcome) => {
+                self.converted += 1;
+                let t = &outcome.timings;
+                Entry::Converted {
+                    input,
+                    input_bytes,
+                    output_bytes: outcome
+                        .outputs
+                        .iter()
+                        .map(|p| fs::metadata(p).map_or(0, |m| m.len()))
+                        .sum(),
+                    outputs: outcome.outputs.clone(),
+                    warnings: outcome.warnings.clone(),
+                    timings: Timings {
+                        decode: millis(t.decode),
+                        encode: millis(t.encode),
+                        write: millis(t.write),
+                        total: millis(t.total),
+                    },
+                }
+            }
+            Err(e) => {
+                self.failed += 1;
+                Entry::Failed {
+                    input,
+                    input_bytes,
+                    error: e.to_string(),
+                    code: e.code(),
+                }
+            }
+        };
+        self.entries.push(entry);
+    }
+
+    /// Writes the report as JSON, with the exit code the run ends with.
+    pub fn write(mut self, path: &Path, exit_code: u8) -> Result<(), MeltforgeError> {
+        self.exit_code = exit_code;
+        let failed = || IoError::WriteError(path.to_path_buf());
+        let mut out = BufWriter::new(File::create(path).map_err(|_| failed())?);
+        serde_json::to_writer_pretty(&mut out, &self).map_err(|_| failed())?;
+        writeln!(out)
+            .and_then(|_| out.flush())
+            .map_err(|_| failed())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mf_core::convert::convert;
+    use mf_core::job::ConvertJob;
+
+    #[test]
+    fn report_lists_every_input_with_its_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+        let missing = dir.path().join("missing.png");
+
+        let mut report = Report::new(FormatType::JPEG);
+        let ok = convert(ConvertJob::new(&input, FormatType::JPEG));
+        report.push(&input, 100, &ok);
+        let failed = convert(ConvertJob::new(&missing, FormatType::JPEG));
+        report.push(&missing, 0, &failed);
+        let path = dir.path().join("report.json");
+        report.write(&path, 6).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["to"], "jpeg");
+        assert_eq!(json["exit_code"], 6);
+        assert_eq!(json["converted"], 1);
+        assert_eq!(json["failed"], 1);
+        let entries = json["entries"].as_array().unwrap();
+        assert_eq!(entries[0]["status"], "converted");
+        assert_eq!(entries[0]["input_bytes"], 100);
+        assert!(entries[0]["output_bytes"].as_u64().unwrap() > 0);
+        assert!(entries[0]["timings"]["total"].is_number());
+        assert_eq!(entries[1]["status"], "failed");
+        assert_eq!(entries[1]["code"], "E_MISSING_INPUT");
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use mf_core::convert::write_output;
+use mf_core::error::MeltforgeError;
+use mf_core::format::FormatType;
+use mf_core::info::inspect;
+use mf_core::job::{ConvertJob, ConvertOptions};
+use mf_core::validate::detect_input_format;
+
+/// What `--sidecar` writes next to an output, as `<output>.json`.
+#[derive(Debug, Serialize)]
+struct Sidecar<'a> {
+    output: &'a Path,
+    format: FormatType,
+    /// Read back from the written file; unknown for formats that cannot be read.
+    width: Option<u32>,
+    height: Option<u32>,
+    bytes: u64,
+    source: Source<'a>,
+    options: &'a ConvertOptions,
+    /// Seconds since the Unix epoch, as are the other times. Left out with
+    /// `reproducible`, like the source's modification time.
+    converted_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Source<'a> {
+    path: &'a Path,
+    format: Option<FormatType>,
+    bytes: u64,
+    modified: Option<u64>,
+}
+
+fn unix_secs(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+/// Path of the sidecar of `output`: `out.png` gets `out.png.json`.
+pub fn sidecar_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+/// Writes the sidecar of `output`, converted by `job` from `input` of
+/// `input_bytes`, and returns its path. An existing sidecar is handled by the
+/// job's conflict policy like an output, `None` if it was skipped.
+pub fn write_sidecar(
+    output: &Path,
+    input: &Path,
+    input_bytes: u64,
+    job: &ConvertJob,
+) -> Result<Option<PathBuf>, MeltforgeError> {
+    let info = inspect(output, Some(job.format_type)).ok();
+    let timestamp = |time: SystemTime| unix_secs(time).filter(|_| !job.options.reproducible);
+    let sidecar = Sidecar {
+        output,
+        format: job.format_type,
+        width: info.as_ref().map(|i| i.width),
+        height: info.as_ref().map(|i| i.height),
+        bytes: fs::metadata(output).map_or(0, |m| m.len()),
+        source: Source {
+            path: input,
+            format: job.input_format.or_else(|| detect_input_format(input).ok()),
+            bytes: input_bytes,
+            modified: fs::metadata(input)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(timestamp),
+        },
+        options: &job.options,
+        converted_at: timestamp(SystemTime::now()),
+    };
+    let json = serde_json::to_string_pretty(&sidecar).expect("sidecar serializes");
+    write_output(
+        (json + "\n").as_bytes(),
+        &sidecar_path(output),
+        job.on_conflict,
+        false,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mf_core::convert::convert;
+    use mf_core::output::ConflictPolicy;
+
+    #[test]
+    fn sidecar_describes_the_output_and_its_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(12, 7).save(&input).unwrap();
+        let job = ConvertJob {
+            options: ConvertOptions {
+                quality: Some(70),
+                ..ConvertOptions::default()
+            },
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        };
+        let output = convert(job.clone()).unwrap().outputs.remove(0);
+
+        let path = write_sidecar(&output, &input, 123, &job).unwrap().unwrap();
+        assert_eq!(path, dir.path().join("in.jpg.json"));
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["format"], "jpeg");
+        assert_eq!(json["width"], 12);
+        assert_eq!(json["height"], 7);
+        assert_eq!(json["source"]["format"], "png");
+        assert_eq!(json["source"]["bytes"], 123);
+        assert!(json["source"]["modified"].is_u64());
+        assert_eq!(json["options"]["quality"], 70);
+        assert!(json["converted_at"].as_u64().unwrap() > 0);
+
+        let job = ConvertJob {
+            options: ConvertOptions {
+                reproducible: true,
+                ..job.options
+            },
+            ..job
+        };
+        // the sidecar now exists, and is only replaced when outputs are
+        assert!(write_sidecar(&output, &input, 123, &job).is_err());
+        let skipping = ConvertJob {
+            on_conflict: ConflictPolicy::Skip,
+            ..job.clone()
+        };
+        assert_eq!(
+            write_sidecar(&output, &input, 123, &skipping).unwrap(),
+            None
+        );
+        let job = ConvertJob {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..job
+        };
+        let path = write_sidecar(&output, &input, 123, &job).unwrap().unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(json["converted_at"].is_null() && json["source"]["modified"].is_null());
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::ValueEnum;
+
+/// When to color the labels of status lines, `--color`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color a stream if it is a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+// Decided once at startup, separately for the two streams, so `2>log` keeps
+// the terminal's stdout colored and the log plain.
+static STDOUT: AtomicBool = AtomicBool::new(false);
+static STDERR: AtomicBool = AtomicBool::new(false);
+
+pub fn init(choice: ColorChoice) {
+    // https://no-color.org: set and not empty
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let enabled = |is_terminal: bool| match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => is_terminal && !no_color,
+    };
+    STDOUT.store(enabled(std::io::stdout().is_terminal()), Ordering::Relaxed);
+    STDERR.store(enabled(std::io::stderr().is_terminal()), Ordering::Relaxed);
+}
+
+fn paint(enabled: &AtomicBool, code: &str, text: &str) -> String {
+    if enabled.load(Ordering::Relaxed) {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Red, for stderr.
+pub fn error(text: &str) -> String {
+    paint(&STDERR, "1;31", text)
+}
+
+/// Red, for errors sent to stdout by `--error-output`.
+pub fn error_stdout(text: &str) -> String {
+    paint(&STDOUT, "1;31", text)
+}
+
+/// Yellow, for stderr.
+pub fn warning(text: &str) -> String {
+    paint(&STDERR, "33", text)
+}
+
+/// Green, for stdout.
+pub fn success(text: &str) -> String {
+    paint(&STDOUT, "32", text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paints_only_when_enabled() {
+        let flag = AtomicBool::new(false);
+        assert_eq!(paint(&flag, "31", "Error:"), "Error:");
+        flag.store(true, Ordering::Relaxed);
+        assert_eq!(paint(&flag, "31", "Error:"), "\x1b[31mError:\x1b[0m");
+    }
+}
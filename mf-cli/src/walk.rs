@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use mf_core::error::IoError;
+use mf_core::format::FormatType;
+
+/// A path the walk came across but left out.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Skipped {
+    /// Symlinks are only followed with `--follow-symlinks`.
+    Symlink(PathBuf),
+    /// Resolves to a file or directory the walk has already been through.
+    AlreadyVisited(PathBuf),
+}
+
+#[derive(Debug, Default)]
+pub struct Walk {
+    pub files: Vec<PathBuf>,
+    pub skipped: Vec<Skipped>,
+    follow_symlinks: bool,
+    // canonical paths, so cycles and trees reachable through several links are seen once
+    visited: HashSet<PathBuf>,
+}
+
+/// Expands the directories among `inputs` into the image files below them,
+/// in sorted order. Other inputs are kept as given, so a missing file is
+/// still reported by its conversion.
+pub fn walk_inputs(inputs: Vec<PathBuf>, follow_symlinks: bool) -> Result<Walk, IoError> {
+    let mut walk = Walk {
+        follow_symlinks,
+        ..Walk::default()
+    };
+    for input in inputs {
+        if input.is_dir() {
+            walk.visit_dir(&input)?;
+        } else {
+            walk.files.push(input);
+        }
+    }
+    Ok(walk)
+}
+
+impl Walk {
+    fn visit_dir(&mut self, dir: &Path) -> Result<(), IoError> {
+        if !self.first_visit(dir)? {
+            self.skipped
+                .push(Skipped::AlreadyVisited(dir.to_path_buf()));
+            return Ok(());
+        }
+
+        let read_err = |_| IoError::ReadError(dir.to_path_buf());
+        let mut entries = fs::read_dir(dir)
+            .map_err(read_err)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(read_err)?;
+        entries.sort();
+
+        for path in entries {
+            let is_symlink = fs::symlink_metadata(&path)
+                .map_err(|_| IoError::ReadError(path.clone()))?
+                .file_type()
+                .is_symlink();
+            if is_symlink && !self.follow_symlinks {
+                self.skipped.push(Skipped::Symlink(path));
+            } else if path.is_dir() {
+                self.visit_dir(&path)?;
+            } else if path.is_file() && has_image_extension(&path) {
+                if self.first_visit(&path)? {
+                    self.files.push(path);
+                } else {
+                    self.skipped.push(Skipped::AlreadyVisited(path));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn first_visit(&mut self, path: &Path) -> Result<bool, IoError> {
+        let real = fs::canonicalize(path).map_err(|_| IoError::ReadError(path.to_path_buf()))?;
+        Ok(self.visited.insert(real))
+    }
+}
+
+fn has_image_extension(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| FormatType::from_extension(&ext.to_string_lossy()).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("a.png"), b"png").unwrap();
+        fs::write(dir.path().join("sub/b.JPG"), b"jpg").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"txt").unwrap();
+        dir
+    }
+
+    #[test]
+    fn collects_image_files_below_directories() {
+        let dir = tree();
+        let single = dir.path().join("single.png");
+
+        let walk = walk_inputs(vec![dir.path().into(), single.clone()], false).unwrap();
+
+        assert_eq!(
+            walk.files,
+            [
+                dir.path().join("a.png"),
+                dir.path().join("sub/b.JPG"),
+                single
+            ]
+        );
+        assert!(walk.skipped.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycles_are_skipped() {
+        let dir = tree();
+        let link = dir.path().join("sub/loop");
+        std::os::unix::fs::symlink(dir.path(), &link).unwrap();
+
+        let walk = walk_inputs(vec![dir.path().into()], false).unwrap();
+        assert_eq!(walk.files.len(), 2);
+        assert_eq!(walk.skipped, [Skipped::Symlink(link.clone())]);
+
+        let walk = walk_inputs(vec![dir.path().into()], true).unwrap();
+        assert_eq!(walk.files.len(), 2);
+        assert_eq!(walk.skipped, [Skipped::AlreadyVisited(link)]);
+    }
+}
@@ -0,0 +1,112 @@
+use image::{DynamicImage, GenericImageView};
+
+// Below this many translucent pixels there is too little evidence either way.
+const MIN_TRANSLUCENT_PIXELS: usize = 16;
+
+/// Heuristic check for premultiplied alpha stored in a straight-alpha format.
+///
+/// Premultiplied pixels never have a color channel above their alpha value,
+/// and fully transparent pixels are black. Straight alpha images almost
+/// always violate that somewhere in their translucent areas.
+pub fn looks_premultiplied(img: &DynamicImage) -> bool {
+    if !img.color().has_alpha() {
+        return false;
+    }
+
+    let mut translucent = 0;
+    let mut has_color = false;
+    for px in img.to_rgba8().pixels() {
+        let [r, g, b, a] = px.0;
+        let max = r.max(g).max(b);
+        if max > a {
+            return false;
+        }
+        if a > 0 && a < u8::MAX {
+            translucent += 1;
+            has_color |= max > 0;
+        }
+    }
+    translucent >= MIN_TRANSLUCENT_PIXELS && has_color
+}
+
+/// Rectangle in pixel coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Bounding box of the image content, `None` if there is none.
+///
+/// The background is taken from the top-left pixel: if it is fully
+/// transparent, every fully transparent pixel counts as background,
+/// otherwise only pixels of exactly that color do.
+pub fn content_bounds(img: &DynamicImage) -> Option<Region> {
+    let background = img.get_pixel(0, 0);
+    let transparent = img.color().has_alpha() && background[3] == 0;
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, px) in img.pixels() {
+        let is_background = if transparent {
+            px[3] == 0
+        } else {
+            px == background
+        };
+        if !is_background {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+
+    (min_x <= max_x).then(|| Region {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x + 1,
+        height: max_y - min_y + 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    #[test]
+    fn straight_alpha_is_not_flagged() {
+        let img = RgbaImage::from_pixel(8, 8, Rgba([200, 180, 160, 100]));
+        assert!(!looks_premultiplied(&DynamicImage::ImageRgba8(img)));
+    }
+
+    #[test]
+    fn opaque_image_is_not_flagged() {
+        let img = RgbaImage::from_pixel(8, 8, Rgba([10, 10, 10, 255]));
+        assert!(!looks_premultiplied(&DynamicImage::ImageRgba8(img)));
+    }
+
+    #[test]
+    fn content_bounds_uses_border_color_without_alpha() {
+        let mut img = RgbImage::from_pixel(10, 10, Rgb([255, 255, 255]));
+        img.put_pixel(2, 3, Rgb([0, 0, 0]));
+        img.put_pixel(6, 4, Rgb([0, 0, 0]));
+        let bounds = content_bounds(&DynamicImage::ImageRgb8(img));
+        assert_eq!(
+            bounds,
+            Some(Region {
+                x: 2,
+                y: 3,
+                width: 5,
+                height: 2
+            })
+        );
+    }
+
+    #[test]
+    fn fully_transparent_image_has_no_content() {
+        let img = RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 0]));
+        assert_eq!(content_bounds(&DynamicImage::ImageRgba8(img)), None);
+    }
+}
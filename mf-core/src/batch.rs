@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::convert::{convert, convert_with_cancel};
+use crate::error::MeltforgeError;
+use crate::job::{ConvertJob, ConvertOutcome};
+
+/// Exit code of a batch where some inputs converted and others failed.
+pub const PARTIAL_FAILURE_EXIT_CODE: u8 = 6;
+
+/// Per-input outcomes of a batch conversion.
+#[derive(Debug, Default)]
+pub struct BatchResult {
+    pub entries: Vec<(PathBuf, Result<ConvertOutcome, MeltforgeError>)>,
+}
+
+impl BatchResult {
+    pub fn push(&mut self, input: PathBuf, result: Result<ConvertOutcome, MeltforgeError>) {
+        self.entries.push((input, result));
+    }
+
+    pub fn succeeded(&self) -> usize {
+        self.entries.iter().filter(|(_, r)| r.is_ok()).count()
+    }
+
+    /// Inputs left alone because their output already existed.
+    pub fn skipped(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|(_, r)| r.as_ref().is_ok_and(|o| o.skipped))
+            .count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.entries.len() - self.succeeded()
+    }
+
+    /// 0 if every input converted, the first error's code if all failed and
+    /// [`PARTIAL_FAILURE_EXIT_CODE`] for a mix of both.
+    pub fn exit_code(&self) -> u8 {
+        aggregate_exit_code(self.entries.iter().map(|(_, r)| match r {
+            Ok(_) => 0,
+            Err(e) => e.exit_code(),
+        }))
+    }
+}
+
+/// Converts `jobs` one after another on the calling thread, yielding each job
+/// with its result as soon as it finishes. Nothing runs until the iterator is
+/// polled, and a failed job does not stop the ones after it.
+pub fn convert_batch(
+    jobs: Vec<ConvertJob>,
+) -> impl Iterator<Item = (ConvertJob, Result<ConvertOutcome, MeltforgeError>)> {
+    jobs.into_iter().map(|job| {
+        let result = convert(job.clone());
+        (job, result)
+    })
+}
+
+/// Converts `jobs` in order until `cancel` is set and returns the results so
+/// far. The job running when it is set ends with
+/// [`ConversionError::Cancelled`](crate::error::ConversionError::Cancelled)
+/// unless it was already writing; jobs after it are left out.
+pub fn convert_batch_with_cancel(
+    jobs: Vec<ConvertJob>,
+    cancel: &AtomicBool,
+) -> Vec<(ConvertJob, Result<ConvertOutcome, MeltforgeError>)> {
+    let mut results = Vec::new();
+    for job in jobs {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        let result = convert_with_cancel(job.clone(), cancel);
+        results.push((job, result));
+    }
+    results
+}
+
+/// Combines per-input exit codes the same way as [`BatchResult::exit_code`].
+pub fn aggregate_exit_code(codes: impl IntoIterator<Item = u8>) -> u8 {
+    let mut any_ok = false;
+    let mut first_failure = None;
+    for code in codes {
+        if code == 0 {
+            any_ok = true;
+        } else {
+            first_failure.get_or_insert(code);
+        }
+    }
+    match first_failure {
+        None => 0,
+        Some(_) if any_ok => PARTIAL_FAILURE_EXIT_CODE,
+        Some(code) => code,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::FormatType;
+
+    #[test]
+    fn convert_batch_yields_each_job_lazily_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+        let jobs = vec![
+            ConvertJob::new(dir.path().join("missing.png"), FormatType::JPEG),
+            ConvertJob::new(&input, FormatType::JPEG),
+        ];
+
+        let mut events = convert_batch(jobs);
+        assert!(!dir.path().join("in.jpg").exists());
+
+        let (job, result) = events.next().unwrap();
+        assert!(job.input.ends_with("missing.png"));
+        assert_eq!(result.unwrap_err().exit_code(), 2);
+        let (job, result) = events.next().unwrap();
+        assert_eq!(job.input, input);
+        assert!(result.unwrap().outputs[0].exists());
+        assert!(events.next().is_none());
+    }
+
+    #[test]
+    fn cancelled_batch_stops_before_the_next_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+        let jobs = vec![ConvertJob::new(&input, FormatType::JPEG)];
+
+        assert!(convert_batch_with_cancel(jobs.clone(), &AtomicBool::new(true)).is_empty());
+        let results = convert_batch_with_cancel(jobs, &AtomicBool::new(false));
+        assert!(results[0].1.is_ok());
+    }
+
+    #[test]
+    fn exit_code_distinguishes_partial_from_total_failure() {
+        assert_eq!(aggregate_exit_code([0, 0]), 0);
+        assert_eq!(aggregate_exit_code([0, 5, 2]), PARTIAL_FAILURE_EXIT_CODE);
+        assert_eq!(aggregate_exit_code([5, 2]), 5);
+        assert_eq!(aggregate_exit_code([]), 0);
+    }
+}
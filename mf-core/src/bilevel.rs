@@ -0,0 +1,77 @@
+//! Black-and-white output, written as 1-bit PNG.
+
+use image::{
+    error::{EncodingError, ImageFormatHint},
+    DynamicImage, GrayImage, ImageError, ImageFormat, ImageResult, Luma,
+};
+
+use crate::{color::Color, transform::flatten};
+
+/// Gray level from which a pixel turns white when no threshold is set.
+pub const DEFAULT_THRESHOLD: u8 = 128;
+
+/// Turns every pixel black or white by its gray level, after flattening any
+/// alpha onto `background`.
+pub(crate) fn binarize(img: &DynamicImage, threshold: u8, background: Color) -> GrayImage {
+    let mut gray = if img.color().has_alpha() {
+        flatten(img, background).to_luma8()
+    } else {
+        img.to_luma8()
+    };
+    for Luma([level]) in gray.pixels_mut() {
+        *level = if *level >= threshold { 255 } else { 0 };
+    }
+    gray
+}
+
+/// Packs a binarized image eight pixels to the byte, as a 1-bit grayscale
+/// PNG. Any level but 0 is white.
+pub(crate) fn encode_png(img: &GrayImage) -> ImageResult<Vec<u8>> {
+    let (width, height) = img.dimensions();
+    let stride = width.div_ceil(8) as usize;
+    let mut packed = vec![0u8; stride * height as usize];
+    for (x, y, Luma([level])) in img.enumerate_pixels() {
+        if *level != 0 {
+            packed[y as usize * stride + x as usize / 8] |= 0x80 >> (x % 8);
+        }
+    }
+
+    let mut buf = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buf, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::One);
+    let mut writer = encoder.write_header().map_err(encoding_error)?;
+    writer.write_image_data(&packed).map_err(encoding_error)?;
+    writer.finish().map_err(encoding_error)?;
+    Ok(buf)
+}
+
+fn encoding_error(e: png::EncodingError) -> ImageError {
+    ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Png),
+        e,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_thresholded_pixels_into_a_1_bit_png() {
+        // 10 wide, so each row needs a second, partly used byte
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(10, 2, |x, _| Luma([x as u8 * 25])));
+        let bilevel = binarize(&img, 100, Color::WHITE);
+        assert_eq!(bilevel.get_pixel(3, 0).0, [0]);
+        assert_eq!(bilevel.get_pixel(4, 0).0, [255]);
+
+        let png = encode_png(&bilevel).unwrap();
+        let decoder = png::Decoder::new(std::io::Cursor::new(&png));
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().bit_depth, png::BitDepth::One);
+
+        let back = image::load_from_memory(&png).unwrap().to_luma8();
+        assert_eq!(back.dimensions(), (10, 2));
+        assert_eq!(back, bilevel);
+    }
+}
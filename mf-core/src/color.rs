@@ -0,0 +1,182 @@
+use std::{fmt, str::FromStr};
+
+use crate::error::InputError;
+
+/// Opaque RGB color, written as hex `RRGGBB` (optionally with a leading `#`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color {
+        r: 255,
+        g: 255,
+        b: 255,
+    };
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::WHITE
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl FromStr for Color {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(InputError::InvalidArgument(format!(
+                "invalid color '{s}' (expected hex RRGGBB, e.g. ffffff)"
+            )));
+        }
+        let channel =
+            |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).expect("checked hex digits");
+        Ok(Color {
+            r: channel(0),
+            g: channel(2),
+            b: channel(4),
+        })
+    }
+}
+
+/// Bits per channel of the written samples, `8` or `16`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u8", into = "u8")
+)]
+pub enum BitDepth {
+    /// Black and white, PNG only.
+    One,
+    Eight,
+    Sixteen,
+}
+
+impl TryFrom<u8> for BitDepth {
+    type Error = InputError;
+
+    fn try_from(bits: u8) -> Result<Self, Self::Error> {
+        match bits {
+            1 => Ok(BitDepth::One),
+            8 => Ok(BitDepth::Eight),
+            16 => Ok(BitDepth::Sixteen),
+            _ => Err(InputError::InvalidArgument(format!(
+                "invalid bit depth {bits} (use 1, 8 or 16)"
+            ))),
+        }
+    }
+}
+
+impl From<BitDepth> for u8 {
+    fn from(depth: BitDepth) -> u8 {
+        match depth {
+            BitDepth::One => 1,
+            BitDepth::Eight => 8,
+            BitDepth::Sixteen => 16,
+        }
+    }
+}
+
+impl fmt::Display for BitDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", u8::from(*self))
+    }
+}
+
+impl FromStr for BitDepth {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bits: u8 = s.trim().parse().map_err(|_| {
+            InputError::InvalidArgument(format!("invalid bit depth '{s}' (use 1, 8 or 16)"))
+        })?;
+        BitDepth::try_from(bits)
+    }
+}
+
+/// Channel layout of the written pixels: `rgb`, `rgba`, `gray` or `graya`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Channels {
+    Rgb,
+    Rgba,
+    Gray,
+    GrayA,
+}
+
+impl Channels {
+    pub fn has_alpha(self) -> bool {
+        matches!(self, Channels::Rgba | Channels::GrayA)
+    }
+
+    pub fn has_color(self) -> bool {
+        matches!(self, Channels::Rgb | Channels::Rgba)
+    }
+}
+
+impl fmt::Display for Channels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Channels::Rgb => "rgb",
+            Channels::Rgba => "rgba",
+            Channels::Gray => "gray",
+            Channels::GrayA => "graya",
+        })
+    }
+}
+
+impl FromStr for Channels {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "rgb" => Ok(Channels::Rgb),
+            "rgba" => Ok(Channels::Rgba),
+            "gray" | "grey" => Ok(Channels::Gray),
+            "graya" | "greya" => Ok(Channels::GrayA),
+            _ => Err(InputError::InvalidArgument(format!(
+                "invalid channels '{s}' (use rgb, rgba, gray or graya)"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_with_and_without_hash() {
+        let expected = Color {
+            r: 255,
+            g: 128,
+            b: 0,
+        };
+        assert_eq!("ff8000".parse::<Color>().unwrap(), expected);
+        assert_eq!("#FF8000".parse::<Color>().unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_malformed_hex() {
+        for bad in ["fff", "ff80001", "gg0000", ""] {
+            assert!(bad.parse::<Color>().is_err(), "{bad}");
+        }
+    }
+}
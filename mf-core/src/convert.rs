@@ -1,79 +1,1145 @@
 use std::{
-    fs::{self, File},
+    borrow::Cow,
+    fmt,
+    fs::{self, FileTimes},
     io,
     path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
-use image::{ImageError, ImageFormat};
+use image::{
+    codecs::jpeg::{JpegEncoder, PixelDensity, PixelDensityUnit},
+    metadata::Orientation,
+    DynamicImage, ImageDecoder, ImageFormat, ImageReader,
+};
 
 use crate::{
-    error::{ConversionError, FormatError, IoError, MeltforgeError},
+    analysis::looks_premultiplied,
+    bilevel::{self, DEFAULT_THRESHOLD},
+    color::BitDepth,
+    density::{set_png_density, Dpi},
+    error::{ConversionError, IoError, MeltforgeError},
     format::FormatType,
-    job::ConvertJob,
-    validate::{detect_input_format, validate_job},
+    fs_path::fs_path,
+    info::dimensions,
+    job::{ConvertJob, ConvertOptions, ConvertOutcome, ConvertStage, Timings},
+    jpeg::{encode_jpeg, insert_comment, ChromaSubsampling},
+    output::{frame_output_paths, job_output_path, next_free_path, ConflictPolicy},
+    registry,
+    transform::{apply_transforms, flatten, with_channels, DEFAULT_MAX_PIXELS},
+    validate::{
+        ensure_enabled, option_warnings, validate_compatibility, validate_conversion,
+        validate_input_format, validate_job, validate_options, validate_reencode,
+    },
+    warning::Warnings,
 };
 
-pub fn convert(cj: ConvertJob) -> Result<PathBuf, MeltforgeError> {
+pub fn convert(cj: ConvertJob) -> Result<ConvertOutcome, MeltforgeError> {
+    convert_with_cancel(cj, &AtomicBool::new(false))
+}
+
+/// [`convert`] that gives up with [`ConversionError::Cancelled`] once `cancel`
+/// is set. The flag is checked before each output is encoded and again before
+/// it is moved into place, so nothing is left half-written.
+pub fn convert_with_cancel(
+    cj: ConvertJob,
+    cancel: &AtomicBool,
+) -> Result<ConvertOutcome, MeltforgeError> {
+    convert_with_events(cj, cancel, &|_| {})
+}
+
+/// [`convert_with_cancel`] that calls `on_event` as each stage finishes, so
+/// a UI can follow a conversion without polling. Events arrive on the
+/// calling thread, in order; nothing is reported for a skipped output.
+pub fn convert_with_events(
+    cj: ConvertJob,
+    cancel: &AtomicBool,
+    on_event: &dyn Fn(ConvertStage),
+) -> Result<ConvertOutcome, MeltforgeError> {
+    let started = Instant::now();
+    let mut timings = Timings::default();
     validate_job(&cj)?; // Validate
 
-    let mut output_path = cj
-        .output
-        .clone()
-        .unwrap_or_else(|| derive_output_path(&cj.input, cj.format_type));
+    let output_path = job_output_path(&cj);
+    if cj.on_conflict == ConflictPolicy::Skip && fs_path(&output_path).exists() {
+        return Ok(skipped(vec![output_path], started));
+    }
 
+    // parent directories are only created on request, a typo should not scatter new dirs
     if let Some(parent) = output_path.parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent).map_err(|e| map_io_write(e, parent.to_path_buf()))?;
+        if cj.create_dirs && !parent.as_os_str().is_empty() && !fs_path(parent).exists() {
+            fs::create_dir_all(fs_path(parent))
+                .map_err(|e| map_io_write(e, parent.to_path_buf()))?;
         }
     }
-    let input_fmt = detect_input_format(&cj.input).map_err(|e| MeltforgeError::from(e))?;
-    match (input_fmt, cj.format_type) {
-        (FormatType::PNG, FormatType::JPEG) => convert_png_jpg(&cj.input, &output_path)?,
-        (FormatType::JPEG, FormatType::PNG) => convert_jpg_png(&cj.input, &output_path)?,
-        _ => {
-            return Err(FormatError::UnsupportedOutput(format!(
-                "{:?} → {:?} not supported yet",
-                input_fmt, cj.format_type
+    let input_fmt = validate_input_format(&cj)?;
+    validate_conversion(&cj, input_fmt)?; // will later be replaced with the plugin function
+    on_event(ConvertStage::Validated(cj.input.clone()));
+
+    // taken before decoding, which may bump the access time, and before an
+    // in-place write replaces the input. Unreadable ones fail a strict job
+    // here, before anything is written.
+    let mut warnings = Warnings::new(cj.strict);
+    let source_times = match cj
+        .preserve_timestamps
+        .then(|| fs::metadata(fs_path(&cj.input)).and_then(|m| file_times(&m)))
+    {
+        Some(Err(e)) => {
+            warnings.warn(format!(
+                "could not read timestamps of {}: {e}",
+                cj.input.display()
+            ))?;
+            None
+        }
+        times => times.and_then(Result::ok),
+    };
+    let source_permissions = match cj
+        .preserve_permissions
+        .then(|| fs::metadata(fs_path(&cj.input)).map(|m| m.permissions()))
+    {
+        Some(Err(e)) => {
+            warnings.warn(format!(
+                "could not read permissions of {}: {e}",
+                cj.input.display()
+            ))?;
+            None
+        }
+        permissions => permissions.and_then(Result::ok),
+    };
+
+    let decode_started = Instant::now();
+    let (frames, decode_warnings) = match oversized_input(&cj, input_fmt)? {
+        None => decode_frames(&cj, input_fmt)?,
+        Some((width, height)) if cj.retry_on_oom => decode_reduced(&cj, input_fmt, width, height)?,
+        Some((width, height)) => return Err(input_too_large(&cj, width, height)),
+    };
+    timings.decode = decode_started.elapsed();
+    on_event(ConvertStage::Decoded(cj.input.clone()));
+    warnings.warn_all(decode_warnings)?;
+    warnings.warn_all(option_warnings(&cj))?;
+    if cj.premultiply_check && frames.iter().any(looks_premultiplied) {
+        warnings.warn(format!(
+            "{} looks like it has premultiplied alpha, expect dark fringes around transparent edges",
+            cj.input.display()
+        ))?;
+    }
+
+    // animated inputs with --all-frames get one numbered output per frame
+    let outputs: Vec<PathBuf> = if frames.len() > 1 {
+        frame_output_paths(&cj, frames.len())
+    } else {
+        vec![output_path]
+    };
+    // the frame count is only known now, a set from an earlier run is skipped here
+    if frames.len() > 1
+        && cj.on_conflict == ConflictPolicy::Skip
+        && outputs.iter().any(|p| fs_path(p).exists())
+    {
+        return Ok(skipped(outputs, started));
+    }
+    if frames.len() > 1 && cj.on_conflict != ConflictPolicy::Overwrite {
+        if let Some(taken) = outputs.iter().find(|p| fs_path(p).exists()) {
+            return Err(IoError::AlreadyExists(taken.clone()).into());
+        }
+    }
+
+    let mut retries = Vec::new();
+    let pixels = frames
+        .iter()
+        .map(|f| f.width() as u64 * f.height() as u64)
+        .sum();
+    let decoded_bytes = frames.iter().map(|f| f.as_bytes().len() as u64).sum();
+    for (written, (img, path)) in frames.into_iter().zip(&outputs).enumerate() {
+        let result = if cancel.load(Ordering::Relaxed) {
+            Err(ConversionError::Cancelled.into())
+        } else {
+            write_image(
+                img,
+                &cj,
+                path,
+                &mut timings,
+                &mut warnings,
+                &mut retries,
+                Hooks { cancel, on_event },
+            )
+        };
+        // the frames are one output, a cancelled or failed one leaves none of them
+        if let Err(e) = result {
+            for path in &outputs[..written] {
+                let _ = fs::remove_file(fs_path(path));
+            }
+            return Err(e);
+        }
+    }
+    let preserved = preserve_metadata(
+        &outputs,
+        source_times,
+        source_permissions.as_ref(),
+        &mut warnings,
+    );
+    if let Err(e) = preserved {
+        // a strict job fails without outputs, unless one already replaced the input
+        if !cj.in_place {
+            for path in &outputs {
+                let _ = fs::remove_file(fs_path(path));
+            }
+        }
+        return Err(e);
+    }
+
+    timings.total = started.elapsed();
+    Ok(ConvertOutcome {
+        outputs,
+        warnings: warnings.into_vec(),
+        timings,
+        pixels,
+        decoded_bytes,
+        skipped: false,
+        retries,
+    }) // Respond
+}
+
+// Outcome of a job whose outputs already exist, under `ConflictPolicy::Skip`.
+fn skipped(outputs: Vec<PathBuf>, started: Instant) -> ConvertOutcome {
+    ConvertOutcome {
+        outputs,
+        warnings: Vec::new(),
+        timings: Timings {
+            total: started.elapsed(),
+            ..Timings::default()
+        },
+        pixels: 0,
+        decoded_bytes: 0,
+        skipped: true,
+        retries: Vec::new(),
+    }
+}
+
+const TIMEOUT_POLL: Duration = Duration::from_millis(50);
+
+enum WorkerMessage {
+    Stage(ConvertStage),
+    Done(Result<ConvertOutcome, MeltforgeError>),
+}
+
+/// [`convert_with_cancel`] on a worker thread that is abandoned with
+/// [`ConversionError::ExecutionFailed`] once `timeout` has passed.
+///
+/// Decoding cannot be interrupted, so an abandoned worker keeps running in
+/// the background until the current step is done; the timeout bounds the
+/// wait, not the CPU time. The abandoned job is cancelled as if by
+/// `cancel`, so it leaves no output behind. Setting `cancel` itself waits for
+/// the worker to stop.
+pub fn convert_with_timeout(
+    cj: ConvertJob,
+    timeout: Duration,
+    cancel: &AtomicBool,
+) -> Result<ConvertOutcome, MeltforgeError> {
+    convert_with_timeout_and_events(cj, timeout, cancel, &|_| {})
+}
+
+/// [`convert_with_timeout`] that passes events on to `on_event` on the
+/// calling thread, as with [`convert_with_events`].
+pub fn convert_with_timeout_and_events(
+    cj: ConvertJob,
+    timeout: Duration,
+    cancel: &AtomicBool,
+    on_event: &dyn Fn(ConvertStage),
+) -> Result<ConvertOutcome, MeltforgeError> {
+    let input = cj.input.clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    let worker_stop = Arc::clone(&stop);
+    thread::spawn(move || {
+        // the receiver is gone once the job timed out
+        let events = tx.clone();
+        let report = move |stage| {
+            let _ = events.send(WorkerMessage::Stage(stage));
+        };
+        let result = convert_with_events(cj, &worker_stop, &report);
+        let _ = tx.send(WorkerMessage::Done(result));
+    });
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            stop.store(true, Ordering::Relaxed);
+            // later stages are dropped, the caller asked to stop
+            return rx
+                .iter()
+                .find_map(|m| match m {
+                    WorkerMessage::Done(result) => Some(result),
+                    WorkerMessage::Stage(_) => None,
+                })
+                .unwrap_or_else(|| Err(ConversionError::Cancelled.into()));
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            stop.store(true, Ordering::Relaxed);
+            return Err(ConversionError::ExecutionFailed(format!(
+                "{}: timed out after {:.1}s",
+                input.display(),
+                timeout.as_secs_f64()
             ))
             .into());
         }
-    } // Convert currently only png  to jpg will later be replaced with the plugin function
+        match rx.recv_timeout(left.min(TIMEOUT_POLL)) {
+            Ok(WorkerMessage::Stage(stage)) => on_event(stage),
+            Ok(WorkerMessage::Done(result)) => return result,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(ConversionError::ExecutionFailed(format!(
+                    "{}: conversion panicked",
+                    input.display()
+                ))
+                .into());
+            }
+        }
+    }
+}
+
+/// Transforms and encodes an already decoded image, for callers that do not
+/// read from a file. Transform warnings are dropped, but options `to` cannot
+/// honor are rejected as for a job.
+pub fn encode_image(
+    img: &DynamicImage,
+    to: FormatType,
+    opts: &ConvertOptions,
+) -> Result<Vec<u8>, MeltforgeError> {
+    ensure_enabled(to)?;
+    validate_options(opts, to)?;
+    let img = apply_transforms(
+        Cow::Borrowed(img),
+        opts,
+        &mut Timings::default(),
+        &mut Vec::new(),
+    )?;
+    let bytes = encode_with_options(&img, to, opts).map_err(|e| ConversionError::image(to, e))?;
+    Ok(bytes)
+}
+
+/// Converts an encoded image held in memory, for callers that never touch the
+/// filesystem. `from` may equal `to` to only re-encode or transform. Inputs
+/// declaring more than [`DEFAULT_MAX_PIXELS`] are rejected before decoding.
+pub fn convert_in_memory(
+    data: &[u8],
+    from: FormatType,
+    to: FormatType,
+    opts: &ConvertOptions,
+) -> Result<Vec<u8>, MeltforgeError> {
+    convert_in_memory_with_limit(data, from, to, opts, DEFAULT_MAX_PIXELS)
+}
+
+/// [`convert_in_memory`] with the pixel limit of a job, `--max-pixels`.
+pub fn convert_in_memory_with_limit(
+    data: &[u8],
+    from: FormatType,
+    to: FormatType,
+    opts: &ConvertOptions,
+    max_pixels: u64,
+) -> Result<Vec<u8>, MeltforgeError> {
+    if from == to {
+        validate_reencode(from)?;
+    } else {
+        validate_compatibility(from, to)?;
+    }
+    // checked again by `encode_image`, but should fail before decoding
+    validate_options(opts, to)?;
+    #[cfg(feature = "heif")]
+    if from == FormatType::HEIF {
+        let ctx = libheif_rs::HeifContext::read_from_bytes(data)
+            .map_err(|e| crate::heif::open_error("input data", e))?;
+        let (width, height) =
+            crate::heif::dimensions(&ctx).map_err(|e| crate::heif::open_error("input data", e))?;
+        check_data_size(width, height, max_pixels)?;
+        let img = crate::heif::decode_primary(&ctx)
+            .map_err(|e| crate::heif::open_error("input data", e))?;
+        return encode_image(&img, to, opts);
+    }
+    if let FormatType::Custom(name) = from {
+        let (width, height) = registry::dimensions(name, data)
+            .map_err(|e| ConversionError::image("input data", e))?;
+        check_data_size(width, height, max_pixels)?;
+        let img =
+            registry::decode(name, data).map_err(|e| ConversionError::image("input data", e))?;
+        return encode_image(&img, to, opts);
+    }
+    #[cfg(feature = "jxl")]
+    if from == FormatType::JXL {
+        let (width, height) =
+            crate::jxl::dimensions(data).map_err(|e| ConversionError::image("input data", e))?;
+        check_data_size(width, height, max_pixels)?;
+        let img = crate::jxl::decode(data).map_err(|e| ConversionError::image("input data", e))?;
+        return encode_image(&img, to, opts);
+    }
+    // untrusted uploads land here, so a forged header must not get to allocate
+    let mut header = ImageReader::new(io::Cursor::new(data));
+    header.set_format(
+        from.image_format()
+            .expect("HEIF, JPEG XL and custom formats are decoded separately"),
+    );
+    let (width, height) = header
+        .into_dimensions()
+        .map_err(|e| ConversionError::image("input data", e))?;
+    check_data_size(width, height, max_pixels)?;
+    let reader = ImageReader::new(io::Cursor::new(data));
+    let orient = opts.auto_orient && from == FormatType::JPEG;
+    let img =
+        decode_reader(reader, from, orient).map_err(|e| ConversionError::image("input data", e))?;
+    encode_image(&img, to, opts)
+}
+
+// The header size of in-memory input against the pixel limit, checked before
+// decoding whatever the format.
+fn check_data_size(width: u32, height: u32, max_pixels: u64) -> Result<(), ConversionError> {
+    if width as u64 * height as u64 > max_pixels {
+        return Err(ConversionError::ExecutionFailed(format!(
+            "input data is {width}x{height}, more than the limit of {max_pixels} pixels"
+        )));
+    }
+    Ok(())
+}
 
-    Ok(output_path) // Respond
+// What the caller of a conversion hands down to each output write.
+#[derive(Clone, Copy)]
+struct Hooks<'a> {
+    cancel: &'a AtomicBool,
+    on_event: &'a dyn Fn(ConvertStage),
 }
 
-fn convert_png_jpg(input: &Path, output: &Path) -> Result<(), MeltforgeError> {
-    let img = image::open(input)
-        .map_err(|e| ConversionError::ExecutionFailed(format!("open {}: {e}", input.display())))?;
+fn write_image(
+    img: DynamicImage,
+    cj: &ConvertJob,
+    path: &Path,
+    timings: &mut Timings,
+    warnings: &mut Warnings,
+    retries: &mut Vec<String>,
+    hooks: Hooks<'_>,
+) -> Result<(), MeltforgeError> {
+    let opts = &cj.options;
+    let mut transform_warnings = Vec::new();
+    let img = apply_transforms(Cow::Owned(img), opts, timings, &mut transform_warnings)?;
+    warnings.warn_all(
+        transform_warnings
+            .into_iter()
+            .map(|w| format!("{} {w}", cj.input.display())),
+    )?;
+    (hooks.on_event)(ConvertStage::Transformed(path.to_path_buf()));
 
-    img.save_with_format(output, ImageFormat::Jpeg)
-        .map_err(|e| {
-            ConversionError::OutputWriteFailed(format!("save {}: {e}", output.display()))
-        })?;
+    let encode_started = Instant::now();
+    let bytes = encode_with_options(&img, cj.format_type, opts)
+        .map_err(|e| ConversionError::image(path.display(), e))?;
+    timings.encode += encode_started.elapsed();
+    (hooks.on_event)(ConvertStage::Encoded(path.to_path_buf()));
 
+    let write_started = Instant::now();
+    // written next to the destination and renamed into place, so `path` never
+    // holds a partial image and a failed in-place write leaves the original intact
+    let temp = TempOutput::new(path);
+    with_write_retries(cj.write_retries, retries, || {
+        fs::write(fs_path(&temp.0), &bytes).map_err(|e| map_io_write(e, temp.0.clone()))
+    })?;
+    if cj.verify {
+        // an ICO decodes to its largest icon, not to the source size
+        let (width, height) = match cj.format_type {
+            FormatType::ICO => {
+                let largest = cj.options.ico_sizes.0.iter().copied().max().unwrap_or(0);
+                (largest, largest)
+            }
+            _ => (img.width(), img.height()),
+        };
+        verify_output(&temp.0, path, cj.format_type, width, height)?;
+    }
+    // the temp file is removed on return, the destination is never touched
+    if hooks.cancel.load(Ordering::Relaxed) {
+        return Err(ConversionError::Cancelled.into());
+    }
+    with_write_retries(cj.write_retries, retries, || {
+        replace_file(&temp.0, path).map_err(|e| map_io_write(e, path.to_path_buf()))
+    })?;
+    timings.write += write_started.elapsed();
+    (hooks.on_event)(ConvertStage::Written(path.to_path_buf()));
     Ok(())
 }
 
-fn convert_jpg_png(input: &Path, output: &Path) -> Result<(), MeltforgeError> {
-    let img = image::open(input)
-        .map_err(|e| ConversionError::ExecutionFailed(format!("open {}: {e}", input.display())))?;
+/// Writes already encoded `bytes` to `path` the way conversions write their
+/// outputs: through a temporary sibling renamed into place, so `path` never
+/// holds a partial file. An existing `path` is handled by `on_conflict`, and
+/// missing parent directories are only created with `create_dirs`.
+///
+/// Returns the path written, which differs from `path` after a
+/// [`ConflictPolicy::Rename`], or `None` if it was skipped.
+pub fn write_output(
+    bytes: &[u8],
+    path: &Path,
+    on_conflict: ConflictPolicy,
+    create_dirs: bool,
+) -> Result<Option<PathBuf>, MeltforgeError> {
+    let path = match on_conflict {
+        _ if !fs_path(path).exists() => path.to_path_buf(),
+        ConflictPolicy::Fail => return Err(IoError::AlreadyExists(path.to_path_buf()).into()),
+        ConflictPolicy::Skip => return Ok(None),
+        ConflictPolicy::Rename => next_free_path(path.to_path_buf()),
+        ConflictPolicy::Overwrite => path.to_path_buf(),
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if create_dirs && !fs_path(parent).exists() {
+            fs::create_dir_all(fs_path(parent))
+                .map_err(|e| map_io_write(e, parent.to_path_buf()))?;
+        }
+    }
+    let temp = TempOutput::new(&path);
+    fs::write(fs_path(&temp.0), bytes).map_err(|e| map_io_write(e, temp.0.clone()))?;
+    replace_file(&temp.0, &path).map_err(|e| map_io_write(e, path.clone()))?;
+    Ok(Some(path))
+}
+
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(50);
 
-    img.save_with_format(output, ImageFormat::Png)
-        .map_err(|e| {
-            ConversionError::OutputWriteFailed(format!("save {}: {e}", output.display()))
-        })?;
+// Network filesystems occasionally fail a write that succeeds moments later.
+// Only `IoError::WriteError` is transient; permission and existence errors
+// would fail the same way again.
+fn with_write_retries(
+    max_retries: u32,
+    retries: &mut Vec<String>,
+    mut write: impl FnMut() -> Result<(), MeltforgeError>,
+) -> Result<(), MeltforgeError> {
+    let mut attempt = 0;
+    loop {
+        match write() {
+            Err(MeltforgeError::Io(e @ IoError::WriteError(_))) if attempt < max_retries => {
+                let delay = RETRY_BASE_DELAY * 2u32.pow(attempt.min(6));
+                attempt += 1;
+                retries.push(format!(
+                    "{e}, retry {attempt} of {max_retries} in {}ms",
+                    delay.as_millis()
+                ));
+                std::thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
 
+// The conversion itself succeeded, so a filesystem without settable times or
+// modes only warns.
+fn preserve_metadata(
+    outputs: &[PathBuf],
+    times: Option<FileTimes>,
+    permissions: Option<&fs::Permissions>,
+    warnings: &mut Warnings,
+) -> Result<(), MeltforgeError> {
+    for path in outputs {
+        if let Some(times) = times {
+            let applied = fs::File::options()
+                .write(true)
+                .open(fs_path(path))
+                .and_then(|f| f.set_times(times));
+            if let Err(e) = applied {
+                warnings.warn(format!(
+                    "could not preserve timestamps on {}: {e}",
+                    path.display()
+                ))?;
+            }
+        }
+        // applied after the timestamps, which need the output to be writable
+        if let Some(permissions) = permissions {
+            if let Err(e) = copy_mode(path, permissions) {
+                warnings.warn(format!(
+                    "could not preserve permissions on {}: {e}",
+                    path.display()
+                ))?;
+            }
+        }
+    }
     Ok(())
 }
 
-fn derive_output_path(input: &Path, to: FormatType) -> PathBuf {
-    let mut p = input.to_path_buf();
-    let ext = match to {
-        FormatType::JPEG => "jpg",
-        FormatType::PNG => "png",
+#[cfg(unix)]
+fn copy_mode(path: &Path, source: &fs::Permissions) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(fs_path(path), fs::Permissions::from_mode(source.mode()))
+}
+
+// Windows only has a read-only flag, outputs keep their default
+#[cfg(not(unix))]
+fn copy_mode(_path: &Path, _source: &fs::Permissions) -> io::Result<()> {
+    Ok(())
+}
+
+fn file_times(meta: &fs::Metadata) -> io::Result<FileTimes> {
+    Ok(FileTimes::new()
+        .set_accessed(meta.accessed()?)
+        .set_modified(meta.modified()?))
+}
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Temporary sibling of an output, removed on drop unless it was renamed into
+// place, so an error between writing and renaming leaves nothing behind. Like
+// the write probe, the name is unique per process and call, so two writers of
+// one output never share or remove each other's temp file.
+struct TempOutput(PathBuf);
+
+impl TempOutput {
+    fn new(path: &Path) -> Self {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        TempOutput(path.with_file_name(format!(".{name}.{}.{n}.meltforge-tmp", process::id())))
+    }
+}
+
+impl Drop for TempOutput {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(fs_path(&self.0));
+    }
+}
+
+// Atomic rename where possible. Copying is the fallback for a temp file that
+// ended up on another filesystem (e.g. a bind mount over the input).
+fn replace_file(temp: &Path, path: &Path) -> io::Result<()> {
+    let (temp, path) = (fs_path(temp), fs_path(path));
+    match fs::rename(&temp, &path) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let copied = fs::copy(&temp, &path).map(|_| ());
+            let _ = fs::remove_file(&temp);
+            copied
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+// Re-decodes the written file as `format` before it is moved to `output`; a
+// broken one is dropped with its temp file so it cannot pass as a result.
+// Targets without a decoder never get here, `validate_job` rejects them.
+fn verify_output(
+    written: &Path,
+    output: &Path,
+    format: FormatType,
+    width: u32,
+    height: u32,
+) -> Result<(), MeltforgeError> {
+    let written = fs::read(fs_path(written))
+        .map_err(image::ImageError::IoError)
+        .and_then(|data| match format {
+            FormatType::Custom(name) => registry::decode(name, &data),
+            #[cfg(feature = "jxl")]
+            FormatType::JXL => crate::jxl::decode(&data),
+            _ => ImageReader::with_format(
+                io::Cursor::new(data),
+                format
+                    .image_format()
+                    .expect("JPEG XL and custom formats are decoded separately"),
+            )
+            .decode(),
+        });
+    let problem = match written {
+        Ok(written) if (written.width(), written.height()) == (width, height) => return Ok(()),
+        Ok(written) => format!(
+            "expected {width}x{height}, got {}x{}",
+            written.width(),
+            written.height()
+        ),
+        Err(e) => format!("decode failed: {e}"),
+    };
+    Err(
+        ConversionError::OutputWriteFailed(format!("verify {}: {problem}", output.display()))
+            .into(),
+    )
+}
+
+// Reads only the header, so oversized inputs fail before their pixel buffer is allocated.
+// Returns the input dimensions if they exceed `max_pixels`.
+fn oversized_input(
+    cj: &ConvertJob,
+    input_fmt: FormatType,
+) -> Result<Option<(u32, u32)>, MeltforgeError> {
+    let (width, height) = dimensions(&cj.input, input_fmt)?;
+    let too_large = width as u64 * height as u64 > cj.max_pixels;
+    Ok(too_large.then_some((width, height)))
+}
+
+// The header only covers one frame, so an animation of many small frames
+// could still exhaust memory. The frames decoded so far count against
+// `max_pixels` and decoding stops once they exceed it.
+#[cfg(any(feature = "gif", feature = "webp"))]
+fn frames_within_limit(
+    cj: &ConvertJob,
+    frames: impl Iterator<Item = image::ImageResult<DynamicImage>>,
+) -> Result<Vec<DynamicImage>, MeltforgeError> {
+    let mut decoded = Vec::new();
+    let mut pixels = 0u64;
+    for frame in frames {
+        let frame = frame.map_err(|e| ConversionError::image(cj.input.display(), e))?;
+        pixels += frame.width() as u64 * frame.height() as u64;
+        if pixels > cj.max_pixels {
+            return Err(ConversionError::ExecutionFailed(format!(
+                "{}: its first {} frames hold more than the limit of {} pixels (see --max-pixels)",
+                cj.input.display(),
+                decoded.len() + 1,
+                cj.max_pixels
+            ))
+            .into());
+        }
+        decoded.push(frame);
+    }
+    Ok(decoded)
+}
+
+fn input_too_large(cj: &ConvertJob, width: u32, height: u32) -> MeltforgeError {
+    ConversionError::ExecutionFailed(format!(
+        "{} is {width}x{height} ({} pixels), more than the limit of {} (see --max-pixels)",
+        cj.input.display(),
+        width as u64 * height as u64,
+        cj.max_pixels
+    ))
+    .into()
+}
+
+/// Recovery for `retry_on_oom`: decodes an input over `max_pixels` at a
+/// reduced size instead of rejecting it.
+///
+/// Only JPEG supports this. Its DCT can decode directly at 1/2, 1/4 or 1/8
+/// scale without ever holding the full-size image, and the largest of those
+/// that fits the limit is used. Other formats cannot be decoded partially or
+/// in tiles through `image`, so they fail just as they would without the retry.
+fn decode_reduced(
+    cj: &ConvertJob,
+    input_fmt: FormatType,
+    width: u32,
+    height: u32,
+) -> Result<Frames, MeltforgeError> {
+    if input_fmt != FormatType::JPEG {
+        return Err(input_too_large(cj, width, height));
+    }
+    let reduced = |d: u32| (width.div_ceil(d), height.div_ceil(d));
+    let Some(denom) = [2, 4, 8].into_iter().find(|&d| {
+        let (w, h) = reduced(d);
+        w as u64 * h as u64 <= cj.max_pixels
+    }) else {
+        return Err(input_too_large(cj, width, height));
+    };
+
+    let (w, h) = reduced(denom);
+    let img = decode_jpeg(cj, Some((w, h)))?.ok_or_else(|| input_too_large(cj, width, height))?;
+
+    let warning = format!(
+        "{} is {width}x{height}, over the pixel limit, decoded at 1/{denom} size ({w}x{h})",
+        cj.input.display()
+    );
+    Ok((vec![img], vec![warning]))
+}
+
+/// Decodes a JPEG with jpeg-decoder, which unlike the `image` decoder can
+/// decode at a reduced `size` and spread the work over `decode_threads`.
+/// Returns `None` for pixel layouts `DynamicImage` has no match for (CMYK,
+/// 16-bit gray), before decoding anything.
+fn decode_jpeg(
+    cj: &ConvertJob,
+    size: Option<(u32, u32)>,
+) -> Result<Option<DynamicImage>, MeltforgeError> {
+    use jpeg_decoder::{Decoder, PixelFormat};
+
+    let open_err = |e| match e {
+        jpeg_decoder::Error::Format(_) | jpeg_decoder::Error::Io(_) => {
+            corrupt_input(cj.input.display(), e)
+        }
+        _ => ConversionError::ExecutionFailed(format!("open {}: {e}", cj.input.display())),
+    };
+    let file =
+        fs::File::open(fs_path(&cj.input)).map_err(|_| IoError::ReadError(cj.input.clone()))?;
+    let mut decoder = Decoder::new(io::BufReader::new(file));
+    decoder.read_info().map_err(open_err)?;
+    let pixel_format = decoder.info().expect("header was read").pixel_format;
+    if !matches!(pixel_format, PixelFormat::RGB24 | PixelFormat::L8) {
+        return Ok(None);
+    }
+    if let Some((w, h)) = size {
+        // JPEG dimensions always fit in u16
+        decoder.scale(w as u16, h as u16).map_err(open_err)?;
+    }
+
+    let pixels = match cj.decode_threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| ConversionError::ExecutionFailed(format!("decode threads: {e}")))?
+            .install(|| decoder.decode()),
+        None => decoder.decode(),
+    }
+    .map_err(open_err)?;
+    let info = decoder.info().expect("header was read");
+    let (w, h) = (info.width as u32, info.height as u32);
+
+    let mut img = match pixel_format {
+        PixelFormat::RGB24 => image::RgbImage::from_raw(w, h, pixels).map(DynamicImage::ImageRgb8),
+        _ => image::GrayImage::from_raw(w, h, pixels).map(DynamicImage::ImageLuma8),
+    }
+    .ok_or_else(|| {
+        ConversionError::ExecutionFailed(format!("open {}: short pixel data", cj.input.display()))
+    })?;
+    if cj.options.auto_orient {
+        if let Some(orientation) = decoder.exif_data().and_then(Orientation::from_exif_chunk) {
+            img.apply_orientation(orientation);
+        }
+    }
+    Ok(Some(img))
+}
+
+fn encode_with_options(
+    img: &DynamicImage,
+    to: FormatType,
+    opts: &ConvertOptions,
+) -> image::ImageResult<Vec<u8>> {
+    let dpi = match opts.dpi {
+        Some(dpi) => Some(Dpi { x: dpi, y: dpi }),
+        None => opts
+            .print_size
+            .map(|ps| ps.dpi_for(img.width(), img.height())),
+    };
+    if opts.bit_depth == Some(BitDepth::One) {
+        // validation only lets this through for PNG
+        let threshold = opts.threshold.unwrap_or(DEFAULT_THRESHOLD);
+        let mut buf = bilevel::encode_png(&bilevel::binarize(img, threshold, opts.background))?;
+        if let Some(dpi) = dpi {
+            set_png_density(&mut buf, dpi);
+        }
+        return Ok(buf);
+    }
+    let img = match opts.channels {
+        Some(channels) => &with_channels(img, channels, opts.background),
+        None => img,
+    };
+    // without this, formats lacking alpha would just drop it and expose whatever
+    // color the transparent pixels happen to store
+    if img.color().has_alpha() && !to.supports_alpha() {
+        let flat = flatten(img, opts.background);
+        return encode(&encodable_color(&flat, to, opts), to, opts, dpi);
+    }
+    encode(&encodable_color(img, to, opts), to, opts, dpi)
+}
+
+// Keeps the decoded color type where the target's encoder can write it, so
+// grayscale or 16-bit inputs are not needlessly widened to RGBA8. A set
+// `bit_depth` then picks the sample size, validated against the target.
+fn encodable_color<'a>(
+    img: &'a DynamicImage,
+    to: FormatType,
+    opts: &ConvertOptions,
+) -> Cow<'a, DynamicImage> {
+    use image::ColorType as C;
+
+    let color = img.color();
+    let target = if opts.force_rgba && opts.channels.is_none() {
+        if to.supports_alpha() {
+            C::Rgba8
+        } else {
+            C::Rgb8
+        }
+    } else {
+        match (to, color) {
+            (FormatType::JPEG, C::L8 | C::Rgb8) => color,
+            (FormatType::JPEG, C::L16) => C::L8,
+            (FormatType::JPEG, _) => C::Rgb8,
+            (FormatType::WEBP, C::L8 | C::La8 | C::Rgb8 | C::Rgba8) => color,
+            (FormatType::WEBP, C::L16) => C::L8,
+            (FormatType::WEBP, C::La16) => C::La8,
+            (FormatType::WEBP, _) if color.has_alpha() => C::Rgba8,
+            (FormatType::WEBP, _) => C::Rgb8,
+            // PGM and PPM as written here are 8-bit only
+            (FormatType::PNM, C::L8 | C::Rgb8) => color,
+            (FormatType::PNM, C::L16) => C::L8,
+            (FormatType::PNM, _) => C::Rgb8,
+            // PNG and TIFF store up to 16 bits per channel, but no floats
+            (FormatType::PNG | FormatType::TIFF, C::Rgb32F) => C::Rgb16,
+            (FormatType::PNG | FormatType::TIFF, C::Rgba32F) => C::Rgba16,
+            _ => color,
+        }
+    };
+    let target = match (opts.bit_depth, target) {
+        (Some(BitDepth::Eight), C::L16) => C::L8,
+        (Some(BitDepth::Eight), C::La16) => C::La8,
+        (Some(BitDepth::Eight), C::Rgb16 | C::Rgb32F) => C::Rgb8,
+        (Some(BitDepth::Eight), C::Rgba16 | C::Rgba32F) => C::Rgba8,
+        (Some(BitDepth::Sixteen), C::L8) => C::L16,
+        (Some(BitDepth::Sixteen), C::La8) => C::La16,
+        (Some(BitDepth::Sixteen), C::Rgb8 | C::Rgb32F) => C::Rgb16,
+        (Some(BitDepth::Sixteen), C::Rgba8 | C::Rgba32F) => C::Rgba16,
+        _ => target,
+    };
+    if target == color {
+        return Cow::Borrowed(img);
+    }
+    Cow::Owned(match target {
+        C::L8 => DynamicImage::ImageLuma8(img.to_luma8()),
+        C::La8 => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+        C::Rgb8 => DynamicImage::ImageRgb8(img.to_rgb8()),
+        C::Rgba8 => DynamicImage::ImageRgba8(img.to_rgba8()),
+        C::L16 => DynamicImage::ImageLuma16(img.to_luma16()),
+        C::La16 => DynamicImage::ImageLumaA16(img.to_luma_alpha16()),
+        C::Rgb16 => DynamicImage::ImageRgb16(img.to_rgb16()),
+        _ => DynamicImage::ImageRgba16(img.to_rgba16()),
+    })
+}
+
+type Frames = (Vec<DynamicImage>, Vec<String>);
+
+// Decodes the input into one image, or one image per frame for animated
+// inputs converted with `all_frames`. Also returns decode warnings.
+pub(crate) fn decode_frames(
+    cj: &ConvertJob,
+    input_fmt: FormatType,
+) -> Result<Frames, MeltforgeError> {
+    let mut warnings = Vec::new();
+    if cj.decode_threads.is_some() {
+        if input_fmt == FormatType::JPEG {
+            if let Some(img) = decode_jpeg(cj, None)? {
+                return Ok((vec![img], warnings));
+            }
+        } else {
+            // no path in it, so a batch can show it once
+            warnings.push(format!(
+                "--decode-threads only applies to JPEG inputs, decoding {input_fmt} single-threaded"
+            ));
+        }
+    }
+    #[cfg(feature = "heif")]
+    if input_fmt == FormatType::HEIF {
+        let ctx = crate::heif::read_file(&cj.input)?;
+        let img = crate::heif::decode_primary(&ctx)
+            .map_err(|e| crate::heif::open_error(cj.input.display(), e))?;
+        return Ok((vec![img], warnings));
+    }
+    #[cfg(feature = "webp")]
+    if input_fmt == FormatType::WEBP {
+        let (frames, webp_warnings) = decode_webp_frames(cj)?;
+        warnings.extend(webp_warnings);
+        return Ok((frames, warnings));
+    }
+    #[cfg(feature = "gif")]
+    if input_fmt == FormatType::GIF {
+        let (frames, gif_warnings) = decode_gif_frames(cj)?;
+        warnings.extend(gif_warnings);
+        return Ok((frames, warnings));
+    }
+    let orient = cj.options.auto_orient && input_fmt == FormatType::JPEG;
+    Ok((vec![decode(&cj.input, input_fmt, orient)?], warnings))
+}
+
+#[cfg(feature = "webp")]
+fn decode_webp_frames(cj: &ConvertJob) -> Result<Frames, MeltforgeError> {
+    use image::{codecs::webp::WebPDecoder, AnimationDecoder};
+
+    let open_err = |e| ConversionError::image(cj.input.display(), e);
+    let file =
+        fs::File::open(fs_path(&cj.input)).map_err(|_| IoError::ReadError(cj.input.clone()))?;
+    let decoder = WebPDecoder::new(io::BufReader::new(file)).map_err(open_err)?;
+
+    let mut warnings = Vec::new();
+    if decoder.has_animation() {
+        if cj.all_frames {
+            let frames = decoder
+                .into_frames()
+                .map(|f| f.map(|f| DynamicImage::ImageRgba8(f.into_buffer())));
+            return Ok((frames_within_limit(cj, frames)?, warnings));
+        }
+        warnings.push(format!(
+            "{} is animated, only the first frame was converted (use --all-frames)",
+            cj.input.display()
+        ));
+    }
+    let first = DynamicImage::from_decoder(decoder).map_err(open_err)?;
+    Ok((vec![first], warnings))
+}
+
+// Unlike WebP, a GIF header does not say whether it is animated, so a second
+// frame is decoded to find out.
+#[cfg(feature = "gif")]
+fn decode_gif_frames(cj: &ConvertJob) -> Result<Frames, MeltforgeError> {
+    use image::{codecs::gif::GifDecoder, AnimationDecoder};
+
+    let open_err = |e| ConversionError::image(cj.input.display(), e);
+    let file =
+        fs::File::open(fs_path(&cj.input)).map_err(|_| IoError::ReadError(cj.input.clone()))?;
+    let decoder = GifDecoder::new(io::BufReader::new(file)).map_err(open_err)?;
+    let mut frames = decoder
+        .into_frames()
+        .map(|f| f.map(|f| DynamicImage::ImageRgba8(f.into_buffer())));
+
+    if cj.all_frames {
+        let frames = frames_within_limit(cj, frames)?;
+        if frames.is_empty() {
+            return Err(corrupt_input(cj.input.display(), "no frames").into());
+        }
+        return Ok((frames, Vec::new()));
+    }
+    let first = match frames.next() {
+        Some(frame) => frame.map_err(open_err)?,
+        None => return Err(corrupt_input(cj.input.display(), "no frames").into()),
+    };
+    let mut warnings = Vec::new();
+    if frames.next().is_some() {
+        warnings.push(format!(
+            "{} is animated, only the first frame was converted (use --all-frames)",
+            cj.input.display()
+        ));
+    }
+    Ok((vec![first], warnings))
+}
+
+// The format is passed explicitly so inputs without a matching extension decode too.
+// With `orient`, the EXIF orientation is baked into the pixels. The tag is
+// dropped along with the rest of the metadata, as no encoder writes EXIF.
+fn decode(input: &Path, fmt: FormatType, orient: bool) -> Result<DynamicImage, MeltforgeError> {
+    if let FormatType::Custom(name) = fmt {
+        let data = fs::read(fs_path(input)).map_err(|_| IoError::ReadError(input.to_path_buf()))?;
+        let img = registry::decode(name, &data)
+            .map_err(|e| ConversionError::image(input.display(), e))?;
+        return Ok(img);
+    }
+    #[cfg(feature = "jxl")]
+    if fmt == FormatType::JXL {
+        let data = fs::read(fs_path(input)).map_err(|_| IoError::ReadError(input.to_path_buf()))?;
+        let img =
+            crate::jxl::decode(&data).map_err(|e| ConversionError::image(input.display(), e))?;
+        return Ok(img);
+    }
+    let reader =
+        ImageReader::open(fs_path(input)).map_err(|_| IoError::ReadError(input.to_path_buf()))?;
+    let img = decode_reader(reader, fmt, orient)
+        .map_err(|e| ConversionError::image(input.display(), e))?;
+    Ok(img)
+}
+
+fn decode_reader<R: io::BufRead + io::Seek>(
+    mut reader: ImageReader<R>,
+    fmt: FormatType,
+    orient: bool,
+) -> image::ImageResult<DynamicImage> {
+    reader.set_format(
+        fmt.image_format()
+            .expect("HEIF, JPEG XL and custom formats are decoded separately"),
+    );
+    let mut decoder = reader.into_decoder()?;
+    let orientation = if orient {
+        decoder.orientation()?
+    } else {
+        Orientation::NoTransforms
     };
-    p.set_extension(ext);
-    p
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+fn corrupt_input(input: impl fmt::Display, e: impl fmt::Display) -> ConversionError {
+    ConversionError::ExecutionFailed(format!("{input}: file appears corrupt or truncated ({e})"))
+}
+
+fn encode(
+    img: &DynamicImage,
+    to: FormatType,
+    opts: &ConvertOptions,
+    dpi: Option<Dpi>,
+) -> image::ImageResult<Vec<u8>> {
+    // lossless formats have no default and never read it
+    let quality = opts.quality.or(to.default_quality()).unwrap_or(100);
+    let mut buf = Vec::new();
+    match to {
+        // image's encoder has no subsampling setting, it always writes
+        // baseline 4:4:4
+        FormatType::JPEG if opts.subsampling.is_some() || opts.progressive => {
+            let subsampling = opts.subsampling.unwrap_or(ChromaSubsampling::S444);
+            buf = encode_jpeg(img, quality, subsampling, opts.progressive, dpi)?;
+        }
+        FormatType::JPEG => {
+            let mut encoder = JpegEncoder::new_with_quality(&mut buf, quality);
+            if let Some(dpi) = dpi {
+                encoder.set_pixel_density(PixelDensity {
+                    density: (dpi.x, dpi.y),
+                    unit: PixelDensityUnit::Inches,
+                });
+            }
+            img.write_with_encoder(encoder)?;
+        }
+        FormatType::PNG => {
+            img.write_to(io::Cursor::new(&mut buf), ImageFormat::Png)?;
+            if let Some(dpi) = dpi {
+                set_png_density(&mut buf, dpi);
+            }
+        }
+        #[cfg(feature = "avif")]
+        FormatType::AVIF => {
+            use image::codecs::avif::AvifEncoder;
+            // how rav1e splits the work depends on the thread count
+            let threads = opts.reproducible.then_some(1);
+            let encoder =
+                AvifEncoder::new_with_speed_quality(&mut buf, 4, quality).with_num_threads(threads);
+            img.write_with_encoder(encoder)?;
+        }
+        #[cfg(feature = "webp")]
+        FormatType::WEBP => {
+            use image::codecs::webp::WebPEncoder;
+            // image only has a lossless WebP encoder, so `webp_lossless` is
+            // what every WebP output gets
+            img.write_with_encoder(WebPEncoder::new_lossless(&mut buf))?;
+        }
+        #[cfg(feature = "tiff")]
+        FormatType::TIFF => {
+            img.write_to(io::Cursor::new(&mut buf), ImageFormat::Tiff)?;
+        }
+        #[cfg(feature = "pnm")]
+        FormatType::PNM => {
+            use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+            // without a subtype the encoder writes PAM, which many PNM readers reject
+            let subtype = match img.color() {
+                image::ColorType::L8 => PnmSubtype::Graymap(SampleEncoding::Binary),
+                _ => PnmSubtype::Pixmap(SampleEncoding::Binary),
+            };
+            img.write_with_encoder(PnmEncoder::new(&mut buf).with_subtype(subtype))?;
+        }
+        #[cfg(feature = "ico")]
+        FormatType::ICO => {
+            buf = crate::ico::encode_ico(img, &opts.ico_sizes)?;
+        }
+        #[cfg(feature = "jxl")]
+        FormatType::JXL => {
+            buf = crate::jxl::encode(img, quality, opts.jxl_lossless)?;
+        }
+        FormatType::Custom(name) => {
+            buf = registry::encode(name, img)?;
+        }
+        // disabled formats never get past validate_compatibility
+        #[allow(unreachable_patterns)]
+        _ => {
+            return Err(image::ImageError::Unsupported(
+                image::error::UnsupportedError::from_format_and_kind(
+                    image::error::ImageFormatHint::Name(to.name().into()),
+                    image::error::UnsupportedErrorKind::Format(
+                        image::error::ImageFormatHint::Name(to.name().into()),
+                    ),
+                ),
+            ))
+        }
+    }
+    if let Some(comment) = opts
+        .jpeg_comment
+        .as_deref()
+        .filter(|_| to == FormatType::JPEG)
+    {
+        insert_comment(&mut buf, comment);
+    }
+    Ok(buf)
 }
 
 fn map_io_write(e: io::Error, p: PathBuf) -> MeltforgeError {
@@ -84,3 +1150,1119 @@ fn map_io_write(e: io::Error, p: PathBuf) -> MeltforgeError {
         _ => IoError::WriteError(p).into(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{FormatError, InputError, SAME_FORMAT_EXIT_CODE};
+
+    #[test]
+    fn existing_output_gets_numbered_suffix() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.png");
+        image::RgbImage::new(4, 4).save(&input).unwrap();
+        let taken = dir.path().join("photo.jpg");
+        fs::write(&taken, b"keep me").unwrap();
+
+        let job = ConvertJob {
+            output: Some(taken.clone()),
+            on_conflict: ConflictPolicy::Rename,
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        };
+        let outcome = convert(job).unwrap();
+
+        assert_eq!(outcome.outputs, vec![dir.path().join("photo-1.jpg")]);
+        assert_eq!(fs::read(&taken).unwrap(), b"keep me");
+    }
+
+    #[test]
+    fn reproducible_outputs_are_byte_identical() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(24, 16, |x, y| {
+            image::Rgba([(x * 10) as u8, (y * 15) as u8, 90, 200])
+        }));
+        let opts = ConvertOptions {
+            reproducible: true,
+            ..ConvertOptions::default()
+        };
+        let outputs = FormatType::ALL
+            .into_iter()
+            .filter(|&to| to.is_enabled() && validate_compatibility(FormatType::PNG, to).is_ok());
+        for to in outputs {
+            let first = encode_image(&img, to, &opts).unwrap();
+            assert_eq!(first, encode_image(&img, to, &opts).unwrap(), "{to}");
+        }
+    }
+
+    #[test]
+    fn timings_cover_every_stage() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.png");
+        image::RgbImage::from_fn(256, 256, |x, y| image::Rgb([x as u8, y as u8, 0]))
+            .save(&input)
+            .unwrap();
+
+        let job = ConvertJob {
+            options: ConvertOptions {
+                scale: Some("50%".parse().unwrap()),
+                ..ConvertOptions::default()
+            },
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        };
+        let outcome = convert(job).unwrap();
+        assert_eq!(outcome.pixels, 256 * 256);
+        let timings = outcome.timings;
+
+        assert!(timings.decode > Duration::ZERO);
+        assert!(timings.encode > Duration::ZERO);
+        assert!(timings.write > Duration::ZERO);
+        assert_eq!(timings.transforms.len(), 1);
+        assert_eq!(timings.transforms[0].0, "scale");
+        assert!(timings.transforms[0].1 > Duration::ZERO);
+        assert!(timings.stages() <= timings.total);
+    }
+
+    #[test]
+    fn timings_add_up_per_stage() {
+        let ms = Duration::from_millis;
+        let timings = Timings {
+            decode: ms(3),
+            transforms: vec![("scale", ms(2))],
+            encode: ms(5),
+            write: ms(1),
+            total: ms(12),
+        };
+
+        let mut batch = timings.clone();
+        batch += &timings;
+        assert_eq!(batch.decode, ms(6));
+        assert_eq!(batch.transforms, [("scale", ms(4))]);
+        assert_eq!(batch.stages(), ms(22));
+        assert_eq!(batch.total, ms(24));
+    }
+
+    #[test]
+    fn autocrop_trims_transparent_sprite_margins() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("sprite.png");
+        // 6x4 opaque sprite inside a 32x32 transparent canvas
+        let sprite = image::RgbaImage::from_fn(32, 32, |x, y| {
+            if (10..16).contains(&x) && (5..9).contains(&y) {
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 0, 0])
+            }
+        });
+        sprite.save(&input).unwrap();
+
+        let job = ConvertJob {
+            options: ConvertOptions {
+                autocrop: true,
+                ..ConvertOptions::default()
+            },
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        };
+        let outcome = convert(job).unwrap();
+
+        let cropped = image::open(&outcome.outputs[0]).unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (6, 4));
+        assert!(outcome.warnings.is_empty());
+    }
+
+    #[test]
+    fn input_over_pixel_limit_is_rejected_before_decoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("big.png");
+        image::RgbImage::new(100, 100).save(&input).unwrap();
+
+        let job = ConvertJob {
+            max_pixels: 100 * 99,
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        };
+        let err = convert(job).unwrap_err();
+
+        assert!(matches!(
+            err,
+            MeltforgeError::Conversion(ConversionError::ExecutionFailed(_))
+        ));
+        assert!(!dir.path().join("big.jpg").exists());
+    }
+
+    #[test]
+    fn encodes_in_memory_image_without_a_file() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(40, 20, |x, _| {
+            image::Rgb([x as u8 * 6, 0, 0])
+        }));
+        let opts = ConvertOptions {
+            scale: Some("50%".parse().unwrap()),
+            ..ConvertOptions::default()
+        };
+
+        let bytes = encode_image(&img, FormatType::PNG, &opts).unwrap();
+
+        let decoded = image::load_from_memory_with_format(&bytes, ImageFormat::Png).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (20, 10));
+    }
+
+    #[test]
+    fn oversized_jpeg_is_retried_at_reduced_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("big.jpg");
+        image::RgbImage::from_pixel(64, 48, image::Rgb([200, 40, 40]))
+            .save(&input)
+            .unwrap();
+
+        let job = ConvertJob {
+            max_pixels: 32 * 24,
+            retry_on_oom: true,
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        let outcome = convert(job).unwrap();
+
+        let written = image::open(&outcome.outputs[0]).unwrap();
+        assert_eq!((written.width(), written.height()), (32, 24));
+        assert_eq!(outcome.warnings.len(), 1);
+    }
+
+    #[test]
+    fn oversized_png_still_fails_with_retry() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("big.png");
+        image::RgbImage::new(64, 48).save(&input).unwrap();
+
+        let job = ConvertJob {
+            max_pixels: 32 * 24,
+            retry_on_oom: true,
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        };
+        assert!(matches!(
+            convert(job),
+            Err(MeltforgeError::Conversion(
+                ConversionError::ExecutionFailed(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn converts_bytes_in_memory() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(image::RgbImage::new(20, 10))
+            .write_to(&mut io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let opts = ConvertOptions {
+            scale: Some("50%".parse().unwrap()),
+            ..ConvertOptions::default()
+        };
+
+        let jpeg = convert_in_memory(&png, FormatType::PNG, FormatType::JPEG, &opts).unwrap();
+
+        let decoded = image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (10, 5));
+        assert!(convert_in_memory(&png[..20], FormatType::PNG, FormatType::JPEG, &opts).is_err());
+    }
+
+    #[test]
+    fn in_memory_conversion_rejects_options_the_target_cannot_honor() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(image::RgbImage::new(4, 4))
+            .write_to(&mut io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        for opts in [
+            ConvertOptions {
+                bit_depth: Some(BitDepth::One),
+                ..ConvertOptions::default()
+            },
+            ConvertOptions {
+                bit_depth: Some(BitDepth::Sixteen),
+                ..ConvertOptions::default()
+            },
+            ConvertOptions {
+                channels: Some(crate::color::Channels::Rgba),
+                ..ConvertOptions::default()
+            },
+        ] {
+            let err =
+                convert_in_memory(&png, FormatType::PNG, FormatType::JPEG, &opts).unwrap_err();
+            assert!(
+                matches!(err, MeltforgeError::Input(InputError::InvalidArgument(_))),
+                "{err}"
+            );
+            let img = image::load_from_memory(&png).unwrap();
+            assert!(encode_image(&img, FormatType::JPEG, &opts).is_err());
+        }
+    }
+
+    #[test]
+    fn forged_dimensions_in_memory_are_rejected_before_decoding() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(image::RgbImage::new(2, 2))
+            .write_to(&mut io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        // IHDR: length at 8, type at 12, width and height at 16 and 20, crc at 29
+        png[16..20].copy_from_slice(&100_000u32.to_be_bytes());
+        png[20..24].copy_from_slice(&100_000u32.to_be_bytes());
+        let crc = crc32fast::hash(&png[12..29]);
+        png[29..33].copy_from_slice(&crc.to_be_bytes());
+
+        let err = convert_in_memory(
+            &png,
+            FormatType::PNG,
+            FormatType::JPEG,
+            &ConvertOptions::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("100000x100000"), "{err}");
+    }
+
+    #[test]
+    fn dpi_is_written_to_png_and_jpeg() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::new(4, 4));
+        let opts = ConvertOptions {
+            dpi: Some(300),
+            ..ConvertOptions::default()
+        };
+
+        let png = encode_image(&img, FormatType::PNG, &opts).unwrap();
+        let phys = png.windows(4).position(|w| w == b"pHYs").unwrap();
+        // 300 dpi is 11811 pixels per meter
+        assert_eq!(png[phys + 4..phys + 8], 11811u32.to_be_bytes());
+
+        let jpeg = encode_image(&img, FormatType::JPEG, &opts).unwrap();
+        // JFIF header: density unit 1 (inches), then x and y density
+        assert_eq!(&jpeg[6..11], b"JFIF\0");
+        assert_eq!(jpeg[13], 1);
+        assert_eq!(jpeg[14..18], [1, 44, 1, 44]);
+    }
+
+    #[test]
+    fn write_errors_are_retried_up_to_the_limit() {
+        let mut retries = Vec::new();
+        let mut failures = 1;
+        with_write_retries(2, &mut retries, || {
+            if failures > 0 {
+                failures -= 1;
+                return Err(IoError::WriteError("out.png".into()).into());
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(retries.len(), 1);
+
+        let mut attempts = 0;
+        let err = with_write_retries(1, &mut Vec::new(), || {
+            attempts += 1;
+            Err(IoError::WriteError("out.png".into()).into())
+        })
+        .unwrap_err();
+        assert!(matches!(err, MeltforgeError::Io(IoError::WriteError(_))));
+        assert_eq!(attempts, 2);
+
+        let mut attempts = 0;
+        let err = with_write_retries(3, &mut Vec::new(), || {
+            attempts += 1;
+            Err(IoError::PermissionDenied("out.png".into()).into())
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            MeltforgeError::Io(IoError::PermissionDenied(_))
+        ));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn grayscale_stays_grayscale_unless_rgba_is_forced() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("gray.png");
+        image::GrayAlphaImage::from_pixel(8, 8, image::LumaA([90, 128]))
+            .save(&input)
+            .unwrap();
+
+        let jpeg = convert(ConvertJob::new(&input, FormatType::JPEG)).unwrap();
+        let decoded = image::open(&jpeg.outputs[0]).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::L8);
+
+        let mut job = ConvertJob {
+            allow_same_format: true,
+            output: Some(dir.path().join("scaled.png")),
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        job.options.scale = Some("50%".parse().unwrap());
+        let png = convert(job.clone()).unwrap();
+        let decoded = image::open(&png.outputs[0]).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::La8);
+
+        job.output = Some(dir.path().join("rgba.png"));
+        job.options.force_rgba = true;
+        let rgba = convert(job).unwrap();
+        let decoded = image::open(&rgba.outputs[0]).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::Rgba8);
+    }
+
+    #[test]
+    fn reports_the_decoded_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbaImage::new(10, 5).save(&input).unwrap();
+
+        let outcome = convert(ConvertJob::new(&input, FormatType::JPEG)).unwrap();
+        assert_eq!(outcome.pixels, 50);
+        assert_eq!(outcome.decoded_bytes, 50 * 4);
+    }
+
+    #[test]
+    fn bit_depth_keeps_or_sets_the_sample_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("deep.png");
+        image::ImageBuffer::<image::Rgb<u16>, _>::from_pixel(
+            4,
+            4,
+            image::Rgb([1000, 40000, 65535]),
+        )
+        .save(&input)
+        .unwrap();
+        let job = |name: &str, depth: Option<BitDepth>| {
+            let mut job = ConvertJob {
+                allow_same_format: true,
+                output: Some(dir.path().join(name)),
+                ..ConvertJob::new(&input, FormatType::PNG)
+            };
+            job.options.bit_depth = depth;
+            job
+        };
+
+        let kept = convert(job("kept.png", None)).unwrap();
+        let decoded = image::open(&kept.outputs[0]).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::Rgb16);
+        assert_eq!(decoded.to_rgb16().get_pixel(0, 0).0, [1000, 40000, 65535]);
+
+        let eight = convert(job("eight.png", Some(BitDepth::Eight))).unwrap();
+        let decoded = image::open(&eight.outputs[0]).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::Rgb8);
+
+        let mut jpeg = ConvertJob::new(&input, FormatType::JPEG);
+        jpeg.options.bit_depth = Some(BitDepth::Sixteen);
+        assert!(matches!(
+            convert(jpeg),
+            Err(MeltforgeError::Input(InputError::InvalidArgument(_)))
+        ));
+    }
+
+    #[test]
+    fn channels_set_the_output_layout() {
+        use crate::color::Channels;
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbaImage::from_pixel(4, 4, image::Rgba([255, 0, 0, 0]))
+            .save(&input)
+            .unwrap();
+        let job = |to: FormatType, channels: Channels| {
+            let mut job = ConvertJob {
+                allow_same_format: true,
+                output: Some(dir.path().join(format!("{channels}.{}", to.extension()))),
+                ..ConvertJob::new(&input, to)
+            };
+            job.options.channels = Some(channels);
+            job.options.background = "0000ff".parse().unwrap();
+            job
+        };
+
+        // dropped alpha shows the background, not the hidden red
+        let rgb = convert(job(FormatType::PNG, Channels::Rgb)).unwrap();
+        let decoded = image::open(&rgb.outputs[0]).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::Rgb8);
+        assert_eq!(decoded.to_rgb8().get_pixel(0, 0).0, [0, 0, 255]);
+
+        let graya = convert(job(FormatType::PNG, Channels::GrayA)).unwrap();
+        let decoded = image::open(&graya.outputs[0]).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::La8);
+
+        let gray = convert(job(FormatType::JPEG, Channels::Gray)).unwrap();
+        let decoded = image::open(&gray.outputs[0]).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::L8);
+
+        assert!(matches!(
+            convert(job(FormatType::JPEG, Channels::Rgba)),
+            Err(MeltforgeError::Input(InputError::InvalidArgument(_)))
+        ));
+    }
+
+    #[test]
+    fn transparent_png_to_jpeg_is_flattened_onto_background() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            16,
+            16,
+            image::Rgba([0, 0, 0, 0]),
+        ));
+        let opts = ConvertOptions {
+            background: "ff0000".parse().unwrap(),
+            quality: Some(100),
+            ..ConvertOptions::default()
+        };
+
+        let bytes = encode_image(&img, FormatType::JPEG, &opts).unwrap();
+
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgb8();
+        let [r, g, b] = decoded.get_pixel(8, 8).0;
+        assert!(r > 240 && g < 16 && b < 16, "got {r},{g},{b}");
+    }
+
+    #[test]
+    fn same_format_needs_opt_in_and_never_overwrites_the_input_unasked() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.png");
+        image::RgbImage::new(10, 10).save(&input).unwrap();
+
+        let rejected = convert(ConvertJob::new(&input, FormatType::PNG)).unwrap_err();
+        assert!(matches!(
+            rejected,
+            MeltforgeError::Format(FormatError::SameFormat(FormatType::PNG))
+        ));
+        assert_eq!(rejected.exit_code(), SAME_FORMAT_EXIT_CODE);
+
+        let job = ConvertJob {
+            allow_same_format: true,
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        assert!(matches!(
+            convert(job),
+            Err(MeltforgeError::Io(IoError::AlreadyExists(_)))
+        ));
+
+        let job = ConvertJob {
+            allow_same_format: true,
+            output: Some(dir.path().join("small.png")),
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        assert!(convert(job).is_ok());
+    }
+
+    #[test]
+    fn existing_derived_output_is_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("a.png");
+        image::RgbImage::new(4, 4).save(&input).unwrap();
+        let existing = dir.path().join("a.jpg");
+        fs::write(&existing, b"precious").unwrap();
+
+        assert!(matches!(
+            convert(ConvertJob::new(&input, FormatType::JPEG)),
+            Err(MeltforgeError::Io(IoError::AlreadyExists(_)))
+        ));
+        assert_eq!(fs::read(&existing).unwrap(), b"precious");
+    }
+
+    #[test]
+    fn in_place_replaces_the_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.png");
+        image::RgbImage::from_pixel(10, 10, image::Rgb([1, 2, 3]))
+            .save(&input)
+            .unwrap();
+
+        let job = ConvertJob {
+            in_place: true,
+            options: ConvertOptions {
+                scale: Some("50%".parse().unwrap()),
+                ..ConvertOptions::default()
+            },
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        let outcome = convert(job).unwrap();
+
+        assert_eq!(outcome.outputs, vec![input.clone()]);
+        assert_eq!(image::open(&input).unwrap().width(), 5);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn existing_output_is_skipped_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.png");
+        image::RgbImage::new(4, 4).save(&input).unwrap();
+        let existing = dir.path().join("photo.jpg");
+        fs::write(&existing, b"done earlier").unwrap();
+
+        let job = ConvertJob {
+            on_conflict: ConflictPolicy::Skip,
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        };
+        let outcome = convert(job).unwrap();
+
+        assert!(outcome.skipped);
+        assert_eq!(outcome.outputs, vec![existing.clone()]);
+        assert_eq!(fs::read(&existing).unwrap(), b"done earlier");
+    }
+
+    // JPEG with an APP1 segment holding only an EXIF orientation tag.
+    fn jpeg_with_orientation(img: &image::RgbImage, orientation: u16) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        img.write_to(&mut io::Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+
+        let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08".to_vec();
+        exif.extend_from_slice(&1u16.to_be_bytes()); // one IFD entry
+        exif.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1]); // orientation, SHORT, count 1
+        exif.extend_from_slice(&orientation.to_be_bytes());
+        exif.extend_from_slice(&[0, 0, 0, 0, 0, 0]); // padding, no next IFD
+
+        let mut app1 = vec![0xFF, 0xE1];
+        app1.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+        app1.extend_from_slice(&exif);
+        jpeg.splice(2..2, app1);
+        jpeg
+    }
+
+    #[test]
+    fn jpeg_exif_orientation_is_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("phone.jpg");
+        let landscape = image::RgbImage::from_pixel(8, 4, image::Rgb([90, 90, 90]));
+        // 6: rotate 90 degrees clockwise to display
+        fs::write(&input, jpeg_with_orientation(&landscape, 6)).unwrap();
+
+        let oriented = convert(ConvertJob::new(&input, FormatType::PNG)).unwrap();
+        let img = image::open(&oriented.outputs[0]).unwrap();
+        assert_eq!((img.width(), img.height()), (4, 8));
+
+        let job = ConvertJob {
+            options: ConvertOptions {
+                auto_orient: false,
+                ..ConvertOptions::default()
+            },
+            output: Some(dir.path().join("raw.png")),
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        let raw = convert(job).unwrap();
+        let img = image::open(&raw.outputs[0]).unwrap();
+        assert_eq!((img.width(), img.height()), (8, 4));
+    }
+
+    #[test]
+    fn threaded_jpeg_decode_matches_default_decode() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.jpg");
+        image::RgbImage::from_fn(64, 48, |x, y| image::Rgb([x as u8 * 4, y as u8 * 5, 0]))
+            .save(&input)
+            .unwrap();
+
+        let job = ConvertJob {
+            decode_threads: Some(4),
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        let outcome = convert(job).unwrap();
+
+        assert!(outcome.warnings.is_empty());
+        let threaded = image::open(&outcome.outputs[0]).unwrap();
+        assert_eq!((threaded.width(), threaded.height()), (64, 48));
+    }
+
+    #[test]
+    fn truncated_input_is_reported_as_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("cut.png");
+        image::RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8, y as u8, 7]))
+            .save(&input)
+            .unwrap();
+        let bytes = fs::read(&input).unwrap();
+        fs::write(&input, &bytes[..bytes.len() / 2]).unwrap();
+
+        let err = convert(ConvertJob::new(&input, FormatType::JPEG)).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("corrupt or truncated"), "{message}");
+        assert!(message.contains("cut.png"), "{message}");
+    }
+
+    #[test]
+    fn cancelled_conversion_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+
+        let err = convert_with_cancel(
+            ConvertJob::new(&input, FormatType::JPEG),
+            &AtomicBool::new(true),
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            err,
+            MeltforgeError::Conversion(ConversionError::Cancelled)
+        ));
+        assert!(!dir.path().join("in.jpg").exists());
+    }
+
+    #[test]
+    fn output_is_renamed_into_place_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+        // a file that happens to look like a temp name is not the temp file
+        let lookalike = dir.path().join(".in.jpg.meltforge-tmp");
+        fs::write(&lookalike, b"keep me").unwrap();
+
+        let outcome = convert(ConvertJob {
+            verify: true,
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        })
+        .unwrap();
+
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, [".in.jpg.meltforge-tmp", "in.jpg", "in.png"]);
+        assert_eq!(fs::read(&lookalike).unwrap(), b"keep me");
+        assert!(image::open(&outcome.outputs[0]).is_ok());
+
+        let output = dir.path().join("in.jpg");
+        assert_ne!(TempOutput::new(&output).0, TempOutput::new(&output).0);
+    }
+
+    #[test]
+    fn timestamps_are_copied_from_the_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+        let modified = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&input)
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(modified))
+            .unwrap();
+
+        let outcome = convert(ConvertJob {
+            preserve_timestamps: true,
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        })
+        .unwrap();
+
+        assert!(outcome.warnings.is_empty());
+        let output_meta = fs::metadata(&outcome.outputs[0]).unwrap();
+        assert_eq!(output_meta.modified().unwrap(), modified);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions_are_copied_from_the_input() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+        fs::set_permissions(&input, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let outcome = convert(ConvertJob {
+            preserve_permissions: true,
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        })
+        .unwrap();
+
+        assert!(outcome.warnings.is_empty());
+        let mode = fs::metadata(&outcome.outputs[0])
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn timed_out_job_leaves_no_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(256, 256).save(&input).unwrap();
+
+        let err = convert_with_timeout(
+            ConvertJob::new(&input, FormatType::JPEG),
+            Duration::ZERO,
+            &AtomicBool::new(false),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+
+        // give the abandoned worker time to notice
+        std::thread::sleep(Duration::from_millis(500));
+        let names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["in.png"]);
+
+        let written = std::cell::Cell::new(false);
+        let outcome = convert_with_timeout_and_events(
+            ConvertJob::new(&input, FormatType::JPEG),
+            Duration::from_secs(30),
+            &AtomicBool::new(false),
+            &|stage| written.set(matches!(stage, ConvertStage::Written(_))),
+        )
+        .unwrap();
+        assert!(outcome.outputs[0].exists());
+        assert!(written.get());
+    }
+
+    #[test]
+    fn reports_each_stage_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+        let output = dir.path().join("in.jpg");
+
+        let stages = std::cell::RefCell::new(Vec::new());
+        convert_with_events(
+            ConvertJob::new(&input, FormatType::JPEG),
+            &AtomicBool::new(false),
+            &|stage| stages.borrow_mut().push(stage),
+        )
+        .unwrap();
+        assert_eq!(
+            stages.into_inner(),
+            [
+                ConvertStage::Validated(input.clone()),
+                ConvertStage::Decoded(input),
+                ConvertStage::Transformed(output.clone()),
+                ConvertStage::Encoded(output.clone()),
+                ConvertStage::Written(output),
+            ]
+        );
+    }
+
+    #[test]
+    fn strict_job_fails_on_a_warning_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("blank.png");
+        image::RgbImage::new(8, 8).save(&input).unwrap();
+        let mut job = ConvertJob::new(&input, FormatType::JPEG);
+        job.options.autocrop = true;
+
+        let lenient = convert(job.clone()).unwrap();
+        assert_eq!(lenient.warnings.len(), 1);
+        fs::remove_file(&lenient.outputs[0]).unwrap();
+
+        job.strict = true;
+        assert!(matches!(
+            convert(job),
+            Err(MeltforgeError::Conversion(ConversionError::StrictWarning(
+                _
+            )))
+        ));
+        assert!(!dir.path().join("blank.jpg").exists());
+    }
+
+    #[test]
+    fn premultiplied_alpha_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("sprite.png");
+        // straight color (200, 100, 50) premultiplied by alpha 128
+        let fringe = image::RgbaImage::from_fn(8, 8, |x, _| match x {
+            0..=3 => image::Rgba([100, 50, 25, 128]),
+            _ => image::Rgba([200, 100, 50, 255]),
+        });
+        fringe.save(&input).unwrap();
+
+        let job = ConvertJob {
+            premultiply_check: true,
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        };
+        let outcome = convert(job).unwrap();
+
+        assert_eq!(outcome.warnings.len(), 1);
+        assert!(outcome.warnings[0].contains("premultiplied"));
+    }
+
+    // Wraps lossless single-frame WebPs into an animated RIFF container.
+    #[cfg(feature = "webp")]
+    fn animated_webp(frames: &[image::RgbaImage]) -> Vec<u8> {
+        use image::codecs::webp::WebPEncoder;
+
+        fn chunk(out: &mut Vec<u8>, fourcc: &[u8], data: &[u8]) {
+            out.extend_from_slice(fourcc);
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                out.push(0);
+            }
+        }
+        fn u24(out: &mut Vec<u8>, v: u32) {
+            out.extend_from_slice(&v.to_le_bytes()[..3]);
+        }
+
+        let (w, h) = frames[0].dimensions();
+        let mut body = b"WEBP".to_vec();
+        let mut vp8x = vec![0x12, 0, 0, 0]; // animation + alpha
+        u24(&mut vp8x, w - 1);
+        u24(&mut vp8x, h - 1);
+        chunk(&mut body, b"VP8X", &vp8x);
+        chunk(&mut body, b"ANIM", &[0, 0, 0, 0, 0, 0]);
+
+        for frame in frames {
+            let mut still = Vec::new();
+            WebPEncoder::new_lossless(&mut still)
+                .encode(frame.as_raw(), w, h, image::ExtendedColorType::Rgba8)
+                .unwrap();
+            // still = RIFF header (12 bytes) followed by the VP8L chunk
+            let mut anmf = Vec::new();
+            u24(&mut anmf, 0);
+            u24(&mut anmf, 0);
+            u24(&mut anmf, w - 1);
+            u24(&mut anmf, h - 1);
+            u24(&mut anmf, 100);
+            anmf.push(0);
+            anmf.extend_from_slice(&still[12..]);
+            chunk(&mut body, b"ANMF", &anmf);
+        }
+
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(&body);
+        out
+    }
+
+    #[cfg(feature = "webp")]
+    #[test]
+    fn animated_webp_writes_every_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("anim.webp");
+        let frames: Vec<_> = [[255, 0, 0, 255], [0, 0, 255, 255], [0, 255, 0, 255]]
+            .into_iter()
+            .map(|c| image::RgbaImage::from_pixel(6, 4, image::Rgba(c)))
+            .collect();
+        fs::write(&input, animated_webp(&frames)).unwrap();
+
+        let job = ConvertJob {
+            all_frames: true,
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        let outcome = convert(job).unwrap();
+
+        assert_eq!(outcome.outputs.len(), 3);
+        assert_eq!(outcome.outputs[1], dir.path().join("anim_001.png"));
+        let second = image::open(&outcome.outputs[1]).unwrap().to_rgba8();
+        let [r, _, b, _] = second.get_pixel(0, 0).0;
+        assert!(r < 8 && b > 247, "second frame should be blue");
+    }
+
+    #[cfg(feature = "webp")]
+    #[test]
+    fn animated_webp_without_flag_warns() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("anim.webp");
+        let frames = vec![image::RgbaImage::new(2, 2); 2];
+        fs::write(&input, animated_webp(&frames)).unwrap();
+
+        let outcome = convert(ConvertJob::new(&input, FormatType::PNG)).unwrap();
+
+        assert_eq!(outcome.outputs, vec![dir.path().join("anim.png")]);
+        assert_eq!(outcome.warnings.len(), 1);
+    }
+
+    #[cfg(feature = "webp")]
+    #[test]
+    fn lossless_webp_keeps_every_pixel() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        let source = image::RgbaImage::from_fn(16, 9, |x, y| {
+            image::Rgba([x as u8 * 15, y as u8 * 28, (x * y) as u8, 255 - x as u8])
+        });
+        source.save(&input).unwrap();
+
+        let mut job = ConvertJob::new(&input, FormatType::WEBP);
+        job.options.webp_lossless = true;
+        job.options.quality = Some(50);
+        let outcome = convert(job).unwrap();
+
+        assert_eq!(
+            outcome.warnings,
+            ["--quality is ignored with --webp-lossless"]
+        );
+        assert_eq!(image::open(&outcome.outputs[0]).unwrap().to_rgba8(), source);
+    }
+
+    #[cfg(feature = "gif")]
+    fn animated_gif(path: &Path, colors: &[[u8; 4]]) {
+        use image::codecs::gif::GifEncoder;
+
+        let mut encoder = GifEncoder::new(fs::File::create(path).unwrap());
+        for &c in colors {
+            let frame = image::RgbaImage::from_pixel(6, 4, image::Rgba(c));
+            encoder.encode_frame(image::Frame::new(frame)).unwrap();
+        }
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn animated_gif_writes_every_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("anim.gif");
+        animated_gif(&input, &[[255, 0, 0, 255], [0, 0, 255, 255]]);
+
+        let job = ConvertJob {
+            all_frames: true,
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        let outcome = convert(job).unwrap();
+
+        assert_eq!(
+            outcome.outputs,
+            [
+                dir.path().join("anim_000.png"),
+                dir.path().join("anim_001.png")
+            ]
+        );
+        let second = image::open(&outcome.outputs[1]).unwrap().to_rgba8();
+        let [r, _, b, _] = second.get_pixel(0, 0).0;
+        assert!(r < 8 && b > 247, "second frame should be blue");
+
+        let outcome = convert(ConvertJob::new(&input, FormatType::JPEG)).unwrap();
+        assert_eq!(outcome.outputs, [dir.path().join("anim.jpg")]);
+        assert_eq!(outcome.warnings.len(), 1);
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn renamed_frames_move_aside_as_a_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("anim.gif");
+        animated_gif(&input, &[[255, 0, 0, 255]; 2]);
+        let job = || ConvertJob {
+            all_frames: true,
+            on_conflict: ConflictPolicy::Rename,
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        convert(job()).unwrap();
+        // only one frame is in the way, the whole set still moves
+        fs::remove_file(dir.path().join("anim_001.png")).unwrap();
+
+        let outcome = convert(job()).unwrap();
+        assert_eq!(
+            outcome.outputs,
+            [
+                dir.path().join("anim-1_000.png"),
+                dir.path().join("anim-1_001.png")
+            ]
+        );
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn existing_frames_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("anim.gif");
+        animated_gif(&input, &[[255, 0, 0, 255]; 2]);
+        let job = || ConvertJob {
+            all_frames: true,
+            on_conflict: ConflictPolicy::Skip,
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        assert!(!convert(job()).unwrap().skipped);
+
+        let outcome = convert(job()).unwrap();
+        assert!(outcome.skipped);
+        assert_eq!(
+            outcome.outputs,
+            [
+                dir.path().join("anim_000.png"),
+                dir.path().join("anim_001.png")
+            ]
+        );
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn cancelled_frames_leave_no_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("anim.gif");
+        animated_gif(&input, &[[255, 0, 0, 255]; 3]);
+
+        let cancel = AtomicBool::new(false);
+        let job = ConvertJob {
+            all_frames: true,
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        // cancelled once the first frame is written
+        let on_event = |stage| {
+            if matches!(stage, ConvertStage::Written(_)) {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        };
+        let err = convert_with_events(job, &cancel, &on_event).unwrap_err();
+
+        assert!(matches!(
+            err,
+            MeltforgeError::Conversion(ConversionError::Cancelled)
+        ));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn frames_count_against_the_pixel_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("anim.gif");
+        animated_gif(&input, &[[255, 0, 0, 255]; 3]);
+
+        // each frame is 6x4, the first two fit
+        let job = ConvertJob {
+            all_frames: true,
+            max_pixels: 2 * 24,
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        let err = convert(job).unwrap_err();
+        assert!(err.to_string().contains("first 3 frames"), "{err}");
+    }
+
+    #[cfg(feature = "pnm")]
+    #[test]
+    fn pnm_subtype_follows_the_color_type() {
+        let dir = tempfile::tempdir().unwrap();
+        let gray = dir.path().join("gray.png");
+        image::GrayImage::new(4, 4).save(&gray).unwrap();
+        let rgba = dir.path().join("rgba.png");
+        image::RgbaImage::new(4, 4).save(&rgba).unwrap();
+
+        let pgm = convert(ConvertJob::new(&gray, FormatType::PNM)).unwrap();
+        assert_eq!(pgm.outputs[0], dir.path().join("gray.ppm"));
+        assert!(fs::read(&pgm.outputs[0]).unwrap().starts_with(b"P5"));
+
+        let ppm = convert(ConvertJob::new(&rgba, FormatType::PNM)).unwrap();
+        assert!(fs::read(&ppm.outputs[0]).unwrap().starts_with(b"P6"));
+
+        let back = dir.path().join("back.png");
+        let job = ConvertJob {
+            output: Some(back.clone()),
+            ..ConvertJob::new(&ppm.outputs[0], FormatType::PNG)
+        };
+        convert(job).unwrap();
+        assert_eq!(image::open(&back).unwrap().width(), 4);
+    }
+
+    #[cfg(feature = "ico")]
+    #[test]
+    fn ico_round_trip_keeps_largest_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("logo.png");
+        image::RgbaImage::from_pixel(64, 40, image::Rgba([0, 120, 255, 255]))
+            .save(&input)
+            .unwrap();
+
+        let job = ConvertJob {
+            options: ConvertOptions {
+                ico_sizes: "16,32".parse().unwrap(),
+                ..ConvertOptions::default()
+            },
+            verify: true,
+            ..ConvertJob::new(&input, FormatType::ICO)
+        };
+        let ico = convert(job).unwrap().outputs.remove(0);
+        assert_eq!(ico, dir.path().join("logo.ico"));
+
+        let job = ConvertJob {
+            output: Some(dir.path().join("back.png")),
+            ..ConvertJob::new(&ico, FormatType::PNG)
+        };
+        let png = convert(job).unwrap().outputs.remove(0);
+        let decoded = image::open(png).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (32, 32));
+    }
+}
@@ -0,0 +1,140 @@
+use std::str::FromStr;
+
+use crate::error::InputError;
+
+const CM_PER_INCH: f64 = 2.54;
+const METERS_PER_INCH: f64 = 0.0254;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LengthUnit {
+    Inch,
+    Centimeter,
+    Millimeter,
+}
+
+impl LengthUnit {
+    fn to_inches(self, value: f64) -> f64 {
+        match self {
+            LengthUnit::Inch => value,
+            LengthUnit::Centimeter => value / CM_PER_INCH,
+            LengthUnit::Millimeter => value / (CM_PER_INCH * 10.0),
+        }
+    }
+}
+
+/// Physical print dimensions like `4x6in`, `10x15cm` or `100x150mm`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrintSize {
+    pub width: f64,
+    pub height: f64,
+    pub unit: LengthUnit,
+}
+
+/// Horizontal and vertical resolution in dots per inch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dpi {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl PrintSize {
+    /// DPI needed for an image of `width`x`height` pixels to print at this size.
+    /// The print size is matched to the image orientation, so `4x6in` also
+    /// covers a landscape photo printed at 6x4 inches.
+    pub fn dpi_for(&self, width: u32, height: u32) -> Dpi {
+        let (mut w, mut h) = (
+            self.unit.to_inches(self.width),
+            self.unit.to_inches(self.height),
+        );
+        if (width > height) != (w > h) {
+            std::mem::swap(&mut w, &mut h);
+        }
+        Dpi {
+            x: to_dpi(width as f64 / w),
+            y: to_dpi(height as f64 / h),
+        }
+    }
+}
+
+fn to_dpi(value: f64) -> u16 {
+    value.round().clamp(1.0, u16::MAX as f64) as u16
+}
+
+impl FromStr for PrintSize {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || InputError::InvalidArgument(format!("invalid print size '{s}' (e.g. 4x6in)"));
+        let lower = s.trim().to_lowercase();
+
+        let (dims, unit) = if let Some(d) = lower.strip_suffix("in") {
+            (d, LengthUnit::Inch)
+        } else if let Some(d) = lower.strip_suffix("cm") {
+            (d, LengthUnit::Centimeter)
+        } else if let Some(d) = lower.strip_suffix("mm") {
+            (d, LengthUnit::Millimeter)
+        } else {
+            return Err(invalid());
+        };
+
+        let (w, h) = dims.split_once('x').ok_or_else(invalid)?;
+        let width: f64 = w.trim().parse().map_err(|_| invalid())?;
+        let height: f64 = h.trim().parse().map_err(|_| invalid())?;
+        if !(width > 0.0 && height > 0.0 && width.is_finite() && height.is_finite()) {
+            return Err(invalid());
+        }
+
+        Ok(PrintSize {
+            width,
+            height,
+            unit,
+        })
+    }
+}
+
+/// Inserts a pHYs chunk (pixels per meter) right after the IHDR chunk of an encoded PNG.
+pub(crate) fn set_png_density(png: &mut Vec<u8>, dpi: Dpi) {
+    // 8 byte signature + IHDR (4 length + 4 type + 13 data + 4 crc)
+    const IHDR_END: usize = 8 + 25;
+
+    let mut chunk = Vec::with_capacity(21);
+    chunk.extend_from_slice(&9u32.to_be_bytes());
+    chunk.extend_from_slice(b"pHYs");
+    chunk.extend_from_slice(&per_meter(dpi.x).to_be_bytes());
+    chunk.extend_from_slice(&per_meter(dpi.y).to_be_bytes());
+    chunk.push(1); // unit: meter
+    let crc = crc32fast::hash(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+
+    png.splice(IHDR_END..IHDR_END, chunk);
+}
+
+fn per_meter(dpi: u16) -> u32 {
+    (dpi as f64 / METERS_PER_INCH).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dpi_for_known_print_size() {
+        let size: PrintSize = "4x6in".parse().unwrap();
+        assert_eq!(size.dpi_for(1200, 1800), Dpi { x: 300, y: 300 });
+        // landscape image gets the print size rotated
+        assert_eq!(size.dpi_for(1800, 1200), Dpi { x: 300, y: 300 });
+
+        let metric: PrintSize = "10x15cm".parse().unwrap();
+        assert_eq!(metric.dpi_for(1181, 1772), Dpi { x: 300, y: 300 });
+    }
+
+    #[test]
+    fn rejects_malformed_print_size() {
+        assert!("4x6".parse::<PrintSize>().is_err());
+        assert!("0x6in".parse::<PrintSize>().is_err());
+        assert!("axbin".parse::<PrintSize>().is_err());
+    }
+}
@@ -1,6 +1,10 @@
-use std::path::{Path, PathBuf};
+use std::{fmt, path::PathBuf};
+
+use image::ImageError;
 use thiserror::Error;
 
+use crate::format::FormatType;
+
 #[derive(Debug, Error)]
 pub enum MeltforgeError {
     #[error(transparent)]
@@ -16,10 +20,70 @@ pub enum MeltforgeError {
     Io(#[from] IoError),
 }
 
+/// Exit code of a job converting a format to itself without opting in.
+pub const SAME_FORMAT_EXIT_CODE: u8 = 7;
+
+/// Category of a [`MeltforgeError`], for callers that branch on it without
+/// matching every variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Input,
+    Format,
+    Conversion,
+    Io,
+}
+
 impl MeltforgeError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            MeltforgeError::Input(_) => ErrorKind::Input,
+            MeltforgeError::Format(_) => ErrorKind::Format,
+            MeltforgeError::Conversion(_) => ErrorKind::Conversion,
+            MeltforgeError::Io(_) => ErrorKind::Io,
+        }
+    }
+
+    /// Stable identifier of the exact error, e.g. `E_UNSUPPORTED_OUTPUT`.
+    /// Unlike the message it never changes, so it can key translations.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MeltforgeError::Input(e) => match e {
+                InputError::MissingInputFile(_) => "E_MISSING_INPUT",
+                InputError::MissingTargetFormat => "E_MISSING_TARGET_FORMAT",
+                InputError::InvalidArgument(_) => "E_INVALID_ARGUMENT",
+                InputError::EmptyInputFile(_) => "E_EMPTY_INPUT",
+            },
+            MeltforgeError::Format(e) => match e {
+                FormatError::UnsupportedInput(_) => "E_UNSUPPORTED_INPUT",
+                FormatError::MissingExtension(_) => "E_MISSING_EXTENSION",
+                FormatError::UnsupportedOutput { .. } => "E_UNSUPPORTED_OUTPUT",
+                FormatError::SameFormat(_) => "E_SAME_FORMAT",
+                FormatError::UnknownFormat(_) => "E_UNKNOWN_FORMAT",
+                FormatError::NotEnabled(_) => "E_FORMAT_NOT_ENABLED",
+                FormatError::AlreadyRegistered(_) => "E_ALREADY_REGISTERED",
+            },
+            MeltforgeError::Conversion(e) => match e {
+                ConversionError::PluginLoadFailed(_) => "E_PLUGIN_LOAD_FAILED",
+                ConversionError::ExecutionFailed(_) => "E_EXECUTION_FAILED",
+                ConversionError::OutputWriteFailed(_) => "E_OUTPUT_WRITE_FAILED",
+                ConversionError::Cancelled => "E_CANCELLED",
+                ConversionError::StrictWarning(_) => "E_STRICT_WARNING",
+            },
+            MeltforgeError::Io(e) => match e {
+                IoError::ReadError(_) => "E_READ",
+                IoError::WriteError(_) => "E_WRITE",
+                IoError::PermissionDenied(_) => "E_PERMISSION_DENIED",
+                IoError::AlreadyExists(_) => "E_ALREADY_EXISTS",
+                IoError::MissingParent(_) => "E_MISSING_PARENT",
+                IoError::Network { .. } => "E_NETWORK",
+            },
+        }
+    }
+
     pub fn exit_code(&self) -> u8 {
         match self {
             MeltforgeError::Input(_) => 2,
+            MeltforgeError::Format(FormatError::SameFormat(_)) => SAME_FORMAT_EXIT_CODE,
             MeltforgeError::Format(_) => 3,
             MeltforgeError::Conversion(_) => 4,
             MeltforgeError::Io(_) => 5,
@@ -31,10 +95,12 @@ impl MeltforgeError {
 pub enum InputError {
     #[error("Missing input file: {0}")]
     MissingInputFile(PathBuf),
-    #[error("Missing target format (--to)")]
+    #[error("Missing target format (use --to, or an --output with a known extension)")]
     MissingTargetFormat,
     #[error("Invalid argument: {0} ")]
     InvalidArgument(String),
+    #[error("Input file is empty: {0}")]
+    EmptyInputFile(PathBuf),
 }
 
 #[derive(Debug, Error)]
@@ -42,8 +108,24 @@ pub enum FormatError {
     #[error("unsupported input format {0}")]
     UnsupportedInput(String),
 
-    #[error("unsupported output format {0}")]
-    UnsupportedOutput(String),
+    #[error("cannot detect input format, file has no extension: {0}")]
+    MissingExtension(PathBuf),
+
+    /// `from` cannot be converted to `to`; both are the actual formats of the job.
+    #[error("unsupported output format {from:?} → {to:?} not supported yet")]
+    UnsupportedOutput { from: FormatType, to: FormatType },
+
+    #[error("input is already {0}, converting it to {0} does nothing (use --allow-same-format to re-encode it)")]
+    SameFormat(FormatType),
+
+    #[error("unknown format '{0}'")]
+    UnknownFormat(String),
+
+    #[error("format {0} is not enabled in this build (rebuild with the `{0}` feature)")]
+    NotEnabled(String),
+
+    #[error("format or extension '{0}' is already registered")]
+    AlreadyRegistered(String),
 }
 
 #[derive(Debug, Error)]
@@ -56,6 +138,58 @@ pub enum ConversionError {
 
     #[error("output write failed: {0}")]
     OutputWriteFailed(String),
+
+    #[error("conversion cancelled")]
+    Cancelled,
+
+    /// A warning, raised as an error because the job is strict.
+    #[error("{0} (treated as an error by --strict)")]
+    StrictWarning(String),
+}
+
+impl ConversionError {
+    /// Classifies an `image` error like the `From` conversion, with the
+    /// message prefixed by `context`, usually the file it concerns.
+    pub fn image(context: impl fmt::Display, e: ImageError) -> Self {
+        match ConversionError::from(e) {
+            ConversionError::ExecutionFailed(msg) => {
+                ConversionError::ExecutionFailed(format!("{context}: {msg}"))
+            }
+            ConversionError::OutputWriteFailed(msg) => {
+                ConversionError::OutputWriteFailed(format!("{context}: {msg}"))
+            }
+            other => other,
+        }
+    }
+}
+
+// Malformed data and reads ending early both mean the file itself is damaged;
+// a failed encode means nothing was written. Anything else (unsupported
+// features, limits) is reported as is.
+impl From<ImageError> for ConversionError {
+    fn from(e: ImageError) -> Self {
+        match e {
+            ImageError::Decoding(_) | ImageError::IoError(_) => {
+                ConversionError::ExecutionFailed(format!("file appears corrupt or truncated ({e})"))
+            }
+            ImageError::Encoding(_) => {
+                ConversionError::OutputWriteFailed(format!("encoding failed ({e})"))
+            }
+            ImageError::Unsupported(_) => {
+                ConversionError::ExecutionFailed(format!("not supported ({e})"))
+            }
+            ImageError::Limits(_) => {
+                ConversionError::ExecutionFailed(format!("exceeds the decoder limits ({e})"))
+            }
+            ImageError::Parameter(_) => ConversionError::ExecutionFailed(e.to_string()),
+        }
+    }
+}
+
+impl From<ImageError> for MeltforgeError {
+    fn from(e: ImageError) -> Self {
+        MeltforgeError::Conversion(e.into())
+    }
 }
 
 #[derive(Debug, Error)]
@@ -74,4 +208,50 @@ pub enum IoError {
 
     #[error("parent directory missing: {0}")]
     MissingParent(PathBuf),
+
+    /// Downloading a URL input failed, `reason` says how.
+    #[error("network error fetching {url}: {reason}")]
+    Network { url: String, reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_and_code_name_the_variant() {
+        let err = MeltforgeError::from(FormatError::UnsupportedOutput {
+            from: FormatType::PNG,
+            to: FormatType::HEIF,
+        });
+        assert_eq!(err.kind(), ErrorKind::Format);
+        assert_eq!(err.code(), "E_UNSUPPORTED_OUTPUT");
+
+        let err = MeltforgeError::from(IoError::AlreadyExists("out.png".into()));
+        assert_eq!(err.kind(), ErrorKind::Io);
+        assert_eq!(err.code(), "E_ALREADY_EXISTS");
+    }
+
+    #[test]
+    fn image_errors_are_classified() {
+        use image::error::{
+            DecodingError, EncodingError, ImageFormatHint, LimitError, LimitErrorKind,
+        };
+
+        let hint = || ImageFormatHint::Exact(image::ImageFormat::Png);
+        let err = ConversionError::image(
+            "a.png",
+            ImageError::Decoding(DecodingError::new(hint(), "bad crc")),
+        );
+        assert!(matches!(&err, ConversionError::ExecutionFailed(m)
+            if m.starts_with("a.png: file appears corrupt or truncated")));
+
+        let err = MeltforgeError::from(ImageError::Encoding(EncodingError::new(hint(), "no")));
+        assert_eq!(err.code(), "E_OUTPUT_WRITE_FAILED");
+
+        let limits = ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory));
+        let err = MeltforgeError::from(limits);
+        assert_eq!(err.exit_code(), 4);
+        assert!(err.to_string().contains("decoder limits"), "{err}");
+    }
 }
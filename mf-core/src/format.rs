@@ -1,5 +1,435 @@
-#[derive(Debug, Clone, Copy)]
+use std::{fmt, str::FromStr};
+
+use image::ImageFormat;
+
+use crate::{error::FormatError, registry};
+
+/// Serialized as its name, and deserialized from anything [`FromStr`] accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum FormatType {
     PNG,
     JPEG,
+    AVIF,
+    WEBP,
+    TIFF,
+    ICO,
+    /// HEIF and HEIC, input only.
+    HEIF,
+    /// Input only.
+    GIF,
+    /// PBM, PGM and PPM. Outputs are PGM for grayscale images, PPM otherwise.
+    PNM,
+    /// JPEG XL, lossy by default or lossless with `jxl_lossless`.
+    JXL,
+    /// A format added with [`registry::register`], by its name.
+    Custom(&'static str),
+}
+
+impl FormatType {
+    /// Every built-in format, whether or not it is enabled in this build.
+    pub const ALL: [FormatType; 10] = [
+        FormatType::PNG,
+        FormatType::JPEG,
+        FormatType::AVIF,
+        FormatType::WEBP,
+        FormatType::TIFF,
+        FormatType::ICO,
+        FormatType::HEIF,
+        FormatType::GIF,
+        FormatType::PNM,
+        FormatType::JXL,
+    ];
+
+    /// The built-in formats followed by the registered ones.
+    pub fn all() -> Vec<FormatType> {
+        let mut all = FormatType::ALL.to_vec();
+        all.extend(registry::registered());
+        all
+    }
+
+    /// Canonical name, shown to users (`jpeg`, not `jpg`).
+    pub fn name(self) -> &'static str {
+        match self {
+            FormatType::PNG => "png",
+            FormatType::JPEG => "jpeg",
+            FormatType::AVIF => "avif",
+            FormatType::WEBP => "webp",
+            FormatType::TIFF => "tiff",
+            FormatType::ICO => "ico",
+            FormatType::HEIF => "heif",
+            FormatType::GIF => "gif",
+            FormatType::PNM => "pnm",
+            FormatType::JXL => "jxl",
+            FormatType::Custom(name) => name,
+        }
+    }
+
+    /// Canonical file extension used for derived output paths (`jpg`, not `jpeg`).
+    pub fn extension(self) -> &'static str {
+        match self {
+            FormatType::PNG => "png",
+            FormatType::JPEG => "jpg",
+            FormatType::AVIF => "avif",
+            FormatType::WEBP => "webp",
+            FormatType::TIFF => "tiff",
+            FormatType::ICO => "ico",
+            FormatType::HEIF => "heic",
+            FormatType::GIF => "gif",
+            FormatType::PNM => "ppm",
+            FormatType::JXL => "jxl",
+            FormatType::Custom(name) => registry::lookup(name).map_or(name, |f| f.extension()),
+        }
+    }
+
+    /// The matching codec in the `image` crate. HEIF and JPEG XL have none,
+    /// they go through libheif and libjxl, and neither have custom formats.
+    pub fn image_format(self) -> Option<ImageFormat> {
+        match self {
+            FormatType::PNG => Some(ImageFormat::Png),
+            FormatType::JPEG => Some(ImageFormat::Jpeg),
+            FormatType::AVIF => Some(ImageFormat::Avif),
+            FormatType::WEBP => Some(ImageFormat::WebP),
+            FormatType::TIFF => Some(ImageFormat::Tiff),
+            FormatType::ICO => Some(ImageFormat::Ico),
+            FormatType::HEIF => None,
+            FormatType::GIF => Some(ImageFormat::Gif),
+            FormatType::PNM => Some(ImageFormat::Pnm),
+            FormatType::JXL => None,
+            FormatType::Custom(_) => None,
+        }
+    }
+
+    /// Format of encoded data judged by its magic bytes, for input without a
+    /// trustworthy name. Custom formats are never detected.
+    pub fn sniff(data: &[u8]) -> Option<FormatType> {
+        // ISO base media files: a size, then `ftyp` and the major brand
+        if data.get(4..8) == Some(b"ftyp") {
+            return match data.get(8..12)? {
+                b"heic" | b"heix" | b"mif1" | b"msf1" => Some(FormatType::HEIF),
+                b"avif" | b"avis" => Some(FormatType::AVIF),
+                _ => None,
+            };
+        }
+        // a bare codestream, or the box that starts the container
+        if data.starts_with(&[0xff, 0x0a]) || data.starts_with(b"\0\0\0\x0cJXL \r\n\x87\n") {
+            return Some(FormatType::JXL);
+        }
+        let format = image::guess_format(data).ok()?;
+        FormatType::ALL
+            .into_iter()
+            .find(|f| f.image_format() == Some(format))
+    }
+
+    /// Format of a file extension such as `JPG` or `.tif`, ignoring case and a
+    /// leading dot. Registered formats are found by their extensions.
+    pub fn from_extension(ext: &str) -> Result<FormatType, FormatError> {
+        let ext = ext.trim();
+        let name = ext.strip_prefix('.').unwrap_or(ext).to_lowercase();
+        registry::find(&name).ok_or(FormatError::UnsupportedInput(name))
+    }
+
+    /// Format of a MIME type such as `image/png`, ignoring parameters like
+    /// `; charset=...` and case.
+    pub fn from_mime(mime: &str) -> Result<FormatType, FormatError> {
+        let essence = mime.split(';').next().unwrap_or_default().trim();
+        match essence.to_lowercase().as_str() {
+            "image/png" => Ok(FormatType::PNG),
+            // image/jpg and image/pjpeg are not registered but common in the wild
+            "image/jpeg" | "image/jpg" | "image/pjpeg" => Ok(FormatType::JPEG),
+            "image/avif" => Ok(FormatType::AVIF),
+            "image/webp" => Ok(FormatType::WEBP),
+            "image/tiff" => Ok(FormatType::TIFF),
+            "image/vnd.microsoft.icon" | "image/x-icon" => Ok(FormatType::ICO),
+            "image/heic" | "image/heif" => Ok(FormatType::HEIF),
+            "image/gif" => Ok(FormatType::GIF),
+            "image/x-portable-anymap"
+            | "image/x-portable-bitmap"
+            | "image/x-portable-graymap"
+            | "image/x-portable-pixmap" => Ok(FormatType::PNM),
+            "image/jxl" => Ok(FormatType::JXL),
+            _ => Err(FormatError::UnsupportedInput(mime.to_string())),
+        }
+    }
+
+    /// Whether outputs are encoded lossy, so a quality setting has an effect.
+    /// WebP counts as lossless because its encoder only writes lossless files.
+    pub fn is_lossy(self) -> bool {
+        matches!(self, FormatType::JPEG | FormatType::AVIF | FormatType::JXL)
+    }
+
+    /// Whether `reproducible` outputs are byte-identical for the same input
+    /// and options. No built-in encoder embeds a timestamp, and the AVIF
+    /// encoder is kept to one thread; custom formats are up to their plugin.
+    pub fn is_reproducible(self) -> bool {
+        !matches!(self, FormatType::Custom(_))
+    }
+
+    /// Whether outputs can be written with 16 bits per channel.
+    pub fn supports_16_bit(self) -> bool {
+        matches!(self, FormatType::PNG | FormatType::TIFF)
+    }
+
+    /// Whether outputs carry a resolution (DPI) for `print_size` and `dpi`.
+    pub fn stores_density(self) -> bool {
+        matches!(self, FormatType::PNG | FormatType::JPEG)
+    }
+
+    /// Whether the format can store an alpha channel. PNM only can as PAM,
+    /// which is not written.
+    pub fn supports_alpha(self) -> bool {
+        !matches!(self, FormatType::JPEG | FormatType::PNM)
+    }
+
+    /// Whether outputs can be written as grayscale. The others store gray
+    /// images as RGB.
+    pub fn supports_gray(self) -> bool {
+        matches!(
+            self,
+            FormatType::PNG
+                | FormatType::JPEG
+                | FormatType::WEBP
+                | FormatType::TIFF
+                | FormatType::PNM
+        )
+    }
+
+    /// Encoder quality used when none is given, `None` for lossless formats.
+    pub fn default_quality(self) -> Option<u8> {
+        match self {
+            FormatType::JPEG => Some(85),
+            FormatType::AVIF => Some(80),
+            // libjxl's visually lossless distance of 1.0
+            FormatType::JXL => Some(90),
+            _ => None,
+        }
+    }
+
+    /// Whether support for this format was compiled in. Everything except
+    /// PNG and JPEG sits behind a cargo feature of the same name. Custom
+    /// formats are enabled once registered.
+    pub fn is_enabled(self) -> bool {
+        match self {
+            FormatType::PNG | FormatType::JPEG => true,
+            FormatType::AVIF => cfg!(feature = "avif"),
+            FormatType::WEBP => cfg!(feature = "webp"),
+            FormatType::TIFF => cfg!(feature = "tiff"),
+            FormatType::ICO => cfg!(feature = "ico"),
+            FormatType::HEIF => cfg!(feature = "heif"),
+            FormatType::GIF => cfg!(feature = "gif"),
+            FormatType::PNM => cfg!(feature = "pnm"),
+            FormatType::JXL => cfg!(feature = "jxl"),
+            FormatType::Custom(name) => registry::lookup(name).is_some(),
+        }
+    }
+
+    /// The known format whose name or extension is within a typo of `name`,
+    /// e.g. `jpeg` for `jpge`. Longer names may be off by two edits.
+    pub fn suggest(name: &str) -> Option<FormatType> {
+        let name = name.trim().to_lowercase();
+        let allowed = if name.chars().count() <= 4 { 1 } else { 2 };
+        FormatType::all()
+            .into_iter()
+            .map(|f| {
+                let distance =
+                    edit_distance(&name, f.name()).min(edit_distance(&name, f.extension()));
+                (distance, f)
+            })
+            .filter(|&(distance, _)| distance <= allowed)
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(_, f)| f)
+    }
+
+    /// The built-in format with this name or extension.
+    pub(crate) fn builtin(name: &str) -> Option<FormatType> {
+        match name.to_lowercase().as_str() {
+            "png" => Some(FormatType::PNG),
+            "jpg" | "jpeg" => Some(FormatType::JPEG),
+            "avif" => Some(FormatType::AVIF),
+            "webp" => Some(FormatType::WEBP),
+            "tif" | "tiff" => Some(FormatType::TIFF),
+            "ico" => Some(FormatType::ICO),
+            "heic" | "heif" => Some(FormatType::HEIF),
+            "gif" => Some(FormatType::GIF),
+            "pnm" | "ppm" | "pgm" | "pbm" => Some(FormatType::PNM),
+            "jxl" => Some(FormatType::JXL),
+            _ => None,
+        }
+    }
+}
+
+// Levenshtein distance that counts swapping two neighbors as one edit, the
+// most common typo in short names.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+impl fmt::Display for FormatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses a format name or extension, case-insensitive. `jpg` and `jpeg` are
+/// equivalent. Registered formats are found by their name and extensions.
+impl FromStr for FormatType {
+    type Err = FormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim();
+        registry::find(name).ok_or_else(|| FormatError::UnknownFormat(s.to_string()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for FormatType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for FormatType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::derive_output_path;
+    use std::path::Path;
+
+    #[test]
+    fn every_builtin_is_listed_once_and_round_trips() {
+        for (i, format) in FormatType::ALL.into_iter().enumerate() {
+            assert!(
+                !FormatType::ALL[..i].contains(&format),
+                "{format} listed twice"
+            );
+            assert_eq!(FormatType::builtin(format.name()), Some(format));
+            assert_eq!(FormatType::builtin(format.extension()), Some(format));
+        }
+        // No wildcard: a new variant stops this compiling until it is sorted
+        // into the built-ins above or handled like custom formats.
+        let builtin = |format: FormatType| match format {
+            FormatType::PNG
+            | FormatType::JPEG
+            | FormatType::AVIF
+            | FormatType::WEBP
+            | FormatType::TIFF
+            | FormatType::ICO
+            | FormatType::HEIF
+            | FormatType::GIF
+            | FormatType::PNM
+            | FormatType::JXL => true,
+            FormatType::Custom(_) => false,
+        };
+        assert_eq!(
+            FormatType::ALL.into_iter().filter(|&f| builtin(f)).count(),
+            FormatType::ALL.len()
+        );
+        for alias in ["jpg", "tif", "heic", "ppm", "pgm", "pbm"] {
+            let format = FormatType::builtin(alias).unwrap();
+            assert!(
+                FormatType::ALL.contains(&format),
+                "{alias} maps outside ALL"
+            );
+        }
+    }
+
+    #[test]
+    fn sniffs_magic_bytes_not_names() {
+        assert_eq!(
+            FormatType::sniff(b"\x89PNG\r\n\x1a\n...."),
+            Some(FormatType::PNG)
+        );
+        assert_eq!(
+            FormatType::sniff(b"\xff\xd8\xff\xe0...."),
+            Some(FormatType::JPEG)
+        );
+        assert_eq!(
+            FormatType::sniff(b"\0\0\0\x18ftypheic"),
+            Some(FormatType::HEIF)
+        );
+        assert_eq!(FormatType::sniff(b"\xff\x0a...."), Some(FormatType::JXL));
+        assert_eq!(FormatType::sniff(b"plain text"), None);
+    }
+
+    #[test]
+    fn suggests_only_close_names() {
+        assert_eq!(FormatType::suggest("jpge"), Some(FormatType::JPEG));
+        assert_eq!(FormatType::suggest("PGN"), Some(FormatType::PNG));
+        assert_eq!(FormatType::suggest("jpegg"), Some(FormatType::JPEG));
+        assert_eq!(FormatType::suggest("xcf"), None);
+        assert_eq!(FormatType::suggest("mp3"), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn jpg_and_jpeg_are_equivalent() {
+        let jpg: FormatType = "jpg".parse().unwrap();
+        let jpeg: FormatType = "JPEG".parse().unwrap();
+        assert_eq!(jpg, jpeg);
+        assert_eq!(jpg.name(), "jpeg");
+
+        let input = Path::new("photos/cat.png");
+        assert_eq!(derive_output_path(input, jpg), Path::new("photos/cat.jpg"));
+        assert_eq!(derive_output_path(input, jpeg), Path::new("photos/cat.jpg"));
+    }
+
+    #[test]
+    fn extensions_map_to_formats() {
+        assert_eq!(FormatType::from_extension("JPG").unwrap(), FormatType::JPEG);
+        assert_eq!(
+            FormatType::from_extension(".jpeg").unwrap(),
+            FormatType::JPEG
+        );
+        assert_eq!(FormatType::from_extension("png").unwrap(), FormatType::PNG);
+        assert!(matches!(
+            FormatType::from_extension("docx"),
+            Err(FormatError::UnsupportedInput(ext)) if ext == "docx"
+        ));
+    }
+
+    #[test]
+    fn mime_types_map_to_formats() {
+        assert_eq!(FormatType::from_mime("image/png").unwrap(), FormatType::PNG);
+        assert_eq!(
+            FormatType::from_mime("Image/JPEG; q=0.9").unwrap(),
+            FormatType::JPEG
+        );
+        assert_eq!(
+            FormatType::from_mime("image/x-icon").unwrap(),
+            FormatType::ICO
+        );
+        assert!(matches!(
+            FormatType::from_mime("application/pdf"),
+            Err(FormatError::UnsupportedInput(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_format_is_rejected() {
+        assert!(matches!(
+            "bmpx".parse::<FormatType>(),
+            Err(FormatError::UnknownFormat(_))
+        ));
+    }
 }
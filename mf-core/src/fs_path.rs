@@ -0,0 +1,77 @@
+use std::{borrow::Cow, path::Path};
+
+/// `path` as it should be handed to filesystem calls.
+///
+/// On Windows, paths of `MAX_PATH` (260) characters or more get the
+/// extended-length prefix `\\?\`, or `\\?\UNC\` for network shares, so deep
+/// directory trees work without the system-wide long path setting. Short
+/// paths, and every path on other platforms, are returned unchanged.
+pub(crate) fn fs_path(path: &Path) -> Cow<'_, Path> {
+    #[cfg(windows)]
+    if path.as_os_str().len() >= MAX_PATH {
+        // the prefix turns off normalization, so the path must be absolute first
+        let extended = std::path::absolute(path)
+            .ok()
+            .and_then(|abs| abs.to_str().and_then(extended_length));
+        if let Some(extended) = extended {
+            return Cow::Owned(extended.into());
+        }
+    }
+    Cow::Borrowed(path)
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+const MAX_PATH: usize = 260;
+
+// Prefixes an absolute Windows path. None for relative paths and for paths
+// that already are verbatim or device paths.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn extended_length(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return None;
+    }
+    if let Some(share) = path.strip_prefix(r"\\") {
+        return Some(format!(r"\\?\UNC\{share}"));
+    }
+    let drive = path.as_bytes();
+    if drive.len() >= 3 && drive[0].is_ascii_alphabetic() && &drive[1..3] == br":\" {
+        return Some(format!(r"\\?\{path}"));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deep(root: &str) -> String {
+        format!(r"{root}{}photo.png", r"nested-directory\".repeat(20))
+    }
+
+    #[test]
+    fn long_drive_and_unc_paths_get_the_prefix() {
+        let local = deep(r"C:\");
+        assert!(local.len() > MAX_PATH);
+        assert_eq!(extended_length(&local), Some(format!(r"\\?\{local}")));
+
+        let unc = deep(r"\\server\share\");
+        assert_eq!(
+            extended_length(&unc),
+            Some(format!(r"\\?\UNC\{}", &unc[2..]))
+        );
+    }
+
+    #[test]
+    fn prefixed_and_relative_paths_are_left_alone() {
+        assert_eq!(extended_length(&deep(r"\\?\C:\")), None);
+        assert_eq!(extended_length(&deep(r"\\.\pipe\")), None);
+        assert_eq!(extended_length(&deep("")), None);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn other_platforms_keep_the_path() {
+        let long = deep("/");
+        assert!(matches!(fs_path(Path::new(&long)), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,61 @@
+//! HEIF/HEIC decoding through libheif. Input only, nothing is encoded to HEIF.
+
+use std::{fmt, path::Path};
+
+use image::{DynamicImage, RgbImage, RgbaImage};
+use libheif_rs::{ColorSpace, HeifContext, HeifError, LibHeif, RgbChroma};
+
+use crate::{
+    error::{ConversionError, IoError, MeltforgeError},
+    fs_path::fs_path,
+};
+
+pub(crate) fn read_file(path: &Path) -> Result<HeifContext<'static>, MeltforgeError> {
+    // libheif takes the file name as a C string
+    let name = fs_path(path);
+    let name = name
+        .to_str()
+        .ok_or_else(|| IoError::ReadError(path.to_path_buf()))?;
+    let ctx = HeifContext::read_from_file(name).map_err(|e| open_error(path.display(), e))?;
+    Ok(ctx)
+}
+
+pub(crate) fn open_error(input: impl fmt::Display, e: HeifError) -> ConversionError {
+    ConversionError::ExecutionFailed(format!("open {input}: {e}"))
+}
+
+/// Size of the primary image, read from the container without decoding it.
+pub(crate) fn dimensions(ctx: &HeifContext) -> Result<(u32, u32), HeifError> {
+    let handle = ctx.primary_image_handle()?;
+    Ok((handle.width(), handle.height()))
+}
+
+/// Decodes the primary image to 8-bit RGB(A). libheif applies the rotation
+/// and mirroring stored in the file, so the result is already upright.
+pub(crate) fn decode_primary(ctx: &HeifContext) -> Result<DynamicImage, HeifError> {
+    let handle = ctx.primary_image_handle()?;
+    let alpha = handle.has_alpha_channel();
+    let chroma = if alpha {
+        RgbChroma::Rgba
+    } else {
+        RgbChroma::Rgb
+    };
+    let image = LibHeif::new().decode(&handle, ColorSpace::Rgb(chroma), None)?;
+    let planes = image.planes();
+    let plane = planes
+        .interleaved
+        .expect("interleaved RGB(A) was requested");
+
+    // rows may be padded, copy them without the stride padding
+    let row = plane.width as usize * if alpha { 4 } else { 3 };
+    let mut pixels = Vec::with_capacity(row * plane.height as usize);
+    for y in 0..plane.height as usize {
+        pixels.extend_from_slice(&plane.data[y * plane.stride..][..row]);
+    }
+    let (width, height) = (plane.width, plane.height);
+    Ok(if alpha {
+        DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, pixels).expect("full rows"))
+    } else {
+        DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, pixels).expect("full rows"))
+    })
+}
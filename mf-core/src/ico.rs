@@ -0,0 +1,89 @@
+use std::{fmt, str::FromStr};
+
+use crate::error::InputError;
+
+/// Square icon sizes packed into an ICO file, written as `16,32,48`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IcoSizes(pub Vec<u32>);
+
+impl Default for IcoSizes {
+    fn default() -> Self {
+        IcoSizes(vec![16, 32, 48])
+    }
+}
+
+impl fmt::Display for IcoSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sizes: Vec<String> = self.0.iter().map(u32::to_string).collect();
+        f.write_str(&sizes.join(","))
+    }
+}
+
+impl FromStr for IcoSizes {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            InputError::InvalidArgument(format!(
+                "invalid icon sizes '{s}' (comma separated, 1-256, e.g. 16,32,48)"
+            ))
+        };
+        let mut sizes = s
+            .split(',')
+            .map(|part| match part.trim().parse::<u32>() {
+                // the ICO directory stores each side in one byte, 0 meaning 256
+                Ok(size) if (1..=256).contains(&size) => Ok(size),
+                _ => Err(invalid()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        sizes.sort_unstable();
+        sizes.dedup();
+        Ok(IcoSizes(sizes))
+    }
+}
+
+/// Packs one downscaled copy of `img` per size. Non-square images are fit
+/// inside the square and padded with transparency.
+#[cfg(feature = "ico")]
+pub(crate) fn encode_ico(
+    img: &image::DynamicImage,
+    sizes: &IcoSizes,
+) -> image::ImageResult<Vec<u8>> {
+    use image::{
+        codecs::ico::{IcoEncoder, IcoFrame},
+        imageops::{self, FilterType},
+        ExtendedColorType, RgbaImage,
+    };
+
+    let frames = sizes
+        .0
+        .iter()
+        .map(|&size| {
+            let icon = img.resize(size, size, FilterType::Lanczos3).to_rgba8();
+            let mut canvas = RgbaImage::new(size, size);
+            let x = (size - icon.width()) / 2;
+            let y = (size - icon.height()) / 2;
+            imageops::overlay(&mut canvas, &icon, x.into(), y.into());
+            IcoFrame::as_png(canvas.as_raw(), size, size, ExtendedColorType::Rgba8)
+        })
+        .collect::<image::ImageResult<Vec<_>>>()?;
+
+    let mut buf = Vec::new();
+    IcoEncoder::new(&mut buf).encode_images(&frames)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sorted_unique_sizes() {
+        let sizes: IcoSizes = "48, 16,32,16".parse().unwrap();
+        assert_eq!(sizes, IcoSizes(vec![16, 32, 48]));
+        assert!("0,16".parse::<IcoSizes>().is_err());
+        assert!("512".parse::<IcoSizes>().is_err());
+        assert!("16,,32".parse::<IcoSizes>().is_err());
+    }
+}
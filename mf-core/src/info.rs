@@ -0,0 +1,205 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use image::{ColorType, ImageDecoder, ImageReader};
+
+use crate::{
+    error::{ConversionError, InputError, IoError, MeltforgeError},
+    format::FormatType,
+    fs_path::fs_path,
+    registry,
+    validate::{detect_input_format, ensure_enabled},
+};
+
+/// Properties of an image file, read from its header without decoding the pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ImageInfo {
+    pub path: PathBuf,
+    pub format: FormatType,
+    pub width: u32,
+    pub height: u32,
+    /// `gray`, `gray+alpha`, `rgb` or `rgba`.
+    pub color: &'static str,
+    /// Bits per channel.
+    pub bit_depth: u16,
+    pub file_size: u64,
+}
+
+/// Reads the properties of `path`. The format is detected from the extension
+/// unless given, like the input format of a conversion.
+pub fn inspect(path: &Path, format: Option<FormatType>) -> Result<ImageInfo, MeltforgeError> {
+    let file_size = fs::metadata(fs_path(path))
+        .map_err(|_| InputError::MissingInputFile(path.to_path_buf()))?
+        .len();
+    let format = match format {
+        Some(fmt) => {
+            ensure_enabled(fmt)?;
+            fmt
+        }
+        None => detect_input_format(path)?,
+    };
+
+    let (width, height, color, bit_depth) = read_header(path, format)?;
+    Ok(ImageInfo {
+        path: path.to_path_buf(),
+        format,
+        width,
+        height,
+        color,
+        bit_depth,
+        file_size,
+    })
+}
+
+/// Width and height of `path`, read from its header without decoding the
+/// pixels. The format is detected from the extension.
+pub fn probe_dimensions(path: &Path) -> Result<(u32, u32), MeltforgeError> {
+    if !fs_path(path).is_file() {
+        return Err(InputError::MissingInputFile(path.to_path_buf()).into());
+    }
+    dimensions(path, detect_input_format(path)?)
+}
+
+pub(crate) fn dimensions(path: &Path, format: FormatType) -> Result<(u32, u32), MeltforgeError> {
+    #[cfg(feature = "heif")]
+    if format == FormatType::HEIF {
+        let ctx = crate::heif::read_file(path)?;
+        let size = crate::heif::dimensions(&ctx)
+            .map_err(|e| crate::heif::open_error(path.display(), e))?;
+        return Ok(size);
+    }
+    #[cfg(feature = "jxl")]
+    if format == FormatType::JXL {
+        let data = fs::read(fs_path(path)).map_err(|_| IoError::ReadError(path.to_path_buf()))?;
+        let size =
+            crate::jxl::dimensions(&data).map_err(|e| ConversionError::image(path.display(), e))?;
+        return Ok(size);
+    }
+    if let FormatType::Custom(name) = format {
+        let data = fs::read(fs_path(path)).map_err(|_| IoError::ReadError(path.to_path_buf()))?;
+        let size = registry::dimensions(name, &data)
+            .map_err(|e| ConversionError::image(path.display(), e))?;
+        return Ok(size);
+    }
+    let mut reader =
+        ImageReader::open(fs_path(path)).map_err(|_| IoError::ReadError(path.to_path_buf()))?;
+    reader.set_format(
+        format
+            .image_format()
+            .expect("HEIF, JPEG XL and custom formats are read above"),
+    );
+    let size = reader
+        .into_dimensions()
+        .map_err(|e| ConversionError::image(path.display(), e))?;
+    Ok(size)
+}
+
+type Header = (u32, u32, &'static str, u16);
+
+fn read_header(path: &Path, format: FormatType) -> Result<Header, MeltforgeError> {
+    #[cfg(feature = "heif")]
+    if format == FormatType::HEIF {
+        let ctx = crate::heif::read_file(path)?;
+        let handle = ctx
+            .primary_image_handle()
+            .map_err(|e| crate::heif::open_error(path.display(), e))?;
+        let color = if handle.has_alpha_channel() {
+            "rgba"
+        } else {
+            "rgb"
+        };
+        let depth = handle.luma_bits_per_pixel().into();
+        return Ok((handle.width(), handle.height(), color, depth));
+    }
+    // registered formats only offer a full decode, and so does JPEG XL for
+    // the color type
+    if matches!(format, FormatType::Custom(_) | FormatType::JXL) {
+        let data = fs::read(fs_path(path)).map_err(|_| IoError::ReadError(path.to_path_buf()))?;
+        let img = match format {
+            #[cfg(feature = "jxl")]
+            FormatType::JXL => crate::jxl::decode(&data),
+            FormatType::Custom(name) => registry::decode(name, &data),
+            _ => unreachable!("matched above"),
+        }
+        .map_err(|e| ConversionError::image(path.display(), e))?;
+        let color_type = img.color();
+        return Ok((
+            img.width(),
+            img.height(),
+            color_name(color_type),
+            bit_depth(color_type),
+        ));
+    }
+
+    let mut reader =
+        ImageReader::open(fs_path(path)).map_err(|_| IoError::ReadError(path.to_path_buf()))?;
+    reader.set_format(
+        format
+            .image_format()
+            .expect("HEIF, JPEG XL and custom formats are read above"),
+    );
+    let decoder = reader
+        .into_decoder()
+        .map_err(|e| ConversionError::image(path.display(), e))?;
+    let (width, height) = decoder.dimensions();
+    let color_type = decoder.color_type();
+    Ok((width, height, color_name(color_type), bit_depth(color_type)))
+}
+
+fn color_name(color: ColorType) -> &'static str {
+    match (color.has_color(), color.has_alpha()) {
+        (false, false) => "gray",
+        (false, true) => "gray+alpha",
+        (true, false) => "rgb",
+        (true, true) => "rgba",
+    }
+}
+
+fn bit_depth(color: ColorType) -> u16 {
+    color.bits_per_pixel() / u16::from(color.channel_count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_size_color_and_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gray.png");
+        image::ImageBuffer::<image::Luma<u16>, _>::new(12, 7)
+            .save(&path)
+            .unwrap();
+
+        let info = inspect(&path, None).unwrap();
+
+        assert_eq!(info.format, FormatType::PNG);
+        assert_eq!((info.width, info.height), (12, 7));
+        assert_eq!((info.color, info.bit_depth), ("gray", 16));
+        assert_eq!(info.file_size, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn probes_dimensions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wide.png");
+        image::RgbImage::new(30, 20).save(&path).unwrap();
+
+        assert_eq!(probe_dimensions(&path).unwrap(), (30, 20));
+        assert!(matches!(
+            probe_dimensions(&dir.path().join("missing.png")),
+            Err(MeltforgeError::Input(InputError::MissingInputFile(_)))
+        ));
+    }
+
+    #[test]
+    fn missing_file_is_reported() {
+        assert!(matches!(
+            inspect(Path::new("does/not/exist.png"), None),
+            Err(MeltforgeError::Input(InputError::MissingInputFile(_)))
+        ));
+    }
+}
@@ -1,7 +1,323 @@
-use crate::format::FormatType;
-use std::path::PathBuf;
+use crate::{
+    color::{BitDepth, Channels, Color},
+    density::PrintSize,
+    format::FormatType,
+    ico::IcoSizes,
+    jpeg::ChromaSubsampling,
+    output::ConflictPolicy,
+    transform::{
+        Crop, Dimensions, Flip, Rotation, Scale, Thumbnail, DEFAULT_MAX_OUTPUT_DIMENSIONS,
+        DEFAULT_MAX_PIXELS,
+    },
+};
+use std::{
+    ops::AddAssign,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConvertJob {
     pub input: PathBuf,
     pub output: Option<PathBuf>,
+    #[cfg_attr(feature = "serde", serde(alias = "to"))]
     pub format_type: FormatType,
+    /// Overrides input format detection from the file extension.
+    #[cfg_attr(feature = "serde", serde(alias = "from"))]
+    pub input_format: Option<FormatType>,
+    /// Permit re-encoding into the input's own format, e.g. to recompress it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub allow_same_format: bool,
+    /// Replace the input with the result; implies `allow_same_format`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub in_place: bool,
+    /// How the image is transformed and encoded. Serialized inline, next to
+    /// the other job fields.
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub options: ConvertOptions,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub create_dirs: bool,
+    /// Probe each output directory for writability only once per process
+    /// instead of once per job. Existing outputs are still detected.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub reuse_dir_probe: bool,
+    /// Inputs with more pixels than this are rejected before decoding.
+    #[cfg_attr(feature = "serde", serde(default = "default_max_pixels"))]
+    pub max_pixels: u64,
+    /// Decode inputs over `max_pixels` at reduced size where the format allows it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub retry_on_oom: bool,
+    /// Threads for decoding JPEG inputs; other formats always decode on one.
+    pub decode_threads: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub verify: bool,
+    /// Times a failed output write is retried, with exponential backoff.
+    /// Only plain write errors are retried, not e.g. permission denied.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub write_retries: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub on_conflict: ConflictPolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub all_frames: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub premultiply_check: bool,
+    /// Copy the input's access and modification times onto the outputs.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preserve_timestamps: bool,
+    /// Copy the input's Unix mode bits onto the outputs. Does nothing elsewhere.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preserve_permissions: bool,
+    /// Fail on the first warning instead of reporting it in the outcome.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub strict: bool,
+}
+
+/// How an image is transformed and encoded, independent of where it is read
+/// from and written to. Shared by `convert`, `convert_in_memory` and `encode_image`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConvertOptions {
+    pub quality: Option<u8>,
+    /// Icon sizes packed into ICO outputs.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ico_sizes: IcoSizes,
+    pub print_size: Option<PrintSize>,
+    /// Resolution written into the output, taking precedence over `print_size`.
+    pub dpi: Option<u16>,
+    /// Chroma subsampling of JPEG outputs, 4:4:4 when unset.
+    pub subsampling: Option<ChromaSubsampling>,
+    /// Write progressive JPEGs, which browsers render in passes of
+    /// increasing detail, instead of baseline ones.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub progressive: bool,
+    /// Text written into JPEG outputs as a COM segment.
+    pub jpeg_comment: Option<String>,
+    pub scale: Option<Scale>,
+    /// Shrink to fit within these dimensions, keeping the aspect ratio.
+    /// Smaller images are not enlarged.
+    pub max_size: Option<Dimensions>,
+    /// Apply the EXIF orientation of JPEG inputs before any other transform.
+    #[cfg_attr(feature = "serde", serde(default = "default_auto_orient"))]
+    pub auto_orient: bool,
+    /// Keep only this region of the upright input, before every other transform.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub crop: Option<Crop>,
+    /// Applied before `flip` and every other transform but `crop`.
+    pub rotate: Option<Rotation>,
+    pub flip: Option<Flip>,
+    /// Crop to the bounding box of the non-background pixels before scaling.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub autocrop: bool,
+    /// Color transparent areas are flattened onto for formats without alpha.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub background: Color,
+    #[cfg_attr(feature = "serde", serde(default = "default_max_output_dimensions"))]
+    pub max_output_dimensions: Dimensions,
+    /// Write 8-bit RGBA (RGB without alpha support) instead of keeping the
+    /// input's color type, e.g. grayscale.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub force_rgba: bool,
+    /// Channel layout of the output, dropping alpha onto `background` or
+    /// adding an opaque one. Takes precedence over `force_rgba`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub channels: Option<Channels>,
+    /// Encode WebP outputs losslessly. This is currently the only WebP mode,
+    /// so the flag only states the intent and makes `quality` a warning.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub webp_lossless: bool,
+    /// Encode JPEG XL outputs losslessly instead of at `quality`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub jxl_lossless: bool,
+    /// Bits per channel of the output. Unset keeps the input's depth where
+    /// the target can store it, so 16-bit PNGs stay 16-bit.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bit_depth: Option<BitDepth>,
+    /// Gray level from which a pixel of a 1-bit output is white,
+    /// [`DEFAULT_THRESHOLD`](crate::bilevel::DEFAULT_THRESHOLD) when unset.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub threshold: Option<u8>,
+    /// Make a square thumbnail, after every other transform.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub thumbnail: Option<Thumbnail>,
+    /// Pin encoder settings that could vary between runs, so the same input
+    /// always gives the same bytes. See [`FormatType::is_reproducible`].
+    ///
+    /// [`FormatType::is_reproducible`]: crate::format::FormatType::is_reproducible
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub reproducible: bool,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            quality: None,
+            ico_sizes: IcoSizes::default(),
+            print_size: None,
+            dpi: None,
+            subsampling: None,
+            progressive: false,
+            jpeg_comment: None,
+            scale: None,
+            max_size: None,
+            auto_orient: true,
+            crop: None,
+            rotate: None,
+            flip: None,
+            autocrop: false,
+            background: Color::WHITE,
+            max_output_dimensions: DEFAULT_MAX_OUTPUT_DIMENSIONS,
+            force_rgba: false,
+            channels: None,
+            webp_lossless: false,
+            jxl_lossless: false,
+            thumbnail: None,
+            bit_depth: None,
+            threshold: None,
+            reproducible: false,
+        }
+    }
+}
+
+/// Result of a successful conversion.
+#[derive(Debug, Clone)]
+pub struct ConvertOutcome {
+    /// Every file written; one per frame when converting all frames of an animation.
+    pub outputs: Vec<PathBuf>,
+    /// Non-fatal issues, e.g. frames that were dropped.
+    pub warnings: Vec<String>,
+    pub timings: Timings,
+    /// Pixels decoded from the input, summed over all frames.
+    pub pixels: u64,
+    /// Memory held by the decoded frames (pixels times bytes per pixel), the
+    /// bulk of a conversion's footprint; transforms and encoding add copies.
+    pub decoded_bytes: u64,
+    /// Nothing was converted because the output already existed
+    /// (`ConflictPolicy::Skip`); `outputs` holds that existing file.
+    pub skipped: bool,
+    /// One line per output write that failed and was retried.
+    pub retries: Vec<String>,
+}
+
+/// A step of a conversion that just finished, reported to the callback of
+/// [`convert_with_events`](crate::convert::convert_with_events).
+///
+/// Validation and decoding name the input; the later stages name the output
+/// and repeat for every frame written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConvertStage {
+    Validated(PathBuf),
+    Decoded(PathBuf),
+    Transformed(PathBuf),
+    Encoded(PathBuf),
+    Written(PathBuf),
+}
+
+impl ConvertStage {
+    pub fn path(&self) -> &Path {
+        match self {
+            ConvertStage::Validated(p)
+            | ConvertStage::Decoded(p)
+            | ConvertStage::Transformed(p)
+            | ConvertStage::Encoded(p)
+            | ConvertStage::Written(p) => p,
+        }
+    }
+
+    /// Lowercase name of the stage, e.g. `decoded`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConvertStage::Validated(_) => "validated",
+            ConvertStage::Decoded(_) => "decoded",
+            ConvertStage::Transformed(_) => "transformed",
+            ConvertStage::Encoded(_) => "encoded",
+            ConvertStage::Written(_) => "written",
+        }
+    }
+}
+
+/// Wall-clock time spent in each stage of a conversion, summed over all frames.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    pub decode: Duration,
+    /// One entry per applied transform, in the order they ran.
+    pub transforms: Vec<(&'static str, Duration)>,
+    pub encode: Duration,
+    /// Writing (and with `verify`, re-reading) the output files.
+    pub write: Duration,
+    /// The whole conversion, including validation.
+    pub total: Duration,
+}
+
+impl Timings {
+    pub(crate) fn add_transform(&mut self, name: &'static str, elapsed: Duration) {
+        match self.transforms.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, total)) => *total += elapsed,
+            None => self.transforms.push((name, elapsed)),
+        }
+    }
+
+    /// Sum of the individual stages; `total` minus this is validation and bookkeeping.
+    pub fn stages(&self) -> Duration {
+        self.decode
+            + self.transforms.iter().map(|(_, d)| *d).sum::<Duration>()
+            + self.encode
+            + self.write
+    }
+}
+
+/// Adds up the stages of several conversions, e.g. for a batch. Transforms
+/// of the same name are summed into one entry.
+impl AddAssign<&Timings> for Timings {
+    fn add_assign(&mut self, other: &Timings) {
+        self.decode += other.decode;
+        for (name, elapsed) in &other.transforms {
+            self.add_transform(name, *elapsed);
+        }
+        self.encode += other.encode;
+        self.write += other.write;
+        self.total += other.total;
+    }
+}
+
+impl ConvertJob {
+    /// Job with the derived output path and default options.
+    pub fn new(input: impl Into<PathBuf>, format_type: FormatType) -> Self {
+        ConvertJob {
+            input: input.into(),
+            output: None,
+            format_type,
+            input_format: None,
+            allow_same_format: false,
+            in_place: false,
+            options: ConvertOptions::default(),
+            create_dirs: false,
+            reuse_dir_probe: false,
+            max_pixels: DEFAULT_MAX_PIXELS,
+            retry_on_oom: false,
+            decode_threads: None,
+            verify: false,
+            write_retries: 0,
+            on_conflict: ConflictPolicy::default(),
+            all_frames: false,
+            premultiply_check: false,
+            preserve_timestamps: false,
+            preserve_permissions: false,
+            strict: false,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn default_max_output_dimensions() -> Dimensions {
+    DEFAULT_MAX_OUTPUT_DIMENSIONS
+}
+
+#[cfg(feature = "serde")]
+fn default_max_pixels() -> u64 {
+    DEFAULT_MAX_PIXELS
+}
+
+#[cfg(feature = "serde")]
+fn default_auto_orient() -> bool {
+    true
 }
@@ -0,0 +1,189 @@
+use std::{fmt, str::FromStr};
+
+use image::{
+    error::{EncodingError, ImageFormatHint},
+    DynamicImage, ImageError, ImageFormat,
+};
+use jpeg_encoder::{ColorType, Encoder, PixelDensity, PixelDensityUnit, SamplingFactor};
+
+use crate::{density::Dpi, error::InputError};
+
+/// Resolution of the color channels of a JPEG relative to its brightness.
+///
+/// Without a setting JPEGs are written by the `image` crate's encoder, which
+/// always keeps full color resolution, the same as `444`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChromaSubsampling {
+    /// Full color resolution, largest files.
+    #[cfg_attr(feature = "serde", serde(rename = "444"))]
+    S444,
+    /// Half the horizontal color resolution.
+    #[cfg_attr(feature = "serde", serde(rename = "422"))]
+    S422,
+    /// Half the horizontal and vertical color resolution, smallest files.
+    #[cfg_attr(feature = "serde", serde(rename = "420"))]
+    S420,
+}
+
+impl fmt::Display for ChromaSubsampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChromaSubsampling::S444 => "444",
+            ChromaSubsampling::S422 => "422",
+            ChromaSubsampling::S420 => "420",
+        })
+    }
+}
+
+/// Parses `444`, `422` or `420`, also written as `4:4:4` etc.
+impl FromStr for ChromaSubsampling {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().replace(':', "").as_str() {
+            "444" => Ok(ChromaSubsampling::S444),
+            "422" => Ok(ChromaSubsampling::S422),
+            "420" => Ok(ChromaSubsampling::S420),
+            _ => Err(InputError::InvalidArgument(format!(
+                "invalid chroma subsampling '{s}' (expected 444, 422 or 420)"
+            ))),
+        }
+    }
+}
+
+/// Encodes a grayscale or RGB image as JPEG with the given subsampling,
+/// progressive or baseline. Grayscale images have no color channels, so the
+/// subsampling does not apply.
+pub(crate) fn encode_jpeg(
+    img: &DynamicImage,
+    quality: u8,
+    subsampling: ChromaSubsampling,
+    progressive: bool,
+    dpi: Option<Dpi>,
+) -> image::ImageResult<Vec<u8>> {
+    let (width, height) = match (u16::try_from(img.width()), u16::try_from(img.height())) {
+        (Ok(w), Ok(h)) => (w, h),
+        _ => {
+            return Err(encoding_error(
+                "image is larger than 65535 pixels, the JPEG limit",
+            ))
+        }
+    };
+    let mut buf = Vec::new();
+    let mut encoder = Encoder::new(&mut buf, quality);
+    encoder.set_sampling_factor(match subsampling {
+        ChromaSubsampling::S444 => SamplingFactor::R_4_4_4,
+        ChromaSubsampling::S422 => SamplingFactor::R_4_2_2,
+        ChromaSubsampling::S420 => SamplingFactor::R_4_2_0,
+    });
+    encoder.set_progressive(progressive);
+    if let Some(dpi) = dpi {
+        encoder.set_density(PixelDensity {
+            density: (dpi.x, dpi.y),
+            unit: PixelDensityUnit::Inches,
+        });
+    }
+    let result = match img {
+        DynamicImage::ImageLuma8(gray) => encoder.encode(gray, width, height, ColorType::Luma),
+        _ => encoder.encode(&img.to_rgb8(), width, height, ColorType::Rgb),
+    };
+    result.map_err(encoding_error)?;
+    Ok(buf)
+}
+
+/// Longest comment a JPEG COM segment holds, its length field included.
+pub const MAX_COMMENT_BYTES: usize = u16::MAX as usize - 2;
+
+/// Adds a COM segment holding `text` right after the APPn headers (JFIF,
+/// EXIF), where tools like `exiftool` and `identify` read it from. Callers
+/// keep `text` within [`MAX_COMMENT_BYTES`].
+pub(crate) fn insert_comment(jpeg: &mut Vec<u8>, text: &str) {
+    // SOI, then segments of a marker and a big-endian length that counts itself
+    let mut at = 2;
+    while jpeg.len() >= at + 4 && jpeg[at] == 0xFF && (0xE0..=0xEF).contains(&jpeg[at + 1]) {
+        at += 2 + usize::from(u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]));
+    }
+    let mut segment = vec![0xFF, 0xFE];
+    segment.extend((text.len() as u16 + 2).to_be_bytes());
+    segment.extend(text.as_bytes());
+    jpeg.splice(at.min(jpeg.len())..at.min(jpeg.len()), segment);
+}
+
+fn encoding_error(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> ImageError {
+    ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(ImageFormat::Jpeg),
+        e,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_with_and_without_colons() {
+        assert_eq!(
+            "420".parse::<ChromaSubsampling>().unwrap(),
+            ChromaSubsampling::S420
+        );
+        assert_eq!(
+            "4:2:2".parse::<ChromaSubsampling>().unwrap(),
+            ChromaSubsampling::S422
+        );
+        assert!(matches!(
+            "411".parse::<ChromaSubsampling>(),
+            Err(InputError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn comment_follows_the_app_headers() {
+        let img = DynamicImage::new_rgb8(8, 8);
+        let mut jpeg = encode_jpeg(&img, 90, ChromaSubsampling::S444, false, None).unwrap();
+        insert_comment(&mut jpeg, "pipeline 42");
+
+        // SOI, the JFIF APP0 segment, then the comment
+        let app0 = 2 + usize::from(u16::from_be_bytes([jpeg[4], jpeg[5]]));
+        assert_eq!(&jpeg[app0 + 2..app0 + 4], [0xFF, 0xFE]);
+        assert_eq!(&jpeg[app0 + 4..app0 + 6], 13u16.to_be_bytes());
+        assert_eq!(&jpeg[app0 + 6..app0 + 17], b"pipeline 42");
+        assert_eq!(image::load_from_memory(&jpeg).unwrap().width(), 8);
+    }
+
+    #[test]
+    fn subsampled_jpeg_is_smaller() {
+        // fine colored stripes, which 4:2:0 averages away
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, _| {
+            if x % 2 == 0 {
+                image::Rgb([255, 0, 0])
+            } else {
+                image::Rgb([0, 0, 255])
+            }
+        }));
+        let full = encode_jpeg(&img, 90, ChromaSubsampling::S444, false, None).unwrap();
+        let reduced = encode_jpeg(&img, 90, ChromaSubsampling::S420, false, None).unwrap();
+
+        assert!(reduced.len() < full.len());
+        let decoded = image::load_from_memory(&reduced).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 64));
+    }
+
+    #[test]
+    fn progressive_jpeg_starts_with_a_progressive_frame() {
+        // SOF0 marks a baseline frame, SOF2 a progressive one
+        let has_marker = |jpeg: &[u8], marker: u8| jpeg.windows(2).any(|w| w == [0xFF, marker]);
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(40, 24, |x, y| {
+            image::Rgb([(x * 6) as u8, (y * 10) as u8, 128])
+        }));
+
+        let baseline = encode_jpeg(&img, 80, ChromaSubsampling::S420, false, None).unwrap();
+        assert!(has_marker(&baseline, 0xC0) && !has_marker(&baseline, 0xC2));
+        for subsampling in [ChromaSubsampling::S444, ChromaSubsampling::S420] {
+            let progressive = encode_jpeg(&img, 80, subsampling, true, None).unwrap();
+            assert!(has_marker(&progressive, 0xC2) && !has_marker(&progressive, 0xC0));
+            let decoded = image::load_from_memory(&progressive).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (40, 24));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! JPEG XL decoding and encoding through libjxl.
+
+use image::{
+    error::{DecodingError, EncodingError, ImageFormatHint},
+    DynamicImage, ImageError, ImageResult,
+};
+use jpegxl_rs::{
+    decode::{Event, Events, Orientation},
+    decoder_builder,
+    encode::EncoderFrame,
+    encoder_builder,
+    image::ToDynamic,
+};
+
+fn hint() -> ImageFormatHint {
+    ImageFormatHint::Name("jxl".into())
+}
+
+fn decoding_error(e: impl std::error::Error + Send + Sync + 'static) -> ImageError {
+    ImageError::Decoding(DecodingError::new(hint(), e))
+}
+
+fn encoding_error(e: impl std::error::Error + Send + Sync + 'static) -> ImageError {
+    ImageError::Encoding(EncodingError::new(hint(), e))
+}
+
+/// Size of the image as displayed, read from the codestream header without
+/// decoding the pixels.
+pub(crate) fn dimensions(mut data: &[u8]) -> ImageResult<(u32, u32)> {
+    let mut decoder = decoder_builder().build().map_err(decoding_error)?;
+    let mut session = decoder
+        .session(Events::BASIC_INFO)
+        .map_err(decoding_error)?;
+    session.close_input();
+    loop {
+        match session.process(&mut data).map_err(decoding_error)? {
+            Event::BasicInfo(info) => {
+                // orientations from 5 on swap the axes
+                let transposed = info.orientation as u32 >= Orientation::Transpose as u32;
+                return Ok(if transposed {
+                    (info.ysize, info.xsize)
+                } else {
+                    (info.xsize, info.ysize)
+                });
+            }
+            Event::NeedMoreInput | Event::Success => {
+                return Err(decoding_error(jpegxl_rs::DecodeError::InternalError(
+                    "no header in the input",
+                )))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decodes a whole file. libjxl applies the stored orientation, so the
+/// result is already upright.
+pub(crate) fn decode(data: &[u8]) -> ImageResult<DynamicImage> {
+    let decoder = decoder_builder().build().map_err(decoding_error)?;
+    decoder
+        .decode_to_image(data)
+        .map_err(decoding_error)?
+        .ok_or_else(|| {
+            decoding_error(jpegxl_rs::DecodeError::InternalError(
+                "pixel layout has no matching image type",
+            ))
+        })
+}
+
+/// Encodes `img` as RGB(A), keeping 16-bit samples. `quality` follows the
+/// JPEG scale and is ignored when `lossless` is set.
+pub(crate) fn encode(img: &DynamicImage, quality: u8, lossless: bool) -> ImageResult<Vec<u8>> {
+    let alpha = img.color().has_alpha();
+    let wide = img.color().bytes_per_pixel() > img.color().channel_count();
+    let mut encoder = encoder_builder()
+        .has_alpha(alpha)
+        .lossless(lossless)
+        // lossless output has to keep the input's color space
+        .uses_original_profile(lossless)
+        .quality(distance(quality))
+        .build()
+        .map_err(encoding_error)?;
+    let channels = if alpha { 4 } else { 3 };
+    let (width, height) = (img.width(), img.height());
+    let encoded = match (alpha, wide) {
+        (false, false) => encoder.encode_frame(
+            &EncoderFrame::new(img.to_rgb8().as_raw()).num_channels(channels),
+            width,
+            height,
+        ),
+        (true, false) => encoder.encode_frame(
+            &EncoderFrame::new(img.to_rgba8().as_raw()).num_channels(channels),
+            width,
+            height,
+        ),
+        (false, true) => encoder.encode_frame(
+            &EncoderFrame::new(img.to_rgb16().as_raw()).num_channels(channels),
+            width,
+            height,
+        ),
+        (true, true) => encoder.encode_frame(
+            &EncoderFrame::new(img.to_rgba16().as_raw()).num_channels(channels),
+            width,
+            height,
+        ),
+    };
+    encoded.map_err(encoding_error)
+}
+
+// libjxl's mapping of a JPEG quality to its butteraugli distance, where 0 is
+// lossless and 1 visually lossless (quality 90).
+fn distance(quality: u8) -> f32 {
+    let q = f32::from(quality);
+    match quality {
+        100.. => 0.0,
+        30.. => 0.1 + (100.0 - q) * 0.09,
+        _ => 53.0 / 3000.0 * q * q - 23.0 / 20.0 * q + 25.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quality_maps_to_distance() {
+        assert_eq!(distance(100), 0.0);
+        assert!((distance(90) - 1.0).abs() < 1e-6);
+        assert!(distance(30) < distance(29));
+        assert!((distance(1) - 23.867_666).abs() < 1e-3);
+    }
+}
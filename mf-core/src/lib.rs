@@ -1,8 +1,27 @@
+pub mod analysis;
+pub mod batch;
+pub mod bilevel;
+pub mod color;
 pub mod convert;
+pub mod density;
 pub mod error;
 pub mod format;
+mod fs_path;
+#[cfg(feature = "heif")]
+mod heif;
+pub mod ico;
+pub mod info;
 pub mod job;
+pub mod jpeg;
+#[cfg(feature = "jxl")]
+mod jxl;
+pub mod output;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod registry;
+pub mod transform;
 pub mod validate;
+mod warning;
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
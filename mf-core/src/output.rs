@@ -0,0 +1,410 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{is_separator, Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{error::InputError, format::FormatType, fs_path::fs_path, job::ConvertJob};
+
+/// What to do when the output path already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum ConflictPolicy {
+    /// Leave the existing file alone and report an error.
+    #[default]
+    Fail,
+    /// Write to the first free `name-1.ext`, `name-2.ext`, ... instead.
+    Rename,
+    /// Leave the existing file alone and report the job as skipped.
+    Skip,
+    /// Replace the existing file.
+    Overwrite,
+}
+
+/// Output path of a job with its conflict policy applied.
+pub fn job_output_path(cj: &ConvertJob) -> PathBuf {
+    if cj.in_place {
+        return cj.input.clone();
+    }
+    let path = resolve_output_path(&cj.input, cj.output.as_deref(), cj.format_type);
+    match cj.on_conflict {
+        ConflictPolicy::Fail | ConflictPolicy::Skip | ConflictPolicy::Overwrite => path,
+        ConflictPolicy::Rename => next_free_path(path),
+    }
+}
+
+/// Final output path for a job. An `--output` that is an existing directory,
+/// or ends with a path separator, receives the derived file name.
+pub fn resolve_output_path(input: &Path, output: Option<&Path>, to: FormatType) -> PathBuf {
+    match output {
+        Some(dir) if is_directory_target(dir) => {
+            let derived = derive_output_path(input, to);
+            match derived.file_name() {
+                Some(name) => dir.join(name),
+                None => derived,
+            }
+        }
+        Some(out) => with_target_extension(out, to),
+        None => derive_output_path(input, to),
+    }
+}
+
+/// An output file named without an extension gets the one of its format:
+/// `-o result --to jpg` writes `result.jpg`. Other names are kept as given.
+pub fn with_target_extension(output: &Path, to: FormatType) -> PathBuf {
+    if output.extension().is_some() || output.file_name().is_none() {
+        return output.to_path_buf();
+    }
+    output.with_extension(to.extension())
+}
+
+pub fn is_directory_target(path: &Path) -> bool {
+    fs_path(path).is_dir() || path.to_string_lossy().ends_with(is_separator)
+}
+
+pub(crate) fn next_free_path(path: PathBuf) -> PathBuf {
+    if !fs_path(&path).exists() {
+        return path;
+    }
+    numbered_paths(&path)
+        .find(|candidate| !fs_path(candidate).exists())
+        .expect("unbounded suffix search")
+}
+
+// `name-1.ext`, `name-2.ext`, ... next to `path`.
+fn numbered_paths(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path.extension().map(|e| e.to_string_lossy().into_owned());
+
+    (1..).map(move |n| {
+        let name = match &ext {
+            Some(ext) => format!("{stem}-{n}.{ext}"),
+            None => format!("{stem}-{n}"),
+        };
+        path.with_file_name(name)
+    })
+}
+
+// Where a job of a batch will write, before any file exists. Renaming jobs
+// are left out, they move aside from each other's outputs when writing.
+fn planned_output(cj: &ConvertJob) -> Option<PathBuf> {
+    if cj.in_place {
+        return Some(cj.input.clone());
+    }
+    if cj.on_conflict == ConflictPolicy::Rename {
+        return None;
+    }
+    Some(resolve_output_path(
+        &cj.input,
+        cj.output.as_deref(),
+        cj.format_type,
+    ))
+}
+
+// What two outputs are compared by to tell whether they are the same file:
+// `out/./a.jpg` and `out/x/../a.jpg` are both `out/a.jpg`, and so is
+// `OUT/A.JPG` on the case-insensitive file systems Windows and macOS use by
+// default. Only lexical, as the outputs may not exist yet.
+fn same_file_key(path: &Path) -> PathBuf {
+    let mut key = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(key.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                key.pop();
+            }
+            other => key.push(other),
+        }
+    }
+    if cfg!(any(windows, target_os = "macos")) {
+        key = PathBuf::from(key.to_string_lossy().to_lowercase());
+    }
+    key
+}
+
+/// Outputs that more than one job of a batch would write, each with the
+/// inputs mapping to it in batch order. Converting such a batch would have
+/// later inputs overwrite or skip the output of earlier ones. Paths are
+/// compared after resolving `.` and `..`, ignoring case where the file
+/// system does; each output is listed as its first job names it.
+pub fn output_collisions(jobs: &[ConvertJob]) -> Vec<(PathBuf, Vec<PathBuf>)> {
+    let mut order = Vec::new();
+    let mut inputs: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for job in jobs {
+        if let Some(output) = planned_output(job) {
+            let claimed = inputs.entry(same_file_key(&output)).or_default();
+            if claimed.is_empty() {
+                order.push(output);
+            }
+            claimed.push(job.input.clone());
+        }
+    }
+    order
+        .into_iter()
+        .filter_map(|output| {
+            let claimed = inputs.remove(&same_file_key(&output))?;
+            (claimed.len() > 1).then_some((output, claimed))
+        })
+        .collect()
+}
+
+/// Gives every job after the first one writing a colliding output its own
+/// `name-1.ext`, `name-2.ext`, ..., skipping names other jobs or existing
+/// files already use.
+pub fn number_colliding_outputs(jobs: &mut [ConvertJob]) {
+    let mut taken: HashSet<PathBuf> = jobs
+        .iter()
+        .filter_map(planned_output)
+        .map(|output| same_file_key(&output))
+        .collect();
+    let mut first_claim = HashSet::new();
+    for job in jobs.iter_mut() {
+        let Some(output) = planned_output(job) else {
+            continue;
+        };
+        if first_claim.insert(same_file_key(&output)) || job.in_place {
+            continue;
+        }
+        let unique = numbered_paths(&output)
+            .find(|candidate| {
+                !taken.contains(&same_file_key(candidate)) && !fs_path(candidate).exists()
+            })
+            .expect("unbounded suffix search");
+        taken.insert(same_file_key(&unique));
+        job.output = Some(unique);
+    }
+}
+
+/// `name.png` becomes `name_000.png`, `name_001.png`, ... for frame outputs.
+pub fn frame_output_path(path: &Path, index: usize, total: usize) -> PathBuf {
+    let width = total.saturating_sub(1).to_string().len().max(3);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}_{index:0width$}.{}", ext.to_string_lossy()),
+        None => format!("{stem}_{index:0width$}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Frame outputs of a job writing `total` frames. With
+/// [`ConflictPolicy::Rename`] the whole set moves aside to `name-1_000.png`,
+/// ... once any of its frames exists, so the frames of two runs never mix.
+pub(crate) fn frame_output_paths(cj: &ConvertJob, total: usize) -> Vec<PathBuf> {
+    let base = resolve_output_path(&cj.input, cj.output.as_deref(), cj.format_type);
+    let frames = |base: &Path| -> Vec<PathBuf> {
+        (0..total)
+            .map(|i| frame_output_path(base, i, total))
+            .collect()
+    };
+    let free = |paths: &[PathBuf]| !paths.iter().any(|p| fs_path(p).exists());
+    let outputs = frames(&base);
+    if cj.on_conflict != ConflictPolicy::Rename || free(&outputs) {
+        return outputs;
+    }
+    let mut renamed = numbered_paths(&base).map(|base| frames(&base));
+    renamed
+        .find(|outputs| free(outputs))
+        .expect("unbounded suffix search")
+}
+
+pub fn derive_output_path(input: &Path, to: FormatType) -> PathBuf {
+    let mut p = input.to_path_buf();
+    p.set_extension(to.extension());
+    p
+}
+
+/// Output path template like `{stem}_converted.{ext}`, expanded per input.
+///
+/// Tokens: `{stem}` is the input file name without extension, `{ext}` the
+/// target extension, `{parent}` the input's directory and `{index}` the
+/// 1-based position of the input in the batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputPattern(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Stem,
+    Ext,
+    Parent,
+    Index,
+}
+
+impl OutputPattern {
+    pub fn expand(&self, input: &Path, to: FormatType, index: usize) -> PathBuf {
+        let mut out = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(text) => out.push_str(text),
+                Segment::Stem => {
+                    out.push_str(&input.file_stem().unwrap_or_default().to_string_lossy())
+                }
+                Segment::Ext => out.push_str(to.extension()),
+                Segment::Parent => match input.parent() {
+                    Some(p) if !p.as_os_str().is_empty() => out.push_str(&p.to_string_lossy()),
+                    _ => out.push('.'),
+                },
+                Segment::Index => out.push_str(&index.to_string()),
+            }
+        }
+        PathBuf::from(out)
+    }
+
+    /// Extension the pattern ends in when it is spelled out, `webp` for
+    /// `{stem}.webp`, so the target format can be read from it.
+    pub fn extension(&self) -> Option<&str> {
+        match self.0.last()? {
+            Segment::Literal(text) => text
+                .rsplit_once('.')
+                .map(|(_, ext)| ext)
+                .filter(|ext| !ext.is_empty() && !ext.contains(['/', '\\'])),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for OutputPattern {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            |why: String| InputError::InvalidArgument(format!("output pattern '{s}': {why}"));
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            let Some(open) = rest.find(['{', '}']) else {
+                segments.push(Segment::Literal(rest.to_string()));
+                break;
+            };
+            if open > 0 {
+                segments.push(Segment::Literal(rest[..open].to_string()));
+            }
+            if rest[open..].starts_with('}') {
+                return Err(invalid("unmatched '}'".into()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| invalid("unclosed '{'".into()))?;
+            segments.push(match &rest[open + 1..open + close] {
+                "stem" => Segment::Stem,
+                "ext" => Segment::Ext,
+                "parent" => Segment::Parent,
+                "index" => Segment::Index,
+                other => {
+                    return Err(invalid(format!(
+                        "unknown token {{{other}}} (use {{stem}}, {{ext}}, {{parent}} or {{index}})"
+                    )))
+                }
+            });
+            rest = &rest[open + close + 1..];
+        }
+        if segments.is_empty() {
+            return Err(invalid("pattern is empty".into()));
+        }
+        Ok(OutputPattern(segments))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colliding_outputs_are_found_and_numbered() {
+        let out = Path::new("flat/");
+        let mut jobs: Vec<ConvertJob> = ["a/cat.png", "b/cat.png", "dog.png", "c/cat.jpg"]
+            .into_iter()
+            .map(|input| ConvertJob {
+                output: Some(out.to_path_buf()),
+                ..ConvertJob::new(input, FormatType::JPEG)
+            })
+            .collect();
+        // a later input that would get the first numbered name on its own
+        jobs.push(ConvertJob {
+            output: Some(PathBuf::from("flat/cat-1.jpg")),
+            ..ConvertJob::new("e/x.png", FormatType::JPEG)
+        });
+
+        let collisions = output_collisions(&jobs);
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].0, Path::new("flat/cat.jpg"));
+        assert_eq!(
+            collisions[0].1,
+            [
+                PathBuf::from("a/cat.png"),
+                PathBuf::from("b/cat.png"),
+                PathBuf::from("c/cat.jpg")
+            ]
+        );
+
+        number_colliding_outputs(&mut jobs);
+        assert!(output_collisions(&jobs).is_empty());
+        assert_eq!(jobs[0].output.as_deref(), Some(out));
+        assert_eq!(jobs[1].output.as_deref(), Some(Path::new("flat/cat-2.jpg")));
+        assert_eq!(jobs[3].output.as_deref(), Some(Path::new("flat/cat-3.jpg")));
+    }
+
+    #[test]
+    fn outputs_collide_after_resolving_dots() {
+        let jobs: Vec<ConvertJob> = [("a.png", "out/./cat.jpg"), ("b.png", "out/x/../cat.jpg")]
+            .into_iter()
+            .map(|(input, output)| ConvertJob {
+                output: Some(PathBuf::from(output)),
+                ..ConvertJob::new(input, FormatType::JPEG)
+            })
+            .collect();
+
+        let collisions = output_collisions(&jobs);
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].0, Path::new("out/./cat.jpg"));
+        assert_eq!(
+            same_file_key(Path::new("../a/./b/../c.jpg")),
+            Path::new("../a/c.jpg")
+        );
+        if cfg!(any(windows, target_os = "macos")) {
+            assert_eq!(
+                same_file_key(Path::new("Out/CAT.jpg")),
+                Path::new("out/cat.jpg")
+            );
+        }
+    }
+
+    #[test]
+    fn pattern_expands_every_token() {
+        let pattern: OutputPattern = "{parent}/out/{index}-{stem}_converted.{ext}"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            pattern.expand(Path::new("shots/cat.png"), FormatType::JPEG, 3),
+            Path::new("shots/out/3-cat_converted.jpg")
+        );
+        assert_eq!(
+            pattern.expand(Path::new("cat.png"), FormatType::JPEG, 1),
+            Path::new("./out/1-cat_converted.jpg")
+        );
+        assert_eq!(pattern.extension(), None);
+        let pattern: OutputPattern = "v1.2/{stem}_small.webp".parse().unwrap();
+        assert_eq!(pattern.extension(), Some("webp"));
+        let pattern: OutputPattern = "v1.2/{stem}".parse().unwrap();
+        assert_eq!(pattern.extension(), None);
+    }
+
+    #[test]
+    fn pattern_rejects_unknown_or_unbalanced_tokens() {
+        for bad in ["{name}.{ext}", "{stem.jpg", "stem}.jpg", ""] {
+            assert!(bad.parse::<OutputPattern>().is_err(), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Custom formats loaded at runtime from dynamic libraries.
+//!
+//! A plugin is a `cdylib` built with the same compiler against the same
+//! version of `mf-core`. It exports a [`PluginDeclaration`] named
+//! `MELTFORGE_PLUGIN`, and the formats it lists are registered together
+//! with [`register_all`]:
+//!
+//! ```ignore
+//! use mf_core::{plugin::{PluginDeclaration, CORE_VERSION}, registry::CustomFormat};
+//!
+//! #[no_mangle]
+//! pub static MELTFORGE_PLUGIN: PluginDeclaration = PluginDeclaration {
+//!     core_version: CORE_VERSION,
+//!     formats,
+//! };
+//!
+//! fn formats() -> Vec<CustomFormat> {
+//!     vec![CustomFormat::new("qoi", &["qoi"]).with_decoder(decode_qoi)]
+//! }
+//! ```
+
+use std::{
+    env::consts::DLL_EXTENSION,
+    error::Error,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+use libloading::Library;
+
+use crate::{
+    error::ConversionError,
+    format::FormatType,
+    fs_path::fs_path,
+    registry::{register_all, CustomFormat},
+};
+
+/// Directory scanned by [`load_from_env`].
+pub const PLUGIN_DIR_ENV: &str = "MELTFORGE_PLUGIN_DIR";
+
+/// Name of the [`PluginDeclaration`] a plugin library exports.
+pub const PLUGIN_SYMBOL: &str = "MELTFORGE_PLUGIN";
+
+/// Version of `mf-core` a plugin has to be built against.
+pub const CORE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What a plugin exports as [`PLUGIN_SYMBOL`].
+pub struct PluginDeclaration {
+    /// [`CORE_VERSION`] of the `mf-core` the plugin was built with. Rust has
+    /// no stable ABI, so a plugin of another version is refused.
+    pub core_version: &'static str,
+    /// The formats to register, called once when the plugin is loaded.
+    pub formats: fn() -> Vec<CustomFormat>,
+}
+
+/// Result of loading one library: the formats it registered.
+pub type PluginResult = (PathBuf, Result<Vec<FormatType>, ConversionError>);
+
+/// Loads the plugin library at `path` and registers its formats.
+///
+/// The library stays loaded for the rest of the process, as the registered
+/// formats run its code.
+pub fn load(path: &Path) -> Result<Vec<FormatType>, ConversionError> {
+    let failed =
+        |why: String| ConversionError::PluginLoadFailed(format!("{}: {why}", path.display()));
+    // SAFETY: loading runs the library's initializers, which a plugin is
+    // trusted with like any other code the user chose to run
+    let library = unsafe { Library::new(path) }.map_err(|e| {
+        // the system's reason, e.g. "invalid ELF header", is the source and
+        // may already start with the file name
+        let why = e
+            .source()
+            .map_or_else(|| e.to_string(), ToString::to_string);
+        let named = format!("{}: ", path.display());
+        failed(why.strip_prefix(&named).unwrap_or(&why).to_string())
+    })?;
+    let formats = {
+        // SAFETY: the symbol is a `PluginDeclaration` by the plugin contract,
+        // and its layout matches once the versions do
+        let symbol = unsafe { library.get::<*const PluginDeclaration>(PLUGIN_SYMBOL) }
+            .map_err(|_| failed(format!("no {PLUGIN_SYMBOL} symbol, not a MeltForge plugin")))?;
+        let declaration = unsafe { &**symbol };
+        if declaration.core_version != CORE_VERSION {
+            return Err(failed(format!(
+                "built for mf-core {}, this is {CORE_VERSION}",
+                declaration.core_version
+            )));
+        }
+        declaration.formats
+    };
+    std::mem::forget(library);
+    register_declared(formats, failed)
+}
+
+// A plugin whose formats clash with known ones is refused as a whole, so a
+// failed load leaves none of its formats behind.
+fn register_declared(
+    formats: fn() -> Vec<CustomFormat>,
+    failed: impl Fn(String) -> ConversionError,
+) -> Result<Vec<FormatType>, ConversionError> {
+    // a panicking plugin costs its own formats, not the run
+    let formats = panic::catch_unwind(AssertUnwindSafe(formats))
+        .map_err(|_| failed("panicked while listing its formats".into()))?;
+    register_all(formats).map_err(|e| failed(e.to_string()))
+}
+
+/// Loads every library in `dir` with the platform's extension (`.so`,
+/// `.dylib` or `.dll`), in file name order. A library that fails to load is
+/// reported and skipped.
+pub fn load_dir(dir: &Path) -> Vec<PluginResult> {
+    let entries = match fs::read_dir(fs_path(dir)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let err = ConversionError::PluginLoadFailed(format!("{}: {e}", dir.display()));
+            return vec![(dir.to_path_buf(), Err(err))];
+        }
+    };
+    let mut libraries: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == DLL_EXTENSION))
+        .collect();
+    libraries.sort();
+    libraries
+        .into_iter()
+        .map(|path| {
+            let result = load(&path);
+            (path, result)
+        })
+        .collect()
+}
+
+/// Loads the plugins in `$MELTFORGE_PLUGIN_DIR`, nothing if it is unset.
+pub fn load_from_env() -> Vec<PluginResult> {
+    match std::env::var_os(PLUGIN_DIR_ENV) {
+        Some(dir) if !dir.is_empty() => load_dir(Path::new(&dir)),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broken_libraries_are_reported_and_others_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(format!("broken.{DLL_EXTENSION}")),
+            b"not a library",
+        )
+        .unwrap();
+        fs::write(dir.path().join("notes.txt"), b"skipped").unwrap();
+
+        let results = load_dir(dir.path());
+        assert_eq!(results.len(), 1);
+        assert!(results[0].0.ends_with(format!("broken.{DLL_EXTENSION}")));
+        assert!(matches!(
+            results[0].1,
+            Err(ConversionError::PluginLoadFailed(_))
+        ));
+
+        let missing = load_dir(&dir.path().join("missing"));
+        assert!(missing[0].1.is_err());
+    }
+
+    #[test]
+    fn a_clashing_format_registers_none_of_its_plugin() {
+        fn formats() -> Vec<CustomFormat> {
+            vec![
+                CustomFormat::new("pluginraw", &["plraw"]),
+                CustomFormat::new("pluginpng", &["png"]),
+            ]
+        }
+        let err = register_declared(formats, ConversionError::PluginLoadFailed).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "plugin load failed: format or extension 'png' is already registered"
+        );
+        assert!(crate::registry::lookup("pluginraw").is_none());
+    }
+}
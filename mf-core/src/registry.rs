@@ -0,0 +1,386 @@
+use std::{
+    fmt,
+    sync::{LazyLock, RwLock},
+};
+
+use image::{
+    error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind},
+    DynamicImage, ImageError, ImageResult,
+};
+
+use crate::{error::FormatError, format::FormatType};
+
+type DecodeFn = dyn Fn(&[u8]) -> ImageResult<DynamicImage> + Send + Sync;
+type EncodeFn = dyn Fn(&DynamicImage) -> ImageResult<Vec<u8>> + Send + Sync;
+type DimensionsFn = dyn Fn(&[u8]) -> ImageResult<(u32, u32)> + Send + Sync;
+
+/// A format added by a downstream crate, see [`register`].
+///
+/// It is known to MeltForge as [`FormatType::Custom`] with its name: parsed
+/// from its extensions, detected on input files, and converted from and to
+/// PNG, JPEG or other custom formats, depending on which closures it has.
+pub struct CustomFormat {
+    name: &'static str,
+    extensions: Vec<&'static str>,
+    decode: Option<Box<DecodeFn>>,
+    encode: Option<Box<EncodeFn>>,
+    dimensions: Option<Box<DimensionsFn>>,
+}
+
+impl CustomFormat {
+    /// A format called `name`, recognized by `extensions`. The first extension
+    /// is used for derived output paths; without any, the name is used.
+    pub fn new(name: &'static str, extensions: &[&'static str]) -> Self {
+        CustomFormat {
+            name,
+            extensions: extensions.to_vec(),
+            decode: None,
+            encode: None,
+            dimensions: None,
+        }
+    }
+
+    /// Makes the format readable. The closure gets the whole input file.
+    pub fn with_decoder(
+        mut self,
+        decode: impl Fn(&[u8]) -> ImageResult<DynamicImage> + Send + Sync + 'static,
+    ) -> Self {
+        self.decode = Some(Box::new(decode));
+        self
+    }
+
+    /// Reads width and height from the header of an input file, so inputs
+    /// over the pixel limit are rejected before the decoder allocates them.
+    /// Without it, inputs are decoded to learn their size.
+    pub fn with_dimensions(
+        mut self,
+        dimensions: impl Fn(&[u8]) -> ImageResult<(u32, u32)> + Send + Sync + 'static,
+    ) -> Self {
+        self.dimensions = Some(Box::new(dimensions));
+        self
+    }
+
+    /// Makes the format writable. The closure returns the whole output file.
+    pub fn with_encoder(
+        mut self,
+        encode: impl Fn(&DynamicImage) -> ImageResult<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        self.encode = Some(Box::new(encode));
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn extension(&self) -> &'static str {
+        self.extensions.first().copied().unwrap_or(self.name)
+    }
+
+    pub fn can_decode(&self) -> bool {
+        self.decode.is_some()
+    }
+
+    pub fn can_encode(&self) -> bool {
+        self.encode.is_some()
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self.extensions.iter().any(|e| e.eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Debug for CustomFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomFormat")
+            .field("name", &self.name)
+            .field("extensions", &self.extensions)
+            .field("decode", &self.can_decode())
+            .field("encode", &self.can_encode())
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Entry {
+    Builtin(FormatType),
+    Custom(&'static CustomFormat),
+}
+
+impl Entry {
+    fn matches(self, name: &str) -> bool {
+        match self {
+            Entry::Builtin(format) => FormatType::builtin(name) == Some(format),
+            Entry::Custom(format) => format.matches(name),
+        }
+    }
+
+    fn format_type(self) -> FormatType {
+        match self {
+            Entry::Builtin(format) => format,
+            Entry::Custom(format) => FormatType::Custom(format.name),
+        }
+    }
+}
+
+// The built-in formats are registered at startup, so names are resolved and
+// new formats checked against one list. Registered formats live for the rest
+// of the process, so lookups can hand out plain references instead of
+// holding the lock while converting.
+static REGISTRY: LazyLock<RwLock<Vec<Entry>>> =
+    LazyLock::new(|| RwLock::new(FormatType::ALL.map(Entry::Builtin).to_vec()));
+
+/// Adds `format` for the rest of the process and returns its [`FormatType`].
+///
+/// Fails if its name or one of its extensions already names a built-in or a
+/// registered format, as parsing them would be ambiguous.
+pub fn register(format: CustomFormat) -> Result<FormatType, FormatError> {
+    register_all(vec![format]).map(|formats| formats[0])
+}
+
+/// [`register`] for several formats at once, e.g. those of one plugin:
+/// either all of them are added or, if one clashes with a known format or
+/// with another of `formats`, none.
+pub fn register_all(formats: Vec<CustomFormat>) -> Result<Vec<FormatType>, FormatError> {
+    let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    for (i, format) in formats.iter().enumerate() {
+        let taken = std::iter::once(format.name)
+            .chain(format.extensions.iter().copied())
+            .find(|name| {
+                registry.iter().any(|entry| entry.matches(name))
+                    || formats[..i].iter().any(|earlier| earlier.matches(name))
+            });
+        if let Some(taken) = taken {
+            return Err(FormatError::AlreadyRegistered(taken.to_string()));
+        }
+    }
+    Ok(formats
+        .into_iter()
+        .map(|format| {
+            let format: &'static CustomFormat = Box::leak(Box::new(format));
+            registry.push(Entry::Custom(format));
+            FormatType::Custom(format.name)
+        })
+        .collect())
+}
+
+/// The built-in or registered format with this name or extension, ignoring
+/// case.
+pub(crate) fn find(name: &str) -> Option<FormatType> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry
+        .iter()
+        .find(|entry| entry.matches(name))
+        .map(|entry| entry.format_type())
+}
+
+/// The registered format with this name or extension, ignoring case.
+pub(crate) fn lookup(name: &str) -> Option<&'static CustomFormat> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry.iter().find_map(|entry| match entry {
+        Entry::Custom(format) if format.matches(name) => Some(*format),
+        _ => None,
+    })
+}
+
+/// Width and height of a whole file of the registered format `name`, from
+/// its header if the format can read one, else by decoding it.
+pub(crate) fn dimensions(name: &str, data: &[u8]) -> ImageResult<(u32, u32)> {
+    match lookup(name).and_then(|f| f.dimensions.as_ref()) {
+        Some(dimensions) => dimensions(data),
+        None => decode(name, data).map(|img| (img.width(), img.height())),
+    }
+}
+
+/// Decodes a whole file of the registered format `name`.
+pub(crate) fn decode(name: &str, data: &[u8]) -> ImageResult<DynamicImage> {
+    match lookup(name).and_then(|f| f.decode.as_ref()) {
+        Some(decode) => decode(data),
+        None => Err(unsupported(name)),
+    }
+}
+
+/// Encodes `img` as a whole file of the registered format `name`.
+pub(crate) fn encode(name: &str, img: &DynamicImage) -> ImageResult<Vec<u8>> {
+    match lookup(name).and_then(|f| f.encode.as_ref()) {
+        Some(encode) => encode(img),
+        None => Err(unsupported(name)),
+    }
+}
+
+fn unsupported(name: &str) -> ImageError {
+    let hint = || ImageFormatHint::Name(name.into());
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        hint(),
+        UnsupportedErrorKind::Format(hint()),
+    ))
+}
+
+/// Every format registered besides the built-ins, in registration order.
+pub fn registered() -> Vec<FormatType> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry
+        .iter()
+        .filter(|entry| matches!(entry, Entry::Custom(_)))
+        .map(|entry| entry.format_type())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        convert::{convert, convert_in_memory},
+        job::{ConvertJob, ConvertOptions},
+    };
+    use std::sync::{Mutex, MutexGuard};
+
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    // The registry is shared by the whole test process, so tests that
+    // register formats run one at a time and leave only the built-ins behind.
+    struct Isolated {
+        _serial: MutexGuard<'static, ()>,
+    }
+
+    fn isolated() -> Isolated {
+        Isolated {
+            _serial: SERIAL.lock().unwrap_or_else(|e| e.into_inner()),
+        }
+    }
+
+    impl Drop for Isolated {
+        fn drop(&mut self) {
+            let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+            registry.retain(|entry| matches!(entry, Entry::Builtin(_)));
+        }
+    }
+
+    // A raw 8-bit gray format: width and height as u16, then the pixels.
+    fn gray_format(name: &'static str, ext: &'static str) -> CustomFormat {
+        CustomFormat::new(name, &[ext])
+            .with_dimensions(|data| {
+                let size = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]).into();
+                Ok((size(0), size(2)))
+            })
+            .with_decoder(|data| {
+                let (w, h) = (
+                    u16::from_le_bytes([data[0], data[1]]),
+                    u16::from_le_bytes([data[2], data[3]]),
+                );
+                let img = image::GrayImage::from_raw(w.into(), h.into(), data[4..].to_vec())
+                    .expect("size matches the header");
+                Ok(DynamicImage::ImageLuma8(img))
+            })
+            .with_encoder(|img| {
+                let gray = img.to_luma8();
+                let mut out = (gray.width() as u16).to_le_bytes().to_vec();
+                out.extend((gray.height() as u16).to_le_bytes());
+                out.extend(gray.as_raw());
+                Ok(out)
+            })
+    }
+
+    #[test]
+    fn registered_format_converts_both_ways() {
+        let _isolated = isolated();
+        let raw = register(gray_format("rawgray", "rgry")).unwrap();
+        assert_eq!("RGRY".parse::<FormatType>().unwrap(), raw);
+        assert_eq!(raw.extension(), "rgry");
+        assert!(registered().contains(&raw));
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::GrayImage::from_pixel(5, 3, image::Luma([77]))
+            .save(&input)
+            .unwrap();
+
+        let out = convert(ConvertJob::new(&input, raw)).unwrap();
+        assert_eq!(out.outputs[0], dir.path().join("in.rgry"));
+
+        let back = dir.path().join("back.png");
+        let job = ConvertJob {
+            output: Some(back.clone()),
+            ..ConvertJob::new(&out.outputs[0], FormatType::PNG)
+        };
+        convert(job).unwrap();
+        let decoded = image::open(&back).unwrap().to_luma8();
+        assert_eq!(decoded.dimensions(), (5, 3));
+        assert_eq!(decoded.get_pixel(4, 2).0, [77]);
+    }
+
+    #[test]
+    fn verify_decodes_the_registered_format() {
+        let _isolated = isolated();
+        let raw = register(gray_format("verifygray", "vgry")).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::GrayImage::new(5, 3).save(&input).unwrap();
+
+        let job = ConvertJob {
+            verify: true,
+            ..ConvertJob::new(&input, raw)
+        };
+        let out = convert(job).unwrap();
+        assert_eq!(out.outputs, [dir.path().join("in.vgry")]);
+
+        // nothing could re-read an output without a decoder
+        let write_only =
+            register(CustomFormat::new("writeonly", &["wo"]).with_encoder(|_| Ok(Vec::new())))
+                .unwrap();
+        let job = ConvertJob {
+            verify: true,
+            ..ConvertJob::new(&input, write_only)
+        };
+        let err = convert(job).unwrap_err();
+        assert!(err.to_string().contains("--verify cannot re-read"), "{err}");
+        assert!(!dir.path().join("in.wo").exists());
+    }
+
+    #[test]
+    fn names_must_be_unique() {
+        let _isolated = isolated();
+        assert!(matches!(
+            register(CustomFormat::new("mypng", &["png"])),
+            Err(FormatError::AlreadyRegistered(name)) if name == "png"
+        ));
+        register(CustomFormat::new("once", &[])).unwrap();
+        assert!(register(CustomFormat::new("other", &["ONCE"])).is_err());
+
+        // formats registered together must not clash among themselves either
+        let pair = vec![
+            CustomFormat::new("twice", &[]),
+            CustomFormat::new("again", &["TWICE"]),
+        ];
+        assert!(register_all(pair).is_err());
+        assert!(lookup("twice").is_none());
+    }
+
+    #[test]
+    fn built_ins_are_registered_at_startup() {
+        let _isolated = isolated();
+        assert_eq!(find("JPG"), Some(FormatType::JPEG));
+        assert_eq!(find("tif"), Some(FormatType::TIFF));
+        assert!(lookup("png").is_none() && registered().is_empty());
+    }
+
+    #[test]
+    fn oversized_custom_input_is_rejected_before_decoding() {
+        let _isolated = isolated();
+        // decoding panics, the pixels the header promises are missing
+        let raw = register(gray_format("hugegray", "hgry")).unwrap();
+        let mut data = 60_000u16.to_le_bytes().repeat(2);
+        data.extend([0; 16]);
+
+        let err =
+            convert_in_memory(&data, raw, FormatType::PNG, &ConvertOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("60000x60000"), "{err}");
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("huge.hgry");
+        std::fs::write(&input, &data).unwrap();
+        let err = convert(ConvertJob::new(&input, FormatType::PNG)).unwrap_err();
+        assert!(err.to_string().contains("60000x60000"), "{err}");
+    }
+}
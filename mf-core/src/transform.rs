@@ -0,0 +1,554 @@
+use std::{borrow::Cow, fmt, str::FromStr, time::Instant};
+
+use image::{imageops::FilterType, DynamicImage, RgbImage};
+
+use crate::{
+    analysis::content_bounds,
+    color::{Channels, Color},
+    error::{InputError, MeltforgeError},
+    job::{ConvertOptions, Timings},
+};
+
+/// Largest output allowed unless the caller raises the cap.
+pub const DEFAULT_MAX_OUTPUT_DIMENSIONS: Dimensions = Dimensions {
+    width: 16384,
+    height: 16384,
+};
+
+/// Largest input, in pixels, decoded unless the caller raises the limit.
+pub const DEFAULT_MAX_PIXELS: u64 = 16384 * 16384;
+
+/// Width and height in pixels, written as `WxH`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl fmt::Display for Dimensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl FromStr for Dimensions {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || InputError::InvalidArgument(format!("invalid dimensions '{s}' (e.g. 1920x1080)"));
+        let (w, h) = s
+            .trim()
+            .to_lowercase()
+            .split_once('x')
+            .map(|(w, h)| (w.trim().parse(), h.trim().parse()))
+            .ok_or_else(invalid)?;
+        match (w, h) {
+            (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok(Dimensions { width, height }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Resize factor, written as a percentage (`50%` or `50`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Scale(pub f64);
+
+impl FromStr for Scale {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let percent: f64 =
+            s.trim().trim_end_matches('%').parse().map_err(|_| {
+                InputError::InvalidArgument(format!("invalid scale '{s}' (e.g. 50%)"))
+            })?;
+        if !(percent > 0.0 && percent.is_finite()) {
+            return Err(InputError::InvalidArgument(format!(
+                "scale must be positive, got '{s}'"
+            )));
+        }
+        Ok(Scale(percent / 100.0))
+    }
+}
+
+/// Clockwise rotation by a multiple of 90 degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u16", into = "u16")
+)]
+pub enum Rotation {
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl TryFrom<u16> for Rotation {
+    type Error = InputError;
+
+    fn try_from(degrees: u16) -> Result<Self, Self::Error> {
+        match degrees {
+            90 => Ok(Rotation::Deg90),
+            180 => Ok(Rotation::Deg180),
+            270 => Ok(Rotation::Deg270),
+            _ => Err(InputError::InvalidArgument(format!(
+                "invalid rotation {degrees} (use 90, 180 or 270)"
+            ))),
+        }
+    }
+}
+
+impl From<Rotation> for u16 {
+    fn from(rotation: Rotation) -> u16 {
+        match rotation {
+            Rotation::Deg90 => 90,
+            Rotation::Deg180 => 180,
+            Rotation::Deg270 => 270,
+        }
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let degrees: u16 = s.trim().parse().map_err(|_| {
+            InputError::InvalidArgument(format!("invalid rotation '{s}' (use 90, 180 or 270)"))
+        })?;
+        Rotation::try_from(degrees)
+    }
+}
+
+/// Mirror axis: `horizontal` swaps left and right, `vertical` top and bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Flip {
+    Horizontal,
+    Vertical,
+}
+
+impl FromStr for Flip {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "horizontal" | "h" => Ok(Flip::Horizontal),
+            "vertical" | "v" => Ok(Flip::Vertical),
+            _ => Err(InputError::InvalidArgument(format!(
+                "invalid flip '{s}' (use horizontal or vertical)"
+            ))),
+        }
+    }
+}
+
+/// Region of the image to keep, written as `X,Y,W,H` from the top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl fmt::Display for Crop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl FromStr for Crop {
+    type Err = InputError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || InputError::InvalidArgument(format!("invalid crop '{s}' (e.g. 10,20,300,200)"));
+        let numbers = s
+            .split(',')
+            .map(|n| n.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        match numbers[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Crop {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Crop {
+    // The region has to lie within the image; nothing is padded.
+    fn apply(self, img: &DynamicImage) -> Result<DynamicImage, InputError> {
+        let fits =
+            |start: u32, len: u32, size: u32| start.checked_add(len).is_some_and(|end| end <= size);
+        if !fits(self.x, self.width, img.width()) || !fits(self.y, self.height, img.height()) {
+            return Err(InputError::InvalidArgument(format!(
+                "crop {self} reaches outside the {}x{} image",
+                img.width(),
+                img.height()
+            )));
+        }
+        Ok(img.crop_imm(self.x, self.y, self.width, self.height))
+    }
+}
+
+/// A square thumbnail of `size` pixels, made by [`ThumbnailFit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Thumbnail {
+    pub size: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fit: ThumbnailFit,
+}
+
+/// How a non-square image becomes a square thumbnail.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum ThumbnailFit {
+    /// Cut the longer side down to the center square.
+    #[default]
+    Crop,
+    /// Keep the whole image and pad the shorter side, transparent where the
+    /// image has alpha and with the background color otherwise.
+    Letterbox,
+}
+
+// Order: crop, rotate, flip, autocrop, scale, max size, thumbnail. Borrowed images are only
+// copied when a transform actually runs.
+pub(crate) fn apply_transforms<'a>(
+    img: Cow<'a, DynamicImage>,
+    opts: &ConvertOptions,
+    timings: &mut Timings,
+    warnings: &mut Vec<String>,
+) -> Result<Cow<'a, DynamicImage>, MeltforgeError> {
+    let mut img = img;
+    if let Some(crop) = opts.crop {
+        let started = Instant::now();
+        img = Cow::Owned(crop.apply(&img)?);
+        timings.add_transform("crop", started.elapsed());
+    }
+    if let Some(rotation) = opts.rotate {
+        let started = Instant::now();
+        img = Cow::Owned(match rotation {
+            Rotation::Deg90 => img.rotate90(),
+            Rotation::Deg180 => img.rotate180(),
+            Rotation::Deg270 => img.rotate270(),
+        });
+        timings.add_transform("rotate", started.elapsed());
+    }
+    if let Some(flip) = opts.flip {
+        let started = Instant::now();
+        img = Cow::Owned(match flip {
+            Flip::Horizontal => img.fliph(),
+            Flip::Vertical => img.flipv(),
+        });
+        timings.add_transform("flip", started.elapsed());
+    }
+    if opts.autocrop {
+        let started = Instant::now();
+        match content_bounds(&img) {
+            Some(r) => img = Cow::Owned(img.crop_imm(r.x, r.y, r.width, r.height)),
+            // an empty image is left as is rather than shrunk to a meaningless 1x1
+            None => warnings.push("has no content to crop to, kept it uncropped".into()),
+        }
+        timings.add_transform("autocrop", started.elapsed());
+    }
+    if let Some(scale) = opts.scale {
+        let started = Instant::now();
+        img = Cow::Owned(resize_by(&img, scale, opts.max_output_dimensions)?);
+        timings.add_transform("scale", started.elapsed());
+    }
+    if let Some(max) = opts.max_size {
+        // smaller images are left alone, this only ever shrinks
+        if img.width() > max.width || img.height() > max.height {
+            let started = Instant::now();
+            img = Cow::Owned(img.resize(max.width, max.height, FilterType::Lanczos3));
+            timings.add_transform("max size", started.elapsed());
+        }
+    }
+    if let Some(thumb) = opts.thumbnail {
+        let started = Instant::now();
+        img = Cow::Owned(thumbnail(
+            &img,
+            thumb,
+            opts.background,
+            opts.max_output_dimensions,
+        )?);
+        timings.add_transform("thumbnail", started.elapsed());
+    }
+    Ok(img)
+}
+
+fn thumbnail(
+    img: &DynamicImage,
+    thumb: Thumbnail,
+    background: Color,
+    max: Dimensions,
+) -> Result<DynamicImage, MeltforgeError> {
+    let size = thumb.size;
+    check_output_dimensions(size as f64, size as f64, max)?;
+    match thumb.fit {
+        ThumbnailFit::Crop => {
+            let side = img.width().min(img.height());
+            let square = img.crop_imm(
+                (img.width() - side) / 2,
+                (img.height() - side) / 2,
+                side,
+                side,
+            );
+            Ok(square.resize_exact(size, size, FilterType::Lanczos3))
+        }
+        ThumbnailFit::Letterbox => {
+            let fitted = img.resize(size, size, FilterType::Lanczos3);
+            let fill = if img.color().has_alpha() {
+                image::Rgba([0, 0, 0, 0])
+            } else {
+                image::Rgba([background.r, background.g, background.b, 255])
+            };
+            let mut canvas = image::RgbaImage::from_pixel(size, size, fill);
+            image::imageops::overlay(
+                &mut canvas,
+                &fitted.to_rgba8(),
+                i64::from((size - fitted.width()) / 2),
+                i64::from((size - fitted.height()) / 2),
+            );
+            let canvas = DynamicImage::ImageRgba8(canvas);
+            Ok(if img.color().has_alpha() {
+                canvas
+            } else {
+                DynamicImage::ImageRgb8(canvas.to_rgb8())
+            })
+        }
+    }
+}
+
+/// Composites an image with alpha over an opaque background color.
+pub(crate) fn flatten(img: &DynamicImage, background: Color) -> DynamicImage {
+    let bg = [background.r, background.g, background.b];
+    let rgba = img.to_rgba8();
+    let flat = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |fg: u8, bg: u8| {
+            ((fg as u32 * a as u32 + bg as u32 * (255 - a as u32) + 127) / 255) as u8
+        };
+        image::Rgb([blend(r, bg[0]), blend(g, bg[1]), blend(b, bg[2])])
+    });
+    // grayscale on a gray background stays grayscale
+    let gray = !img.color().has_color() && bg[0] == bg[1] && bg[1] == bg[2];
+    if gray {
+        DynamicImage::ImageLuma8(DynamicImage::ImageRgb8(flat).to_luma8())
+    } else {
+        DynamicImage::ImageRgb8(flat)
+    }
+}
+
+/// Converts to the `channels` layout, keeping 16-bit samples. Dropped alpha
+/// is composited over `background` rather than cut off.
+pub(crate) fn with_channels(
+    img: &DynamicImage,
+    channels: Channels,
+    background: Color,
+) -> DynamicImage {
+    let img = if img.color().has_alpha() && !channels.has_alpha() {
+        Cow::Owned(flatten(img, background))
+    } else {
+        Cow::Borrowed(img)
+    };
+    let wide = img.color().bytes_per_pixel() > img.color().channel_count();
+    match (channels, wide) {
+        (Channels::Rgb, false) => DynamicImage::ImageRgb8(img.to_rgb8()),
+        (Channels::Rgb, true) => DynamicImage::ImageRgb16(img.to_rgb16()),
+        (Channels::Rgba, false) => DynamicImage::ImageRgba8(img.to_rgba8()),
+        (Channels::Rgba, true) => DynamicImage::ImageRgba16(img.to_rgba16()),
+        (Channels::Gray, false) => DynamicImage::ImageLuma8(img.to_luma8()),
+        (Channels::Gray, true) => DynamicImage::ImageLuma16(img.to_luma16()),
+        (Channels::GrayA, false) => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+        (Channels::GrayA, true) => DynamicImage::ImageLumaA16(img.to_luma_alpha16()),
+    }
+}
+
+fn resize_by(
+    img: &DynamicImage,
+    scale: Scale,
+    max: Dimensions,
+) -> Result<DynamicImage, MeltforgeError> {
+    // computed in f64 so absurd factors cannot overflow before the cap check
+    let width = (img.width() as f64 * scale.0).round().max(1.0);
+    let height = (img.height() as f64 * scale.0).round().max(1.0);
+    check_output_dimensions(width, height, max)?;
+
+    Ok(img.resize_exact(width as u32, height as u32, FilterType::Lanczos3))
+}
+
+// Rejects oversized outputs before the target buffer gets allocated.
+fn check_output_dimensions(width: f64, height: f64, max: Dimensions) -> Result<(), InputError> {
+    if width > max.width as f64 || height > max.height as f64 {
+        return Err(InputError::InvalidArgument(format!(
+            "output {width}x{height} exceeds the maximum output dimensions {max} (see --max-output-dimensions)"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extreme_scale_is_rejected() {
+        let img = DynamicImage::new_rgb8(100, 100);
+        let scale: Scale = "10000000%".parse().unwrap();
+
+        let result = resize_by(&img, scale, DEFAULT_MAX_OUTPUT_DIMENSIONS);
+        assert!(matches!(
+            result,
+            Err(MeltforgeError::Input(InputError::InvalidArgument(_)))
+        ));
+    }
+
+    #[test]
+    fn flatten_blends_alpha_over_background() {
+        let img = image::RgbaImage::from_fn(2, 1, |x, _| match x {
+            0 => image::Rgba([0, 0, 0, 0]),
+            _ => image::Rgba([0, 0, 0, 128]),
+        });
+        let flat = flatten(&DynamicImage::ImageRgba8(img), Color::WHITE).to_rgb8();
+        assert_eq!(flat.get_pixel(0, 0).0, [255, 255, 255]);
+        assert_eq!(flat.get_pixel(1, 0).0, [127, 127, 127]);
+    }
+
+    #[test]
+    fn crop_comes_before_scaling_and_stays_inside_the_image() {
+        assert!("10,20,0,5".parse::<Crop>().is_err());
+        assert!("10,20,30".parse::<Crop>().is_err());
+        let opts = ConvertOptions {
+            crop: Some(" 10, 20,40,30".parse().unwrap()),
+            scale: Some(Scale(0.5)),
+            ..ConvertOptions::default()
+        };
+        let crop = |w, h| {
+            apply_transforms(
+                Cow::Owned(DynamicImage::new_rgb8(w, h)),
+                &opts,
+                &mut Timings::default(),
+                &mut Vec::new(),
+            )
+            .map(|img| (img.width(), img.height()))
+        };
+        assert_eq!(crop(100, 100).unwrap(), (20, 15));
+        assert!(matches!(
+            crop(49, 100),
+            Err(MeltforgeError::Input(InputError::InvalidArgument(_)))
+        ));
+    }
+
+    #[test]
+    fn rotation_then_flip_is_applied_in_order() {
+        // 2x1: red, blue
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(2, 1, |x, _| match x {
+            0 => image::Rgb([255, 0, 0]),
+            _ => image::Rgb([0, 0, 255]),
+        }));
+        let opts = ConvertOptions {
+            rotate: Some(Rotation::Deg90),
+            flip: Some(Flip::Vertical),
+            ..ConvertOptions::default()
+        };
+        let out = apply_transforms(
+            Cow::Borrowed(&img),
+            &opts,
+            &mut Timings::default(),
+            &mut Vec::new(),
+        )
+        .unwrap()
+        .to_rgb8();
+
+        // rotating puts red on top, flipping then moves it to the bottom
+        assert_eq!(out.dimensions(), (1, 2));
+        assert_eq!(out.get_pixel(0, 0).0, [0, 0, 255]);
+        assert_eq!(out.get_pixel(0, 1).0, [255, 0, 0]);
+    }
+
+    #[test]
+    fn max_size_only_shrinks_and_keeps_the_aspect_ratio() {
+        let opts = ConvertOptions {
+            max_size: Some("50x50".parse().unwrap()),
+            ..ConvertOptions::default()
+        };
+        let shrink = |w, h| {
+            let img = DynamicImage::new_rgb8(w, h);
+            let out = apply_transforms(
+                Cow::Owned(img),
+                &opts,
+                &mut Timings::default(),
+                &mut Vec::new(),
+            )
+            .unwrap();
+            (out.width(), out.height())
+        };
+        assert_eq!(shrink(200, 100), (50, 25));
+        assert_eq!(shrink(30, 40), (30, 40));
+    }
+
+    #[test]
+    fn thumbnail_crops_or_letterboxes_to_a_square() {
+        // 30x10: red, green, blue thirds
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(30, 10, |x, _| match x / 10 {
+            0 => image::Rgb([255, 0, 0]),
+            1 => image::Rgb([0, 255, 0]),
+            _ => image::Rgb([0, 0, 255]),
+        }));
+        let max = DEFAULT_MAX_OUTPUT_DIMENSIONS;
+
+        let crop = Thumbnail {
+            size: 6,
+            fit: ThumbnailFit::Crop,
+        };
+        let out = thumbnail(&img, crop, Color::WHITE, max).unwrap().to_rgb8();
+        assert_eq!(out.dimensions(), (6, 6));
+        assert_eq!(out.get_pixel(0, 0).0, [0, 255, 0]);
+
+        let letterbox = Thumbnail {
+            size: 6,
+            fit: ThumbnailFit::Letterbox,
+        };
+        let out = thumbnail(&img, letterbox, Color::WHITE, max)
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(out.dimensions(), (6, 6));
+        assert_eq!(out.get_pixel(3, 0).0, [255, 255, 255]);
+        assert_eq!(out.get_pixel(0, 3).0, [255, 0, 0]);
+    }
+
+    #[test]
+    fn invalid_rotation_is_rejected() {
+        assert!(matches!(
+            "45".parse::<Rotation>(),
+            Err(InputError::InvalidArgument(_))
+        ));
+        assert_eq!("270".parse::<Rotation>().unwrap(), Rotation::Deg270);
+    }
+
+    #[test]
+    fn scale_within_cap_resizes() {
+        let img = DynamicImage::new_rgb8(100, 50);
+        let resized =
+            resize_by(&img, "50%".parse().unwrap(), DEFAULT_MAX_OUTPUT_DIMENSIONS).unwrap();
+        assert_eq!((resized.width(), resized.height()), (50, 25));
+    }
+}
@@ -1,98 +1,576 @@
 use std::{
+    collections::HashSet,
     fs::{self, File},
-    io::ErrorKind,
-    path::Path,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        LazyLock, Mutex,
+    },
 };
 
 use crate::{
-    error::{FormatError, InputError, IoError, MeltforgeError},
+    color::{BitDepth, Channels},
+    convert::decode_frames,
+    error::{ConversionError, FormatError, InputError, IoError, MeltforgeError},
     format::FormatType,
-    job::ConvertJob,
+    fs_path::fs_path,
+    info::{inspect, ImageInfo},
+    job::{ConvertJob, ConvertOptions},
+    jpeg::MAX_COMMENT_BYTES,
+    output::{
+        is_directory_target, job_output_path, output_collisions, with_target_extension,
+        ConflictPolicy,
+    },
+    registry,
+    warning::Warnings,
 };
 
 pub fn validate_job(cj: &ConvertJob) -> Result<(), MeltforgeError> {
     // check if paths are readable and
-    validate_path(&cj.input)?;
-    ensure_readable(&cj.input)?;
+    validate_input_file(&cj.input)?;
 
     // validate input format and compatibility(will be excluded when plugins are available)
-    let input_fmt = validate_input_format(&cj.input)?;
-    validate_compatibility(input_fmt, cj.format_type)?;
+    let input_fmt = validate_input_format(cj)?;
+    validate_conversion(cj, input_fmt)?;
+    validate_options(&cj.options, cj.format_type)?;
+    validate_verify(cj)?;
+    if cj.strict {
+        Warnings::new(true).warn_all(option_warnings(cj))?;
+    }
+    if cj.in_place {
+        return validate_in_place(cj, input_fmt);
+    }
+
+    validate_output(cj)
+}
+
+/// Options of the job that will be ignored for its target format, or an
+/// `--output` that gets its extension added. Checked up front for strict
+/// jobs, so they fail before anything is decoded.
+pub fn option_warnings(cj: &ConvertJob) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let quality = cj.options.quality.is_some();
+    let webp_lossless = cj.options.webp_lossless && cj.format_type == FormatType::WEBP;
+    if quality && webp_lossless {
+        warnings.push("--quality is ignored with --webp-lossless".into());
+    }
+    if quality && cj.options.jxl_lossless && cj.format_type == FormatType::JXL {
+        warnings.push("--quality is ignored with --jxl-lossless".into());
+    }
+    // lossless WebP output already got its more specific warning
+    if quality && !cj.format_type.is_lossy() && !webp_lossless {
+        warnings.push(format!(
+            "--quality has no effect on {} output, which is lossless",
+            cj.format_type
+        ));
+    }
+    let density_flag = if cj.options.dpi.is_some() {
+        Some("--dpi")
+    } else if cj.options.print_size.is_some() {
+        Some("--print-size")
+    } else {
+        None
+    };
+    if let Some(flag) = density_flag.filter(|_| !cj.format_type.stores_density()) {
+        warnings.push(format!(
+            "{flag} has no effect on {} output, which cannot store a resolution",
+            cj.format_type
+        ));
+    }
+    if cj.options.webp_lossless && cj.format_type != FormatType::WEBP {
+        warnings.push(format!(
+            "--webp-lossless has no effect on {} output",
+            cj.format_type
+        ));
+    }
+    if cj.options.jxl_lossless && cj.format_type != FormatType::JXL {
+        warnings.push(format!(
+            "--jxl-lossless has no effect on {} output",
+            cj.format_type
+        ));
+    }
+    if cj.options.threshold.is_some() && cj.options.bit_depth != Some(BitDepth::One) {
+        warnings.push("--threshold has no effect without --bit-depth 1".into());
+    }
+    if cj.options.jpeg_comment.is_some() && cj.format_type != FormatType::JPEG {
+        warnings.push(format!(
+            "--jpeg-comment has no effect on {} output",
+            cj.format_type
+        ));
+    }
+    if let Some(out) = cj.output.as_deref().filter(|_| !cj.in_place) {
+        let written = with_target_extension(out, cj.format_type);
+        if written != out && !is_directory_target(out) {
+            warnings.push(format!(
+                "--output {} has no extension, writing {}",
+                out.display(),
+                written.display()
+            ));
+        }
+    }
+    if cj.options.progressive && cj.format_type != FormatType::JPEG {
+        warnings.push(format!(
+            "--progressive has no effect on {} output, it only applies to JPEG",
+            cj.format_type
+        ));
+    }
+    if cj.options.reproducible && !cj.format_type.is_reproducible() {
+        warnings.push(format!(
+            "--reproducible cannot guarantee identical {} output, its plugin decides",
+            cj.format_type
+        ));
+    }
+    if cj.options.subsampling.is_some() && cj.format_type != FormatType::JPEG {
+        warnings.push(format!(
+            "--subsampling has no effect on {} output, it only applies to JPEG",
+            cj.format_type
+        ));
+    }
+    warnings
+}
+
+/// Like [`validate_job`], but keeps going after a failure and returns every
+/// problem found. The input file, its format and the output are checked
+/// independently; only checks that build on a failed one are left out.
+pub fn validate_job_all(cj: &ConvertJob) -> Result<(), Vec<MeltforgeError>> {
+    let mut errors = Vec::new();
+
+    if let Err(e) = validate_input_file(&cj.input) {
+        errors.push(e);
+    }
+    // the format comes from the path or --from, so it is checked even for a missing file
+    match validate_input_format(cj) {
+        Ok(input_fmt) => {
+            if let Err(e) = validate_conversion(cj, input_fmt) {
+                errors.push(e.into());
+            }
+            if cj.in_place {
+                if let Err(e) = validate_in_place(cj, input_fmt) {
+                    errors.push(e);
+                }
+            }
+        }
+        Err(e) => errors.push(e.into()),
+    }
+    if let Err(e) = validate_options(&cj.options, cj.format_type) {
+        errors.push(e.into());
+    }
+    if let Err(e) = validate_verify(cj) {
+        errors.push(e.into());
+    }
+    if cj.strict {
+        errors.extend(
+            option_warnings(cj)
+                .into_iter()
+                .map(|w| ConversionError::StrictWarning(w).into()),
+        );
+    }
+    if !cj.in_place {
+        if let Err(e) = validate_output(cj) {
+            errors.push(e);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
 
-    // check output if set
-    if let Some(out) = &cj.output {
-        validate_output_dir(out)?;
+/// Checks that `path` is an image of an enabled format that decodes in full,
+/// without converting it: the input checks of [`validate_job`], then a
+/// decode of all its pixels, every frame of an animation included. The
+/// format is detected from the extension unless given.
+pub fn validate_image(
+    path: &Path,
+    format: Option<FormatType>,
+) -> Result<ImageInfo, MeltforgeError> {
+    validate_input_file(path)?;
+    // the target is never used, only the input settings are
+    let cj = ConvertJob {
+        input_format: format,
+        all_frames: true,
+        ..ConvertJob::new(path, FormatType::PNG)
+    };
+    let input_fmt = validate_input_format(&cj)?;
+    let info = inspect(path, Some(input_fmt))?;
+    decode_frames(&cj, input_fmt)?;
+    Ok(info)
+}
+
+/// Fails if several jobs of a batch would write the same output, listing
+/// each such output with its inputs. Nothing is converted in that case, so
+/// no output is lost halfway through the batch.
+pub fn validate_unique_outputs(jobs: &[ConvertJob]) -> Result<(), InputError> {
+    let collisions = output_collisions(jobs);
+    if collisions.is_empty() {
+        return Ok(());
+    }
+    let listed: Vec<String> = collisions
+        .iter()
+        .map(|(output, inputs)| {
+            let inputs: Vec<String> = inputs.iter().map(|p| p.display().to_string()).collect();
+            format!("{} from {}", output.display(), inputs.join(", "))
+        })
+        .collect();
+    Err(InputError::InvalidArgument(format!(
+        "several inputs would write the same output (use --unique to number them): {}",
+        listed.join("; ")
+    )))
+}
+
+fn validate_input_file(path: &Path) -> Result<(), MeltforgeError> {
+    validate_path(path)?;
+    ensure_readable(path)?;
+    ensure_not_empty(path)
+}
+
+fn validate_output(cj: &ConvertJob) -> Result<(), MeltforgeError> {
+    let output_path = job_output_path(cj);
+    if cj.on_conflict == ConflictPolicy::Skip && fs_path(&output_path).exists() {
+        // nothing will be written
+        return Ok(());
+    }
+
+    if cj.output.is_none() && output_path == cj.input {
+        // a same-format job would otherwise overwrite its input unasked
+        return Err(IoError::AlreadyExists(cj.input.clone()).into());
+    }
+    if cj.output.is_some() && same_file(&cj.input, &output_path) {
+        return Err(InputError::InvalidArgument(format!(
+            "{} is also the input, use --in-place to replace it",
+            output_path.display()
+        ))
+        .into());
+    }
+    // derived outputs next to the input are protected the same as named ones
+    if fs_path(&output_path).exists() && cj.on_conflict != ConflictPolicy::Overwrite {
+        return Err(IoError::AlreadyExists(output_path).into());
+    }
+    if cj.output.is_some() {
+        validate_output_dir(&output_path, cj.create_dirs, cj.reuse_dir_probe)?;
     }
 
     Ok(())
 }
 
+// Whether both paths name one existing file, however they are spelled:
+// through links, `..` or, on case-insensitive filesystems, another case.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(fs_path(a)), fs::metadata(fs_path(b))) {
+        (Ok(a_meta), Ok(b_meta)) => file_id(a, &a_meta) == file_id(b, &b_meta),
+        _ => false,
+    }
+}
+
+#[cfg(unix)]
+fn file_id(_path: &Path, meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+// without stable file ids, the canonical path names the file; Windows
+// filesystems ignore case, so it is compared lowercased
+#[cfg(not(unix))]
+fn file_id(path: &Path, _meta: &fs::Metadata) -> Option<String> {
+    let canonical = fs::canonicalize(fs_path(path)).ok()?;
+    Some(canonical.to_string_lossy().to_lowercase())
+}
+
 fn validate_path(path: &Path) -> Result<(), InputError> {
-    if !path.exists() || !path.is_file() {
+    let fs_path = fs_path(path);
+    if !fs_path.exists() || !fs_path.is_file() {
         return Err(InputError::MissingInputFile(path.to_path_buf()));
     }
     Ok(())
 }
 
-fn validate_input_format(path: &Path) -> Result<FormatType, FormatError> {
-    detect_input_format(path)
+/// Input format of a job: the explicit `--from` if given, otherwise detected from the extension.
+pub(crate) fn validate_input_format(cj: &ConvertJob) -> Result<FormatType, FormatError> {
+    match cj.input_format {
+        Some(fmt) => {
+            ensure_enabled(fmt)?;
+            Ok(fmt)
+        }
+        None => detect_input_format(&cj.input),
+    }
 }
 
 pub fn detect_input_format(path: &Path) -> Result<FormatType, FormatError> {
     let ext = path
         .extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.to_lowercase())
-        .ok_or_else(|| FormatError::UnsupportedInput("<no extension>".into()))?;
-
-    let fmt = match ext.as_str() {
-        "png" => FormatType::PNG,
-        "jpg" | "jpeg" => FormatType::JPEG,
-        _ => return Err(FormatError::UnsupportedInput(ext)),
-    };
+        .ok_or_else(|| FormatError::MissingExtension(path.to_path_buf()))?;
+    let fmt = FormatType::from_extension(&ext.to_string_lossy())?;
+    ensure_enabled(fmt)?;
 
     Ok(fmt)
 }
 
+pub(crate) fn ensure_enabled(fmt: FormatType) -> Result<(), FormatError> {
+    if !fmt.is_enabled() {
+        return Err(FormatError::NotEnabled(fmt.name().into()));
+    }
+    Ok(())
+}
+
+fn validate_in_place(cj: &ConvertJob, input_fmt: FormatType) -> Result<(), MeltforgeError> {
+    let conflict = if cj.output.is_some() {
+        Some("--in-place cannot be combined with --output")
+    } else if cj.all_frames {
+        Some("--in-place cannot write every frame back to a single file")
+    } else if input_fmt != cj.format_type {
+        Some("--in-place needs --to to match the input format")
+    } else {
+        None
+    };
+    if let Some(msg) = conflict {
+        return Err(InputError::InvalidArgument(msg.into()).into());
+    }
+    Ok(())
+}
+
+// `verify` re-reads the output, which needs a decoder for the target. AVIF
+// is only encoded by `image`, and a registered format may lack a decoder.
+fn validate_verify(cj: &ConvertJob) -> Result<(), InputError> {
+    let readable = match cj.format_type {
+        FormatType::AVIF | FormatType::HEIF => false,
+        FormatType::Custom(name) => registry::lookup(name).is_some_and(|f| f.can_decode()),
+        _ => true,
+    };
+    if cj.verify && !readable {
+        return Err(InputError::InvalidArgument(format!(
+            "--verify cannot re-read {} output, there is no decoder for it",
+            cj.format_type
+        )));
+    }
+    Ok(())
+}
+
+// Options the target format cannot honor at all, unlike `option_warnings`
+// which are only ignored.
+pub(crate) fn validate_options(opts: &ConvertOptions, to: FormatType) -> Result<(), InputError> {
+    if let Some(comment) = opts
+        .jpeg_comment
+        .as_ref()
+        .filter(|c| c.len() > MAX_COMMENT_BYTES)
+    {
+        return Err(InputError::InvalidArgument(format!(
+            "--jpeg-comment is {} bytes, a JPEG comment holds at most {MAX_COMMENT_BYTES}",
+            comment.len()
+        )));
+    }
+    if opts.bit_depth == Some(BitDepth::Sixteen) && !to.supports_16_bit() {
+        return Err(InputError::InvalidArgument(format!(
+            "--bit-depth 16 is not supported for {to} output (use png or tiff)"
+        )));
+    }
+    if opts.bit_depth == Some(BitDepth::One) {
+        if to != FormatType::PNG {
+            return Err(InputError::InvalidArgument(format!(
+                "--bit-depth 1 is not supported for {to} output (use png)"
+            )));
+        }
+        if let Some(channels) = opts.channels.filter(|c| *c != Channels::Gray) {
+            return Err(InputError::InvalidArgument(format!(
+                "--bit-depth 1 writes gray, it cannot be combined with --channels {channels}"
+            )));
+        }
+    }
+    if let Some(channels) = opts.channels {
+        if (channels.has_alpha() && !to.supports_alpha())
+            || (!channels.has_color() && !to.supports_gray())
+        {
+            return Err(InputError::InvalidArgument(format!(
+                "--channels {channels} is not supported for {to} output"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Compatibility of the job's conversion, including the opt-in same-format re-encode.
+pub(crate) fn validate_conversion(
+    cj: &ConvertJob,
+    input_fmt: FormatType,
+) -> Result<(), FormatError> {
+    if input_fmt == cj.format_type {
+        if !(cj.allow_same_format || cj.in_place) {
+            return Err(FormatError::SameFormat(input_fmt));
+        }
+        return validate_reencode(input_fmt);
+    }
+    validate_compatibility(input_fmt, cj.format_type)
+}
+
+// A format can be re-encoded when it is both read and written in some
+// conversion, as the decoder and encoder of its codec are then available.
+pub(crate) fn validate_reencode(fmt: FormatType) -> Result<(), FormatError> {
+    ensure_enabled(fmt)?;
+    let readable = FormatType::all()
+        .into_iter()
+        .any(|to| validate_compatibility(fmt, to).is_ok());
+    if !readable {
+        return Err(FormatError::UnsupportedInput(fmt.name().into()));
+    }
+    let writable = FormatType::all()
+        .into_iter()
+        .any(|from| validate_compatibility(from, fmt).is_ok());
+    if !writable {
+        return Err(FormatError::UnsupportedOutput { from: fmt, to: fmt });
+    }
+    Ok(())
+}
+
 pub fn validate_compatibility(input: FormatType, output: FormatType) -> Result<(), FormatError> {
+    ensure_enabled(input)?;
+    ensure_enabled(output)?;
+    if matches!(input, FormatType::Custom(_)) || matches!(output, FormatType::Custom(_)) {
+        return validate_custom(input, output);
+    }
+
     match (input, output) {
         (FormatType::PNG, FormatType::JPEG) => Ok(()),
         (FormatType::JPEG, FormatType::PNG) => Ok(()),
-        _ => Err(FormatError::UnsupportedOutput(format!(
-            "{:?} → {:?} not supported yet",
-            input, output
-        ))),
+        #[cfg(feature = "avif")]
+        (FormatType::PNG | FormatType::JPEG, FormatType::AVIF) => Ok(()),
+        #[cfg(feature = "webp")]
+        (FormatType::PNG | FormatType::JPEG, FormatType::WEBP) => Ok(()),
+        #[cfg(feature = "webp")]
+        (FormatType::WEBP, FormatType::PNG | FormatType::JPEG) => Ok(()),
+        #[cfg(feature = "tiff")]
+        (FormatType::PNG | FormatType::JPEG, FormatType::TIFF) => Ok(()),
+        #[cfg(feature = "tiff")]
+        (FormatType::TIFF, FormatType::PNG | FormatType::JPEG) => Ok(()),
+        #[cfg(feature = "ico")]
+        (FormatType::PNG | FormatType::JPEG, FormatType::ICO) => Ok(()),
+        // decoding an ICO picks its largest image
+        #[cfg(feature = "ico")]
+        (FormatType::ICO, FormatType::PNG) => Ok(()),
+        // HEIF is decoded by libheif but never encoded
+        #[cfg(feature = "heif")]
+        (FormatType::HEIF, FormatType::PNG | FormatType::JPEG) => Ok(()),
+        #[cfg(feature = "gif")]
+        (FormatType::GIF, FormatType::PNG | FormatType::JPEG) => Ok(()),
+        #[cfg(feature = "pnm")]
+        (FormatType::PNG | FormatType::JPEG, FormatType::PNM) => Ok(()),
+        #[cfg(feature = "pnm")]
+        (FormatType::PNM, FormatType::PNG | FormatType::JPEG) => Ok(()),
+        #[cfg(feature = "jxl")]
+        (FormatType::PNG | FormatType::JPEG, FormatType::JXL) => Ok(()),
+        #[cfg(feature = "jxl")]
+        (FormatType::JXL, FormatType::PNG | FormatType::JPEG) => Ok(()),
+        _ => Err(FormatError::UnsupportedOutput {
+            from: input,
+            to: output,
+        }),
     }
 }
 
-fn validate_output_dir(output_path: &Path) -> Result<(), IoError> {
-    if output_path.exists() {
-        return Err(IoError::AlreadyExists(output_path.to_path_buf()).into());
+// Registered formats pair with PNG, JPEG and each other, as far as they
+// have a decoder for inputs and an encoder for outputs.
+fn validate_custom(input: FormatType, output: FormatType) -> Result<(), FormatError> {
+    let readable = match input {
+        FormatType::PNG | FormatType::JPEG => true,
+        FormatType::Custom(name) => registry::lookup(name).is_some_and(|f| f.can_decode()),
+        _ => false,
+    };
+    let writable = match output {
+        FormatType::PNG | FormatType::JPEG => true,
+        FormatType::Custom(name) => registry::lookup(name).is_some_and(|f| f.can_encode()),
+        _ => false,
+    };
+    if readable && writable {
+        Ok(())
+    } else {
+        Err(FormatError::UnsupportedOutput {
+            from: input,
+            to: output,
+        })
     }
+}
+
+/// Every (input, output) pair `validate_compatibility` accepts in this build,
+/// registered formats included.
+pub fn supported_conversions() -> Vec<(FormatType, FormatType)> {
+    let all = FormatType::all();
+    all.iter()
+        .flat_map(|&from| all.iter().map(move |&to| (from, to)))
+        .filter(|&(from, to)| validate_compatibility(from, to).is_ok())
+        .collect()
+}
+
+fn validate_output_dir(
+    output_path: &Path,
+    create_dirs: bool,
+    reuse_probe: bool,
+) -> Result<(), IoError> {
     // defaulting to used directory for User friendly expierience
-    let dir = output_path.parent().unwrap_or(Path::new("."));
+    let dir = match output_path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
 
-    if !dir.exists() {
+    if !fs_path(dir).exists() {
+        if create_dirs {
+            // created right before writing, nothing to probe yet
+            return Ok(());
+        }
         return Err(IoError::MissingParent(output_path.to_path_buf()));
     }
 
-    let test_path = dir.join(".meltforge_write_test");
-
-    match fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .open(&test_path)
-    {
-        Ok(_) => {
-            let _ = fs::remove_file(&test_path);
+    if reuse_probe && lock_writable_dirs().contains(dir) {
+        return Ok(());
+    }
+    match WriteProbe::create(dir) {
+        Ok(_probe) => {
+            if reuse_probe {
+                lock_writable_dirs().insert(dir.to_path_buf());
+            }
             Ok(())
         }
         Err(_) => Err(IoError::PermissionDenied(dir.to_path_buf())),
     }
 }
 
+// Directories a probe succeeded in, shared by every job in the process that
+// opted into `reuse_dir_probe`.
+static WRITABLE_DIRS: LazyLock<Mutex<HashSet<PathBuf>>> = LazyLock::new(Mutex::default);
+
+fn lock_writable_dirs() -> std::sync::MutexGuard<'static, HashSet<PathBuf>> {
+    // the set stays consistent even if a holder panicked
+    WRITABLE_DIRS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+static PROBE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Temporary file proving a directory is writable. The name is unique per
+// process and call so parallel conversions into one directory don't remove
+// each other's probe; it is deleted on drop, including during unwinding.
+struct WriteProbe(PathBuf);
+
+impl WriteProbe {
+    fn create(dir: &Path) -> io::Result<Self> {
+        let n = PROBE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!(".meltforge_write_test.{}.{n}", process::id()));
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(fs_path(&path))?;
+        Ok(WriteProbe(path))
+    }
+}
+
+impl Drop for WriteProbe {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(fs_path(&self.0));
+    }
+}
+
 fn ensure_readable(path: &Path) -> Result<(), IoError> {
-    match File::open(path) {
+    match File::open(fs_path(path)) {
         Ok(_) => Ok(()),
         Err(e) => match e.kind() {
             ErrorKind::PermissionDenied => Err(IoError::PermissionDenied(path.to_path_buf())),
@@ -100,3 +578,332 @@ fn ensure_readable(path: &Path) -> Result<(), IoError> {
         },
     }
 }
+
+// A zero-byte file would otherwise only fail once a decoder finds no header.
+fn ensure_not_empty(path: &Path) -> Result<(), MeltforgeError> {
+    let len = fs::metadata(fs_path(path))
+        .map_err(|_| IoError::ReadError(path.to_path_buf()))?
+        .len();
+    if len == 0 {
+        return Err(InputError::EmptyInputFile(path.to_path_buf()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_pair_carries_both_formats() {
+        let err = validate_compatibility(FormatType::PNG, FormatType::PNG).unwrap_err();
+        assert!(matches!(
+            err,
+            FormatError::UnsupportedOutput {
+                from: FormatType::PNG,
+                to: FormatType::PNG
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "unsupported output format PNG → PNG not supported yet"
+        );
+    }
+
+    #[test]
+    fn supported_conversions_follow_compatibility() {
+        let pairs = supported_conversions();
+        assert!(pairs.contains(&(FormatType::PNG, FormatType::JPEG)));
+        assert!(pairs.contains(&(FormatType::JPEG, FormatType::PNG)));
+        assert!(!pairs.contains(&(FormatType::PNG, FormatType::PNG)));
+    }
+
+    #[cfg(not(feature = "tiff"))]
+    #[test]
+    fn disabled_format_is_reported_as_not_enabled() {
+        assert!(matches!(
+            detect_input_format(Path::new("scan.tif")),
+            Err(FormatError::NotEnabled(_))
+        ));
+        assert!(matches!(
+            validate_compatibility(FormatType::PNG, FormatType::TIFF),
+            Err(FormatError::NotEnabled(_))
+        ));
+    }
+
+    #[cfg(not(feature = "heif"))]
+    #[test]
+    fn heic_without_the_feature_is_not_enabled() {
+        assert!(matches!(
+            detect_input_format(Path::new("IMG_0001.HEIC")),
+            Err(FormatError::NotEnabled(name)) if name == "heif"
+        ));
+    }
+
+    #[cfg(not(feature = "jxl"))]
+    #[test]
+    fn jxl_without_the_feature_is_not_enabled() {
+        assert!(matches!(
+            detect_input_format(Path::new("photo.jxl")),
+            Err(FormatError::NotEnabled(name)) if name == "jxl"
+        ));
+        assert!(matches!(
+            validate_compatibility(FormatType::PNG, FormatType::JXL),
+            Err(FormatError::NotEnabled(name)) if name == "jxl"
+        ));
+    }
+
+    #[test]
+    fn dir_probe_is_reused_only_on_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.png");
+
+        validate_output_dir(&out, false, false).unwrap();
+        assert!(!lock_writable_dirs().contains(dir.path()));
+
+        validate_output_dir(&out, false, true).unwrap();
+        assert!(lock_writable_dirs().contains(dir.path()));
+
+        // the existence check still runs for a cached directory
+        let input = dir.path().join("in.jpg");
+        fs::write(&input, b"jpg").unwrap();
+        fs::write(&out, b"taken").unwrap();
+        let job = ConvertJob {
+            output: Some(out),
+            reuse_dir_probe: true,
+            ..ConvertJob::new(&input, FormatType::PNG)
+        };
+        assert!(matches!(
+            validate_output(&job),
+            Err(MeltforgeError::Io(IoError::AlreadyExists(_)))
+        ));
+        let job = ConvertJob {
+            on_conflict: ConflictPolicy::Overwrite,
+            ..job
+        };
+        validate_output(&job).unwrap();
+    }
+
+    #[test]
+    fn output_aliasing_the_input_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(4, 4).save(&input).unwrap();
+
+        let aliased = dir.path().join("sub").join("..").join("in.png");
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        for policy in [ConflictPolicy::Fail, ConflictPolicy::Overwrite] {
+            let job = ConvertJob {
+                output: Some(aliased.clone()),
+                on_conflict: policy,
+                allow_same_format: true,
+                ..ConvertJob::new(&input, FormatType::PNG)
+            };
+            assert!(matches!(
+                validate_job(&job),
+                Err(MeltforgeError::Input(InputError::InvalidArgument(_)))
+            ));
+        }
+    }
+
+    #[test]
+    fn heif_is_never_an_output() {
+        for from in FormatType::ALL {
+            assert!(validate_compatibility(from, FormatType::HEIF).is_err());
+        }
+    }
+
+    #[test]
+    fn missing_extension_is_distinct_from_unsupported() {
+        assert!(matches!(
+            detect_input_format(Path::new("upload")),
+            Err(FormatError::MissingExtension(_))
+        ));
+    }
+
+    #[test]
+    fn zero_byte_input_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("empty.png");
+        File::create(&input).unwrap();
+
+        let err = validate_job(&ConvertJob::new(&input, FormatType::JPEG)).unwrap_err();
+        assert!(matches!(
+            err,
+            MeltforgeError::Input(InputError::EmptyInputFile(p)) if p == input
+        ));
+    }
+
+    #[test]
+    fn validate_job_all_reports_every_problem() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("notes.txt");
+        fs::write(&input, "not an image").unwrap();
+        let job = ConvertJob {
+            output: Some(dir.path().join("missing/out.jpg")),
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        };
+
+        let errors = validate_job_all(&job).unwrap_err();
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                MeltforgeError::Format(FormatError::UnsupportedInput(_)),
+                MeltforgeError::Io(IoError::MissingParent(_)),
+            ]
+        ));
+        // the fast path still stops at the first
+        assert!(matches!(
+            validate_job(&job),
+            Err(MeltforgeError::Format(FormatError::UnsupportedInput(_)))
+        ));
+    }
+
+    #[test]
+    fn validate_job_all_accepts_a_valid_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(4, 4).save(&input).unwrap();
+
+        assert!(validate_job_all(&ConvertJob::new(&input, FormatType::JPEG)).is_ok());
+    }
+
+    #[test]
+    fn quality_for_lossless_target_fails_only_when_strict() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.jpg");
+        image::RgbImage::new(4, 4).save(&input).unwrap();
+        let mut job = ConvertJob::new(&input, FormatType::PNG);
+        job.options.quality = Some(80);
+
+        assert_eq!(option_warnings(&job).len(), 1);
+        assert!(validate_job(&job).is_ok());
+        job.strict = true;
+        assert!(matches!(
+            validate_job(&job),
+            Err(MeltforgeError::Conversion(ConversionError::StrictWarning(
+                _
+            )))
+        ));
+    }
+
+    #[test]
+    fn lossless_flags_do_not_hide_the_lossless_target_warning() {
+        let mut job = ConvertJob::new("in.jpg", FormatType::PNG);
+        job.options.quality = Some(80);
+        job.options.webp_lossless = true;
+        assert_eq!(
+            option_warnings(&job),
+            [
+                "--quality has no effect on png output, which is lossless",
+                "--webp-lossless has no effect on png output"
+            ]
+        );
+
+        job.options.webp_lossless = false;
+        job.options.jxl_lossless = true;
+        assert_eq!(
+            option_warnings(&job)[0],
+            "--quality has no effect on png output, which is lossless"
+        );
+    }
+
+    #[test]
+    fn dpi_for_a_format_without_density_warns() {
+        let mut job = ConvertJob::new("icon.png", FormatType::ICO);
+        job.options.dpi = Some(300);
+        assert_eq!(
+            option_warnings(&job),
+            ["--dpi has no effect on ico output, which cannot store a resolution"]
+        );
+
+        job.format_type = FormatType::PNG;
+        assert!(option_warnings(&job).is_empty());
+    }
+
+    #[test]
+    fn output_without_extension_gets_one_and_fails_when_strict() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.png");
+        image::RgbImage::new(4, 4).save(&input).unwrap();
+        let mut job = ConvertJob {
+            output: Some(dir.path().join("result")),
+            ..ConvertJob::new(&input, FormatType::JPEG)
+        };
+
+        assert_eq!(job_output_path(&job), dir.path().join("result.jpg"));
+        assert_eq!(option_warnings(&job).len(), 1);
+        job.output = Some(dir.path().join("result.jpeg"));
+        assert!(option_warnings(&job).is_empty());
+
+        job.output = Some(dir.path().join("result"));
+        job.strict = true;
+        assert!(matches!(
+            validate_job(&job),
+            Err(MeltforgeError::Conversion(ConversionError::StrictWarning(
+                _
+            )))
+        ));
+    }
+
+    #[test]
+    fn validate_image_decodes_every_pixel() {
+        let dir = tempfile::tempdir().unwrap();
+        let good = dir.path().join("good.png");
+        image::RgbImage::new(16, 9).save(&good).unwrap();
+        let info = validate_image(&good, None).unwrap();
+        assert_eq!(
+            (info.format, info.width, info.height),
+            (FormatType::PNG, 16, 9)
+        );
+
+        // the header is intact, so only a full decode notices
+        let data = fs::read(&good).unwrap();
+        let truncated = dir.path().join("truncated.png");
+        fs::write(&truncated, &data[..data.len() - 20]).unwrap();
+        assert!(inspect(&truncated, None).is_ok());
+        let err = validate_image(&truncated, None).unwrap_err();
+        assert_eq!(err.exit_code(), 4);
+
+        let empty = dir.path().join("empty.png");
+        fs::write(&empty, b"").unwrap();
+        assert!(matches!(
+            validate_image(&empty, None),
+            Err(MeltforgeError::Input(InputError::EmptyInputFile(_)))
+        ));
+    }
+
+    #[test]
+    fn existing_derived_output_fails_unless_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("a.png");
+        image::RgbImage::new(4, 4).save(&input).unwrap();
+        fs::write(dir.path().join("a.jpg"), b"precious").unwrap();
+
+        // no --output, as after answering the conflict prompt with enter
+        let job = ConvertJob::new(&input, FormatType::JPEG);
+        assert!(matches!(
+            validate_job(&job),
+            Err(MeltforgeError::Io(IoError::AlreadyExists(_)))
+        ));
+        for on_conflict in [
+            ConflictPolicy::Skip,
+            ConflictPolicy::Rename,
+            ConflictPolicy::Overwrite,
+        ] {
+            validate_job(&ConvertJob {
+                on_conflict,
+                ..job.clone()
+            })
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn unknown_extension_is_unsupported() {
+        assert!(matches!(
+            detect_input_format(Path::new("notes.txt")),
+            Err(FormatError::UnsupportedInput(_))
+        ));
+    }
+}
@@ -0,0 +1,57 @@
+use crate::error::{ConversionError, MeltforgeError};
+
+/// Non-fatal issues of one conversion. Every warning goes through [`Warnings::warn`],
+/// so a strict job fails on the first one instead of collecting it.
+#[derive(Debug, Default)]
+pub(crate) struct Warnings {
+    strict: bool,
+    list: Vec<String>,
+}
+
+impl Warnings {
+    pub(crate) fn new(strict: bool) -> Self {
+        Warnings {
+            strict,
+            list: Vec::new(),
+        }
+    }
+
+    pub(crate) fn warn(&mut self, warning: impl Into<String>) -> Result<(), MeltforgeError> {
+        let warning = warning.into();
+        if self.strict {
+            return Err(ConversionError::StrictWarning(warning).into());
+        }
+        self.list.push(warning);
+        Ok(())
+    }
+
+    pub(crate) fn warn_all(
+        &mut self,
+        warnings: impl IntoIterator<Item = String>,
+    ) -> Result<(), MeltforgeError> {
+        warnings.into_iter().try_for_each(|w| self.warn(w))
+    }
+
+    pub(crate) fn into_vec(self) -> Vec<String> {
+        self.list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_turns_the_first_warning_into_an_error() {
+        let mut lenient = Warnings::new(false);
+        lenient.warn("first").unwrap();
+        lenient.warn("second").unwrap();
+        assert_eq!(lenient.into_vec(), ["first", "second"]);
+
+        let err = Warnings::new(true).warn("first").unwrap_err();
+        assert!(matches!(
+            err,
+            MeltforgeError::Conversion(ConversionError::StrictWarning(w)) if w == "first"
+        ));
+    }
+}